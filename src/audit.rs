@@ -0,0 +1,583 @@
+// Copyright 2018-2024 the Deno authors. MIT license.
+
+use std::collections::HashMap;
+use std::path::Path;
+use std::path::PathBuf;
+
+use deno_semver::Version;
+use deno_semver::VersionReq;
+use deno_semver::package::PackageKind;
+use serde::Deserialize;
+use thiserror::Error;
+
+use crate::DependencyPath;
+use crate::LockfileContent;
+use crate::LockfilePackageId;
+use crate::dependency_paths::DependencyPathFinder;
+
+#[derive(Debug, Error)]
+#[error("Failed loading advisory at '{}'", path.display())]
+pub struct AdvisoryLoadError {
+  pub path: PathBuf,
+  #[source]
+  pub source: AdvisoryLoadErrorKind,
+}
+
+#[derive(Debug, Error)]
+pub enum AdvisoryLoadErrorKind {
+  #[error(transparent)]
+  Io(#[from] std::io::Error),
+  #[error(transparent)]
+  Parse(#[from] serde_json::Error),
+  #[error("Advisory '{advisory_id}' has an invalid version range '{range}'")]
+  InvalidRange { advisory_id: String, range: String },
+}
+
+#[derive(Debug, Deserialize)]
+struct OsvAdvisory {
+  id: String,
+  #[serde(default)]
+  summary: Option<String>,
+  #[serde(default)]
+  withdrawn: Option<String>,
+  #[serde(default)]
+  affected: Vec<OsvAffected>,
+  #[serde(default)]
+  database_specific: Option<OsvDatabaseSpecific>,
+}
+
+#[derive(Debug, Deserialize)]
+struct OsvAffected {
+  package: OsvPackage,
+  #[serde(default)]
+  ranges: Vec<OsvRange>,
+  #[serde(default)]
+  versions: Vec<String>,
+  #[serde(default)]
+  database_specific: Option<OsvDatabaseSpecific>,
+}
+
+#[derive(Debug, Deserialize)]
+struct OsvPackage {
+  ecosystem: String,
+  name: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct OsvRange {
+  #[serde(rename = "type")]
+  kind: String,
+  #[serde(default)]
+  events: Vec<OsvEvent>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum OsvEvent {
+  Introduced(String),
+  Fixed(String),
+  LastAffected(String),
+  Limit(String),
+}
+
+#[derive(Debug, Deserialize)]
+struct OsvDatabaseSpecific {
+  #[serde(default)]
+  severity: Option<String>,
+}
+
+/// A range of versions affected by an advisory.
+#[derive(Debug)]
+struct AffectedRange {
+  text: String,
+  req: VersionReq,
+  fixed: Option<String>,
+}
+
+#[derive(Debug)]
+struct AffectedPackage {
+  advisory_index: usize,
+  ranges: Vec<AffectedRange>,
+  versions: Vec<String>,
+  severity: Option<String>,
+}
+
+/// A vulnerability found in a package in the lockfile.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AuditFinding {
+  /// The OSV id of the advisory, such as `GHSA-xxxx-xxxx-xxxx`.
+  pub advisory_id: String,
+  pub summary: Option<String>,
+  pub package: LockfilePackageId,
+  /// Severity from the advisory's `database_specific` data, such as
+  /// `"HIGH"` for GitHub advisories.
+  pub severity: Option<String>,
+  /// The affected range the package's version is in, such as
+  /// `">=1.0.0 <1.2.3"`, or the version itself when the advisory only
+  /// lists affected versions.
+  pub affected_range: String,
+  pub fixed_version: Option<String>,
+  /// The shortest path to the package from each root that depends on it.
+  pub paths: Vec<DependencyPath>,
+}
+
+/// Offline database of advisories in the OSV format.
+///
+/// Advisories for the `npm` and `JSR` ecosystems are used and the rest are
+/// ignored.
+#[derive(Debug, Default)]
+pub struct AdvisoryDatabase {
+  advisories: Vec<OsvAdvisory>,
+  packages: HashMap<(PackageKind, String), Vec<AffectedPackage>>,
+}
+
+impl AdvisoryDatabase {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  /// Loads all the `.json` advisory files in the directory and its
+  /// subdirectories.
+  ///
+  /// Files that can't be read or parsed are skipped and returned as
+  /// errors along with the database of the rest.
+  pub fn load_dir(dir: &Path) -> (Self, Vec<AdvisoryLoadError>) {
+    fn visit(
+      db: &mut AdvisoryDatabase,
+      dir: &Path,
+      errors: &mut Vec<AdvisoryLoadError>,
+    ) {
+      let error =
+        |path: &Path, source: AdvisoryLoadErrorKind| AdvisoryLoadError {
+          path: path.to_path_buf(),
+          source,
+        };
+      let mut entries = match std::fs::read_dir(dir)
+        .and_then(|entries| entries.collect::<Result<Vec<_>, _>>())
+      {
+        Ok(entries) => entries,
+        Err(err) => return errors.push(error(dir, err.into())),
+      };
+      entries.sort_by_key(|entry| entry.file_name());
+      for entry in entries {
+        let path = entry.path();
+        let file_type = match entry.file_type() {
+          Ok(file_type) => file_type,
+          Err(err) => {
+            errors.push(error(&path, err.into()));
+            continue;
+          }
+        };
+        if file_type.is_dir() {
+          visit(db, &path, errors);
+        } else if path.extension().is_some_and(|ext| ext == "json") {
+          let result = std::fs::read_to_string(&path)
+            .map_err(AdvisoryLoadErrorKind::from)
+            .and_then(|text| db.add_json(&text));
+          if let Err(err) = result {
+            errors.push(error(&path, err));
+          }
+        }
+      }
+    }
+
+    let mut db = Self::new();
+    let mut errors = Vec::new();
+    visit(&mut db, dir, &mut errors);
+    (db, errors)
+  }
+
+  /// Adds an advisory from its OSV JSON text.
+  ///
+  /// The advisory is not added when one of its version ranges can't be
+  /// parsed, because then it's not known which versions are affected.
+  pub fn add_json(&mut self, text: &str) -> Result<(), AdvisoryLoadErrorKind> {
+    let advisory: OsvAdvisory = serde_json::from_str(text)?;
+    if advisory.withdrawn.is_some() {
+      return Ok(());
+    }
+    let advisory_index = self.advisories.len();
+    let advisory_severity = advisory
+      .database_specific
+      .as_ref()
+      .and_then(|d| d.severity.clone());
+    let mut affected_packages = Vec::with_capacity(advisory.affected.len());
+    for affected in &advisory.affected {
+      let kind = match affected.package.ecosystem.to_ascii_lowercase().as_str()
+      {
+        "npm" => PackageKind::Npm,
+        "jsr" => PackageKind::Jsr,
+        _ => continue,
+      };
+      let mut ranges = Vec::new();
+      for range in &affected.ranges {
+        if range.kind == "SEMVER" || range.kind == "ECOSYSTEM" {
+          let range = affected_ranges(&range.events).map_err(|range| {
+            AdvisoryLoadErrorKind::InvalidRange {
+              advisory_id: advisory.id.clone(),
+              range,
+            }
+          })?;
+          ranges.extend(range);
+        }
+      }
+      affected_packages.push((
+        (kind, affected.package.name.clone()),
+        AffectedPackage {
+          advisory_index,
+          ranges,
+          versions: affected.versions.clone(),
+          severity: affected
+            .database_specific
+            .as_ref()
+            .and_then(|d| d.severity.clone())
+            .or_else(|| advisory_severity.clone()),
+        },
+      ));
+    }
+    for (key, affected) in affected_packages {
+      self.packages.entry(key).or_default().push(affected);
+    }
+    self.advisories.push(advisory);
+    Ok(())
+  }
+
+  /// Finds the advisories that affect the jsr and npm packages in the
+  /// lockfile content.
+  pub fn audit(&self, content: &LockfileContent) -> Vec<AuditFinding> {
+    let finder = DependencyPathFinder::new(content);
+    let ids = content
+      .packages
+      .jsr
+      .keys()
+      .map(|nv| LockfilePackageId::Jsr(nv.clone()))
+      .chain(
+        content
+          .packages
+          .npm
+          .keys()
+          .map(|id| LockfilePackageId::Npm(id.clone())),
+      );
+    let mut findings = Vec::new();
+    for id in ids {
+      let Some((name, version)) = id.name_and_version() else {
+        continue;
+      };
+      let Some(affected_packages) =
+        self.packages.get(&(id.kind(), name.to_string()))
+      else {
+        continue;
+      };
+      for affected in affected_packages {
+        let Some((affected_range, fixed_version)) =
+          affected_version(affected, &version)
+        else {
+          continue;
+        };
+        let advisory = &self.advisories[affected.advisory_index];
+        findings.push(AuditFinding {
+          advisory_id: advisory.id.clone(),
+          summary: advisory.summary.clone(),
+          package: id.clone(),
+          severity: affected.severity.clone(),
+          affected_range,
+          fixed_version,
+          paths: finder.paths_to(&id),
+        });
+      }
+    }
+    findings
+  }
+}
+
+/// Converts OSV range events into version ranges, failing with the text
+/// of a range that can't be parsed.
+fn affected_ranges(events: &[OsvEvent]) -> Result<Vec<AffectedRange>, String> {
+  /// Creates the range from `introduced` up to the bound, which is only
+  /// reported as the fixed version when it's a fix rather than a limit.
+  fn to_range(
+    introduced: &str,
+    upper: Option<(&str, &str)>,
+    is_fixed: bool,
+  ) -> Result<AffectedRange, String> {
+    let mut parts = Vec::new();
+    if introduced != "0" {
+      parts.push(format!(">={}", introduced));
+    }
+    if let Some((op, version)) = upper {
+      parts.push(format!("{}{}", op, version));
+    }
+    let text = if parts.is_empty() {
+      "*".to_string()
+    } else {
+      parts.join(" ")
+    };
+    let Ok(req) = VersionReq::parse_from_npm(&text) else {
+      return Err(text);
+    };
+    Ok(AffectedRange {
+      req,
+      text,
+      fixed: upper
+        .filter(|_| is_fixed)
+        .map(|(_, version)| version.to_string()),
+    })
+  }
+
+  let mut ranges = Vec::new();
+  let mut introduced = None;
+  for event in events {
+    match event {
+      OsvEvent::Introduced(version) => introduced = Some(version.as_str()),
+      OsvEvent::Fixed(version) => {
+        if let Some(start) = introduced.take() {
+          ranges.push(to_range(start, Some(("<", version)), true)?);
+        }
+      }
+      // a limit only bounds the range, so later versions may still be
+      // affected and it isn't a fix
+      OsvEvent::Limit(version) => {
+        if let Some(start) = introduced.take() {
+          ranges.push(to_range(start, Some(("<", version)), false)?);
+        }
+      }
+      OsvEvent::LastAffected(version) => {
+        if let Some(start) = introduced.take() {
+          ranges.push(to_range(start, Some(("<=", version)), false)?);
+        }
+      }
+    }
+  }
+  if let Some(start) = introduced {
+    ranges.push(to_range(start, None, false)?);
+  }
+  Ok(ranges)
+}
+
+/// Gets the affected range and fixed version when the version is affected.
+fn affected_version(
+  affected: &AffectedPackage,
+  version: &Version,
+) -> Option<(String, Option<String>)> {
+  if let Some(range) = affected.ranges.iter().find(|r| r.req.matches(version)) {
+    return Some((range.text.clone(), range.fixed.clone()));
+  }
+  let version_text = version.to_string();
+  affected
+    .versions
+    .iter()
+    .find(|v| **v == version_text)
+    .map(|v| (v.clone(), None))
+}
+
+#[cfg(test)]
+mod test {
+  use pretty_assertions::assert_eq;
+
+  use super::*;
+  use crate::test_util::TempDir;
+
+  #[test]
+  fn finds_affected_packages() {
+    let content = LockfileContent::from_json(serde_json::json!({
+      "version": "5",
+      "specifiers": {
+        "jsr:@scope/a@1": "1.0.0",
+        "npm:b@1": "1.5.0",
+      },
+      "jsr": {
+        "@scope/a@1.0.0": { "integrity": "sha256" },
+      },
+      "npm": {
+        "b@1.5.0": { "integrity": "sha512", "dependencies": ["c"] },
+        "c@2.0.0": { "integrity": "sha512" },
+      },
+      "workspace": {
+        "dependencies": ["npm:b@1"],
+      },
+    }))
+    .unwrap();
+    let mut db = AdvisoryDatabase::new();
+    db.add_json(
+      r#"{
+  "id": "GHSA-1",
+  "summary": "Bad things",
+  "affected": [{
+    "package": { "ecosystem": "npm", "name": "c" },
+    "ranges": [{
+      "type": "SEMVER",
+      "events": [
+        { "introduced": "0" },
+        { "fixed": "1.0.1" },
+        { "introduced": "2.0.0" },
+        { "fixed": "2.0.5" }
+      ]
+    }]
+  }],
+  "database_specific": { "severity": "HIGH" }
+}"#,
+    )
+    .unwrap();
+    db.add_json(
+      r#"{
+  "id": "OSV-2",
+  "affected": [{
+    "package": { "ecosystem": "JSR", "name": "@scope/a" },
+    "versions": ["1.0.0"]
+  }, {
+    "package": { "ecosystem": "npm", "name": "b" },
+    "ranges": [{ "type": "SEMVER", "events": [{ "introduced": "1.6.0" }] }]
+  }]
+}"#,
+    )
+    .unwrap();
+    db.add_json(
+      r#"{
+  "id": "GHSA-3",
+  "withdrawn": "2024-01-01T00:00:00Z",
+  "affected": [{
+    "package": { "ecosystem": "npm", "name": "c" },
+    "ranges": [{ "type": "SEMVER", "events": [{ "introduced": "0" }] }]
+  }]
+}"#,
+    )
+    .unwrap();
+
+    let findings = db.audit(&content);
+    assert_eq!(
+      findings,
+      vec![
+        AuditFinding {
+          advisory_id: "OSV-2".to_string(),
+          summary: None,
+          package: LockfilePackageId::Jsr(
+            deno_semver::package::PackageNv::from_str("@scope/a@1.0.0")
+              .unwrap()
+          ),
+          severity: None,
+          affected_range: "1.0.0".to_string(),
+          fixed_version: None,
+          paths: finder_paths(&content, "jsr:@scope/a@1.0.0"),
+        },
+        AuditFinding {
+          advisory_id: "GHSA-1".to_string(),
+          summary: Some("Bad things".to_string()),
+          package: LockfilePackageId::Npm("c@2.0.0".into()),
+          severity: Some("HIGH".to_string()),
+          affected_range: ">=2.0.0 <2.0.5".to_string(),
+          fixed_version: Some("2.0.5".to_string()),
+          paths: finder_paths(&content, "npm:c@2.0.0"),
+        },
+      ]
+    );
+    assert_eq!(
+      findings[1]
+        .paths
+        .iter()
+        .map(|p| p.to_string())
+        .collect::<Vec<_>>(),
+      vec!["(root) > npm:b@1 > npm:b@1.5.0 > npm:c@2.0.0"]
+    );
+  }
+
+  fn finder_paths(content: &LockfileContent, id: &str) -> Vec<DependencyPath> {
+    let id = match id.split_once(':').unwrap() {
+      ("jsr", nv) => LockfilePackageId::Jsr(
+        deno_semver::package::PackageNv::from_str(nv).unwrap(),
+      ),
+      (_, id) => LockfilePackageId::Npm(id.into()),
+    };
+    DependencyPathFinder::new(content).paths_to(&id)
+  }
+
+  #[test]
+  fn limit_is_not_a_fix() {
+    let event = |json: serde_json::Value| -> OsvEvent {
+      serde_json::from_value(json).unwrap()
+    };
+    let ranges = affected_ranges(&[
+      event(serde_json::json!({ "introduced": "0" })),
+      event(serde_json::json!({ "fixed": "1.0.1" })),
+      event(serde_json::json!({ "introduced": "2.0.0" })),
+      event(serde_json::json!({ "limit": "3.0.0" })),
+      event(serde_json::json!({ "introduced": "4.0.0" })),
+      event(serde_json::json!({ "last_affected": "4.1.0" })),
+    ])
+    .unwrap();
+    assert_eq!(
+      ranges
+        .iter()
+        .map(|r| (r.text.as_str(), r.fixed.as_deref()))
+        .collect::<Vec<_>>(),
+      vec![
+        ("<1.0.1", Some("1.0.1")),
+        (">=2.0.0 <3.0.0", None),
+        (">=4.0.0 <=4.1.0", None),
+      ]
+    );
+  }
+
+  #[test]
+  fn rejects_invalid_ranges() {
+    let mut db = AdvisoryDatabase::new();
+    let err = db
+      .add_json(
+        r#"{
+  "id": "GHSA-1",
+  "affected": [{
+    "package": { "ecosystem": "npm", "name": "a" },
+    "ranges": [{ "type": "SEMVER", "events": [{ "introduced": "0" }] }]
+  }, {
+    "package": { "ecosystem": "npm", "name": "b" },
+    "ranges": [{
+      "type": "SEMVER",
+      "events": [{ "introduced": "0" }, { "fixed": "not a version" }]
+    }]
+  }]
+}"#,
+      )
+      .err()
+      .unwrap();
+    assert_eq!(
+      err.to_string(),
+      "Advisory 'GHSA-1' has an invalid version range '<not a version'"
+    );
+    // nothing from the advisory was added
+    assert!(db.advisories.is_empty());
+    assert!(db.packages.is_empty());
+  }
+
+  #[test]
+  fn load_dir() {
+    let temp_dir = TempDir::new();
+    let dir = temp_dir.path();
+    std::fs::create_dir_all(dir.join("npm")).unwrap();
+    std::fs::write(
+      dir.join("npm/GHSA-1.json"),
+      r#"{ "id": "GHSA-1", "affected": [] }"#,
+    )
+    .unwrap();
+    std::fs::write(dir.join("README.md"), "not an advisory").unwrap();
+    let (db, errors) = AdvisoryDatabase::load_dir(dir);
+    assert_eq!(db.advisories.len(), 1);
+    assert!(errors.is_empty());
+
+    std::fs::write(dir.join("invalid.json"), "{").unwrap();
+    std::fs::write(
+      dir.join("npm/GHSA-2.json"),
+      r#"{ "id": "GHSA-2", "affected": [] }"#,
+    )
+    .unwrap();
+    let (db, errors) = AdvisoryDatabase::load_dir(dir);
+    // the invalid file doesn't stop the others from loading
+    assert_eq!(db.advisories.len(), 2);
+    assert_eq!(
+      errors.iter().map(|e| e.path.clone()).collect::<Vec<_>>(),
+      vec![dir.join("invalid.json")]
+    );
+
+    let (db, errors) = AdvisoryDatabase::load_dir(&dir.join("missing"));
+    assert!(db.advisories.is_empty());
+    assert_eq!(errors.len(), 1);
+  }
+}
@@ -0,0 +1,661 @@
+// Copyright 2018-2024 the Deno authors. MIT license.
+
+use std::collections::HashMap;
+use std::collections::HashSet;
+
+use deno_semver::jsr::JsrDepPackageReq;
+use deno_semver::package::PackageNv;
+
+use crate::LockfileContent;
+use crate::LockfileMutation;
+use crate::LockfilePackageId;
+use crate::PackageChanges;
+use crate::RepairEdit;
+
+/// Reverse dependencies of the packages in a lockfile that are kept up to
+/// date as packages are inserted, so removing packages doesn't require
+/// building a graph of the whole lockfile.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct DependencyIndex {
+  /// Whether the index needs to be rebuilt from the content.
+  stale: bool,
+  /// Specifiers that resolve to each package.
+  specifiers_by_package: HashMap<LockfilePackageId, HashSet<JsrDepPackageReq>>,
+  /// Jsr packages that depend on each specifier.
+  jsr_dependents: HashMap<JsrDepPackageReq, HashSet<PackageNv>>,
+  /// Number of specifiers in the content the index was updated for.
+  specifier_count: usize,
+  /// Number of jsr package dependencies in the content the index was
+  /// updated for.
+  jsr_dep_count: usize,
+}
+
+impl DependencyIndex {
+  pub fn new_stale() -> Self {
+    Self {
+      stale: true,
+      ..Default::default()
+    }
+  }
+
+  /// Marks the index to be rebuilt, such as after the content was
+  /// replaced.
+  pub fn invalidate(&mut self) {
+    self.stale = true;
+    self.specifiers_by_package.clear();
+    self.jsr_dependents.clear();
+    self.specifier_count = 0;
+    self.jsr_dep_count = 0;
+  }
+
+  fn ensure_built(&mut self, content: &LockfileContent) {
+    if !self.stale {
+      return;
+    }
+    self.stale = false;
+    for (req, version) in &content.packages.specifiers {
+      self.set_specifier(req, None, Some(version));
+    }
+    for (nv, package) in &content.packages.jsr {
+      self.add_jsr_deps(nv, package.dependencies.iter());
+    }
+  }
+
+  /// Gets if the content has as many specifiers and jsr dependencies as
+  /// the index, which catches entries that were added to or removed from
+  /// the content directly without going over every entry.
+  fn has_same_size(&self, content: &LockfileContent) -> bool {
+    let packages = &content.packages;
+    packages.specifiers.len() == self.specifier_count
+      && packages
+        .jsr
+        .values()
+        .map(|package| package.dependencies.len())
+        .sum::<usize>()
+        == self.jsr_dep_count
+  }
+
+  /// Updates the index for a specifier that was inserted, changed, or
+  /// removed.
+  pub fn set_specifier(
+    &mut self,
+    req: &JsrDepPackageReq,
+    old_version: Option<&str>,
+    new_version: Option<&str>,
+  ) {
+    if self.stale {
+      return;
+    }
+    match (old_version, new_version) {
+      (None, Some(_)) => self.specifier_count += 1,
+      (Some(_), None) => self.specifier_count -= 1,
+      _ => {}
+    }
+    if let Some(old_id) =
+      old_version.and_then(|v| LockfilePackageId::from_specifier(req, v))
+      && let Some(reqs) = self.specifiers_by_package.get_mut(&old_id)
+    {
+      reqs.remove(req);
+      if reqs.is_empty() {
+        self.specifiers_by_package.remove(&old_id);
+      }
+    }
+    if let Some(new_id) =
+      new_version.and_then(|v| LockfilePackageId::from_specifier(req, v))
+    {
+      self
+        .specifiers_by_package
+        .entry(new_id)
+        .or_default()
+        .insert(req.clone());
+    }
+  }
+
+  /// Updates the index for dependencies added to a jsr package.
+  pub fn add_jsr_deps<'a>(
+    &mut self,
+    nv: &PackageNv,
+    deps: impl Iterator<Item = &'a JsrDepPackageReq>,
+  ) {
+    if self.stale {
+      return;
+    }
+    for dep in deps {
+      if self
+        .jsr_dependents
+        .entry(dep.clone())
+        .or_default()
+        .insert(nv.clone())
+      {
+        self.jsr_dep_count += 1;
+      }
+    }
+  }
+
+  /// Updates the index for dependencies removed from a jsr package.
+  pub fn remove_jsr_deps<'a>(
+    &mut self,
+    nv: &PackageNv,
+    deps: impl Iterator<Item = &'a JsrDepPackageReq>,
+  ) {
+    if self.stale {
+      return;
+    }
+    for dep in deps {
+      if let Some(dependents) = self.jsr_dependents.get_mut(dep)
+        && dependents.remove(nv)
+      {
+        self.jsr_dep_count -= 1;
+        if dependents.is_empty() {
+          self.jsr_dependents.remove(dep);
+        }
+      }
+    }
+  }
+
+  /// Updates the index for a change to the content, or for undoing it.
+  pub fn apply(&mut self, mutation: &LockfileMutation, undo: bool) {
+    fn ordered<T>(old: T, new: T, undo: bool) -> (T, T) {
+      if undo { (new, old) } else { (old, new) }
+    }
+
+    match mutation {
+      LockfileMutation::Specifier { req, old, new } => {
+        let (old, new) = ordered(old, new, undo);
+        self.set_specifier(req, old.as_deref(), new.as_deref());
+      }
+      LockfileMutation::JsrPackage { nv, old, new } => {
+        let (old, new) = ordered(old, new, undo);
+        if let Some(old) = old {
+          self.remove_jsr_deps(nv, old.dependencies.iter());
+        }
+        if let Some(new) = new {
+          self.add_jsr_deps(nv, new.dependencies.iter());
+        }
+      }
+      LockfileMutation::NpmPackage { .. }
+      | LockfileMutation::Redirect { .. }
+      | LockfileMutation::Remote { .. }
+      | LockfileMutation::Workspace { .. } => {}
+    }
+  }
+
+  /// Updates the index for the entries removed by pruning without it.
+  pub fn apply_changes(&mut self, changes: &PackageChanges) {
+    for (req, version) in &changes.removed.specifiers {
+      self.set_specifier(req, Some(version), None);
+    }
+    for (nv, package) in &changes.removed.jsr {
+      self.remove_jsr_deps(nv, package.dependencies.iter());
+    }
+    for (nv, deps) in &changes.removed_jsr_deps {
+      self.remove_jsr_deps(nv, deps.iter());
+    }
+  }
+
+  /// Updates the index for an edit made by [`crate::Lockfile::repair`].
+  pub fn apply_repair(&mut self, edit: &RepairEdit) {
+    match edit {
+      RepairEdit::RemovedSpecifier { specifier, version }
+      | RepairEdit::RemovedUnsatisfiedSpecifier { specifier, version }
+      | RepairEdit::RemovedInvalidSpecifier { specifier, version } => {
+        self.set_specifier(specifier, Some(version), None);
+      }
+      RepairEdit::RemovedJsrDependency {
+        package,
+        dependency,
+      } => {
+        self.remove_jsr_deps(package, std::iter::once(dependency));
+      }
+      RepairEdit::RemovedNpmDependency { .. }
+      | RepairEdit::RemovedRedirect { .. } => {}
+    }
+  }
+
+  /// Removes the workspace dependencies that were removed and the links
+  /// that changed the same way [`crate::graphs::LockfilePackageGraph`]
+  /// does, but only visiting the affected packages and the specifiers
+  /// that jsr packages depend on.
+  ///
+  /// The index is rebuilt first when the content was changed directly in
+  /// a way that's noticed cheaply, which is when entries were added or
+  /// removed or when the affected entries differ from the index. In debug
+  /// builds, this also checks that the index matches the content before
+  /// and after pruning.
+  pub fn prune(
+    &mut self,
+    content: &mut LockfileContent,
+    removed_deps: HashSet<JsrDepPackageReq>,
+    changed_links: HashSet<JsrDepPackageReq>,
+  ) -> PackageChanges {
+    self.ensure_built(content);
+    let found = if self.has_same_size(content) {
+      self.removed_ids(content, &removed_deps, &changed_links)
+    } else {
+      None
+    };
+    let removed_ids = match found {
+      Some(removed_ids) => removed_ids,
+      None => {
+        // changed without invalidating the index
+        self.invalidate();
+        self.ensure_built(content);
+        self
+          .removed_ids(content, &removed_deps, &changed_links)
+          .expect("a rebuilt index matches the content")
+      }
+    };
+    #[cfg(debug_assertions)]
+    self.assert_up_to_date(content);
+
+    let mut changes = PackageChanges::default();
+    let removed = &mut changes.removed;
+    for id in removed_ids {
+      if let LockfilePackageId::Jsr(nv) = &id
+        && let Some(package) = content.packages.jsr.remove(nv)
+      {
+        self.remove_jsr_deps(nv, package.dependencies.iter());
+        removed.jsr.insert(nv.clone(), package);
+      }
+      for req in self.specifiers_by_package.remove(&id).unwrap_or_default() {
+        if let Some(version) = content.packages.specifiers.remove(&req) {
+          self.specifier_count -= 1;
+          removed.specifiers.insert(req, version);
+        }
+      }
+    }
+
+    // remove the dependencies on specifiers that are no longer in the
+    // lockfile, which also drops the ones that never had a specifier like
+    // the graph does
+    let specifiers = &content.packages.specifiers;
+    let jsr = &mut content.packages.jsr;
+    let removed_jsr_deps = &mut changes.removed_jsr_deps;
+    let jsr_dep_count = &mut self.jsr_dep_count;
+    self.jsr_dependents.retain(|req, dependents| {
+      if specifiers.contains_key(req) {
+        return !dependents.is_empty();
+      }
+      for nv in dependents.iter() {
+        if let Some(package) = jsr.get_mut(nv)
+          && package.dependencies.remove(req)
+        {
+          *jsr_dep_count -= 1;
+          removed_jsr_deps
+            .entry(nv.clone())
+            .or_default()
+            .push(req.clone());
+        }
+      }
+      false
+    });
+
+    #[cfg(debug_assertions)]
+    self.assert_up_to_date(content);
+
+    changes
+  }
+
+  /// Gets the packages to remove, or `None` when a visited entry doesn't
+  /// match the index because the content was changed directly.
+  ///
+  /// npm packages are only disassociated from their specifiers, while jsr
+  /// packages are purged along with every jsr package connected to them.
+  fn removed_ids(
+    &self,
+    content: &LockfileContent,
+    removed_deps: &HashSet<JsrDepPackageReq>,
+    changed_links: &HashSet<JsrDepPackageReq>,
+  ) -> Option<HashSet<LockfilePackageId>> {
+    let specifiers = &content.packages.specifiers;
+    let resolved_id = |req: &JsrDepPackageReq| {
+      let version = specifiers.get(req)?;
+      LockfilePackageId::from_specifier(req, version)
+    };
+
+    let mut pending_ids = removed_deps
+      .iter()
+      .filter_map(resolved_id)
+      .collect::<Vec<_>>();
+    for link in changed_links {
+      pending_ids.extend(
+        specifiers
+          .iter()
+          .filter(|(req, _)| {
+            req.kind == link.kind && req.req.name == link.req.name
+          })
+          .filter_map(|(req, version)| {
+            LockfilePackageId::from_specifier(req, version)
+          }),
+      );
+    }
+
+    let mut removed_ids = HashSet::new();
+    while let Some(id) = pending_ids.pop() {
+      if !removed_ids.insert(id.clone()) {
+        continue;
+      }
+      let reqs = self.specifiers_by_package.get(&id);
+      if reqs
+        .into_iter()
+        .flatten()
+        .any(|req| resolved_id(req).as_ref() != Some(&id))
+      {
+        return None;
+      }
+      let LockfilePackageId::Jsr(nv) = &id else {
+        continue;
+      };
+      let Some(package) = content.packages.jsr.get(nv) else {
+        continue;
+      };
+      for dep in &package.dependencies {
+        if !self
+          .jsr_dependents
+          .get(dep)
+          .is_some_and(|dependents| dependents.contains(nv))
+        {
+          return None;
+        }
+        pending_ids.extend(resolved_id(dep));
+      }
+      for req in reqs.into_iter().flatten() {
+        pending_ids.extend(
+          self
+            .jsr_dependents
+            .get(req)
+            .into_iter()
+            .flatten()
+            .map(|nv| LockfilePackageId::Jsr(nv.clone())),
+        );
+      }
+    }
+    Some(removed_ids)
+  }
+
+  /// Panics when the index no longer matches the content, which happens
+  /// when the content was changed directly without invalidating it.
+  #[cfg(debug_assertions)]
+  fn assert_up_to_date(&self, content: &LockfileContent) {
+    fn non_empty<K, V>(map: &HashMap<K, HashSet<V>>) -> HashMap<K, HashSet<V>>
+    where
+      K: Clone + Eq + std::hash::Hash,
+      V: Clone + Eq + std::hash::Hash,
+    {
+      map
+        .iter()
+        .filter(|(_, values)| !values.is_empty())
+        .map(|(key, values)| (key.clone(), values.clone()))
+        .collect()
+    }
+
+    let mut fresh = Self::new_stale();
+    fresh.ensure_built(content);
+    assert!(
+      non_empty(&self.specifiers_by_package)
+        == non_empty(&fresh.specifiers_by_package)
+        && non_empty(&self.jsr_dependents) == non_empty(&fresh.jsr_dependents)
+        && self.specifier_count == fresh.specifier_count
+        && self.jsr_dep_count == fresh.jsr_dep_count,
+      "the dependency index is out of date; call \
+       Lockfile::invalidate_dependency_index after changing the content"
+    );
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use std::collections::HashMap;
+  use std::path::PathBuf;
+
+  use pretty_assertions::assert_eq;
+
+  use super::*;
+  use crate::Lockfile;
+  use crate::SetWorkspaceConfigOptions;
+  use crate::WorkspaceConfig;
+  use crate::WorkspaceMemberConfig;
+
+  fn options(deps: &[&str]) -> SetWorkspaceConfigOptions {
+    SetWorkspaceConfigOptions {
+      config: WorkspaceConfig {
+        root: WorkspaceMemberConfig {
+          dependencies: deps
+            .iter()
+            .map(|dep| JsrDepPackageReq::from_str(dep).unwrap())
+            .collect(),
+          package_json_deps: HashSet::new(),
+        },
+        members: HashMap::new(),
+        links: HashMap::new(),
+        overrides: HashMap::new(),
+      },
+      no_config: false,
+      no_npm: false,
+    }
+  }
+
+  #[test]
+  fn prunes_like_package_graph() {
+    let mut lockfile = Lockfile::new_empty(PathBuf::from("/deno.lock"), false);
+    lockfile.content = LockfileContent::from_json(serde_json::json!({
+      "version": "5",
+      "specifiers": {
+        "jsr:@scope/a@1": "1.0.0",
+        "jsr:@scope/b@1": "1.0.0",
+        "jsr:@scope/shared@1": "1.0.0",
+        "jsr:@scope/c@1": "1.0.0",
+        "npm:d@1": "1.0.0",
+        "npm:e@1": "1.0.0",
+      },
+      "jsr": {
+        "@scope/a@1.0.0": {
+          "integrity": "sha256",
+          "dependencies": ["jsr:@scope/shared@1", "npm:d@1"],
+        },
+        "@scope/b@1.0.0": {
+          "integrity": "sha256",
+          "dependencies": ["jsr:@scope/shared@1"],
+        },
+        "@scope/shared@1.0.0": { "integrity": "sha256" },
+        "@scope/c@1.0.0": { "integrity": "sha256" },
+      },
+      "npm": {
+        "d@1.0.0": { "integrity": "sha512" },
+        "e@1.0.0": { "integrity": "sha512" },
+      },
+      "workspace": {
+        "dependencies": ["jsr:@scope/a@1", "jsr:@scope/c@1", "npm:e@1"],
+      },
+    }))
+    .unwrap();
+    // no specifier resolves this dependency, so the graph drops it
+    lockfile.add_package_deps(
+      &PackageNv::from_str("@scope/c@1.0.0").unwrap(),
+      [JsrDepPackageReq::from_str("jsr:@scope/missing@1").unwrap()].into_iter(),
+    );
+    let mut indexed = lockfile.clone();
+    indexed.enable_dependency_index();
+
+    for deps in [
+      &["jsr:@scope/a@1", "jsr:@scope/c@1"][..],
+      &["jsr:@scope/c@1"][..],
+    ] {
+      let report = lockfile.set_workspace_config_with_report(options(deps));
+      let indexed_report =
+        indexed.set_workspace_config_with_report(options(deps));
+      assert_eq!(indexed_report, report);
+      assert_eq!(indexed.as_json_string(), lockfile.as_json_string());
+    }
+    assert_eq!(
+      indexed
+        .content
+        .packages
+        .jsr
+        .keys()
+        .map(|nv| nv.to_string())
+        .collect::<Vec<_>>(),
+      vec!["@scope/c@1.0.0"]
+    );
+
+    // inserted packages are added to the index
+    for lockfile in [&mut lockfile, &mut indexed] {
+      let req = JsrDepPackageReq::from_str("jsr:@scope/f@1").unwrap();
+      let nv = PackageNv::from_str("@scope/f@1.0.0").unwrap();
+      lockfile.insert_package_specifier(req.clone(), "1.0.0".into());
+      lockfile.insert_package(nv.clone(), "sha256".to_string());
+      lockfile.add_package_deps(
+        &nv,
+        [JsrDepPackageReq::from_str("jsr:@scope/c@1").unwrap()].into_iter(),
+      );
+      lockfile
+        .set_workspace_config(options(&["jsr:@scope/c@1", "jsr:@scope/f@1"]));
+      lockfile.set_workspace_config(options(&["jsr:@scope/f@1"]));
+    }
+    assert_eq!(indexed.as_json_string(), lockfile.as_json_string());
+    assert!(indexed.content.packages.jsr.is_empty());
+  }
+
+  #[test]
+  fn rebuilds_index_changed_directly() {
+    let mut lockfile = Lockfile::new_empty(PathBuf::from("/deno.lock"), false);
+    lockfile.set_workspace_config(options(&["jsr:@scope/a@1", "npm:b@1"]));
+    lockfile.insert_package_specifier(
+      JsrDepPackageReq::from_str("jsr:@scope/a@1").unwrap(),
+      "1.0.0".into(),
+    );
+    lockfile.insert_package(
+      PackageNv::from_str("@scope/a@1.0.0").unwrap(),
+      "sha256".to_string(),
+    );
+    lockfile.insert_package_specifier(
+      JsrDepPackageReq::from_str("npm:b@1").unwrap(),
+      "1.0.0".into(),
+    );
+    let mut indexed = lockfile.clone();
+    indexed.enable_dependency_index();
+    // builds the index
+    indexed.set_workspace_config(options(&["jsr:@scope/a@1"]));
+    lockfile.set_workspace_config(options(&["jsr:@scope/a@1"]));
+
+    // a jsr package that depends on @scope/a, added without invalidating
+    // the index
+    for lockfile in [&mut lockfile, &mut indexed] {
+      let content = &mut lockfile.content.packages;
+      content.specifiers.insert(
+        JsrDepPackageReq::from_str("jsr:@scope/x@1").unwrap(),
+        "1.0.0".into(),
+      );
+      content.jsr.insert(
+        PackageNv::from_str("@scope/x@1.0.0").unwrap(),
+        crate::JsrPackageInfo::new(
+          "sha256".to_string(),
+          HashSet::from(
+            [JsrDepPackageReq::from_str("jsr:@scope/a@1").unwrap()],
+          ),
+        ),
+      );
+      lockfile.set_workspace_config(options(&[]));
+    }
+    assert_eq!(indexed.as_json_string(), lockfile.as_json_string());
+    assert!(indexed.content.packages.jsr.is_empty());
+  }
+
+  #[test]
+  fn updates_index_after_edits() {
+    let mut lockfile = Lockfile::new_empty(PathBuf::from("/deno.lock"), false);
+    lockfile.content = LockfileContent::from_json(serde_json::json!({
+      "version": "5",
+      "specifiers": {
+        "jsr:@scope/a@1": "1.0.0",
+        "jsr:@scope/b@1": "1.0.0",
+        "jsr:@scope/c@1": "1.0.0",
+        "npm:d@1": "1.0.0",
+        "npm:e@1": "1.0.0",
+      },
+      "jsr": {
+        "@scope/a@1.0.0": {
+          "integrity": "sha256",
+          "dependencies": ["jsr:@scope/b@1", "npm:d@1"],
+        },
+        "@scope/b@1.0.0": { "integrity": "sha256" },
+        "@scope/c@1.0.0": {
+          "integrity": "sha256",
+          "dependencies": ["npm:e@1"],
+        },
+      },
+      "npm": {
+        "d@1.0.0": { "integrity": "sha512" },
+        "e@1.0.0": { "integrity": "sha512" },
+      },
+      "workspace": {
+        "dependencies": [
+          "jsr:@scope/a@1",
+          "jsr:@scope/c@1",
+          "npm:d@1",
+          "npm:e@1",
+        ],
+      },
+    }))
+    .unwrap();
+    lockfile.enable_dependency_index();
+    lockfile.set_workspace_config(options(&[
+      "jsr:@scope/a@1",
+      "jsr:@scope/c@1",
+      "npm:d@1",
+    ]));
+    let assert_updated = |lockfile: &Lockfile| {
+      let index = lockfile.dependency_index.as_ref().unwrap();
+      assert!(!index.stale);
+      assert!(index.has_same_size(&lockfile.content));
+      #[cfg(debug_assertions)]
+      index.assert_up_to_date(&lockfile.content);
+    };
+    assert_updated(&lockfile);
+
+    let mut transaction = lockfile.begin_transaction();
+    transaction.remove_npm_package("d@1.0.0");
+    transaction.repair();
+    transaction.rollback();
+    assert_updated(&lockfile);
+    assert_eq!(lockfile.content.packages.jsr.len(), 3);
+
+    lockfile.remove_npm_package("e@1.0.0");
+    assert_updated(&lockfile);
+    // dangling specifier for repair to remove
+    lockfile.insert_package_specifier(
+      JsrDepPackageReq::from_str("npm:f@1").unwrap(),
+      "1.0.0".into(),
+    );
+    assert_eq!(lockfile.repair().len(), 1);
+    assert_updated(&lockfile);
+    lockfile
+      .remove_jsr_package(&PackageNv::from_str("@scope/b@1.0.0").unwrap());
+    lockfile.set_workspace_config(options(&["npm:d@1"]));
+    assert_updated(&lockfile);
+    assert!(lockfile.content.packages.jsr.is_empty());
+  }
+
+  #[test]
+  #[cfg(debug_assertions)]
+  #[should_panic(expected = "the dependency index is out of date")]
+  fn panics_on_stale_index() {
+    let mut lockfile = Lockfile::new_empty(PathBuf::from("/deno.lock"), false);
+    lockfile.enable_dependency_index();
+    lockfile.set_workspace_config(options(&["npm:a@1", "npm:b@1", "npm:c@1"]));
+    for name in ["a", "b", "c"] {
+      let req = JsrDepPackageReq::from_str(&format!("npm:{name}@1")).unwrap();
+      lockfile.insert_package_specifier(req, "1.0.0".into());
+    }
+    // builds the index
+    lockfile.set_workspace_config(options(&["npm:a@1", "npm:b@1"]));
+    // changed without invalidating the index in a way that isn't noticed
+    *lockfile
+      .content
+      .packages
+      .specifiers
+      .get_mut(&JsrDepPackageReq::from_str("npm:b@1").unwrap())
+      .unwrap() = "2.0.0".into();
+    lockfile.set_workspace_config(options(&["npm:b@1"]));
+  }
+}
@@ -0,0 +1,439 @@
+// Copyright 2018-2024 the Deno authors. MIT license.
+
+use std::collections::HashMap;
+use std::collections::HashSet;
+use std::collections::VecDeque;
+use std::fmt;
+
+use deno_semver::StackString;
+use deno_semver::Version;
+use deno_semver::jsr::JsrDepPackageReq;
+use deno_semver::package::PackageKind;
+use deno_semver::package::PackageNv;
+
+use crate::LockfileContent;
+
+/// A jsr or npm package in the lockfile.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum LockfilePackageId {
+  Jsr(PackageNv),
+  /// The npm package's id, which is the key in the `npm` section.
+  Npm(StackString),
+}
+
+impl LockfilePackageId {
+  /// Gets the package a specifier resolves to.
+  pub(crate) fn from_specifier(
+    req: &JsrDepPackageReq,
+    version: &str,
+  ) -> Option<Self> {
+    let nv = format!("{}@{}", req.req.name, version);
+    match req.kind {
+      PackageKind::Jsr => PackageNv::from_str(&nv).ok().map(Self::Jsr),
+      PackageKind::Npm => Some(Self::Npm(nv.as_str().into())),
+    }
+  }
+
+  /// Gets the package's name and version, excluding any peer dependency
+  /// suffix for npm packages.
+  pub(crate) fn name_and_version(&self) -> Option<(&str, Version)> {
+    match self {
+      LockfilePackageId::Jsr(nv) => Some((&nv.name, nv.version.clone())),
+      LockfilePackageId::Npm(id) => {
+        let at_index = id.get(1..)?.find('@')? + 1;
+        let name = &id[..at_index];
+        let version = &id[at_index + 1..];
+        let version = version.split('_').next().unwrap();
+        Some((name, Version::parse_from_npm(version).ok()?))
+      }
+    }
+  }
+
+  pub(crate) fn kind(&self) -> PackageKind {
+    match self {
+      LockfilePackageId::Jsr(_) => PackageKind::Jsr,
+      LockfilePackageId::Npm(_) => PackageKind::Npm,
+    }
+  }
+}
+
+impl fmt::Display for LockfilePackageId {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      LockfilePackageId::Jsr(nv) => write!(f, "jsr:{}", nv),
+      LockfilePackageId::Npm(id) => write!(f, "npm:{}", id),
+    }
+  }
+}
+
+/// Where a [`DependencyPath`] starts.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum DependencyPathRoot {
+  /// A dependency of the workspace root.
+  WorkspaceRoot,
+  /// A dependency of the workspace member with the provided name.
+  WorkspaceMember(String),
+  /// A specifier that's not a dependency of the workspace config or of
+  /// any jsr package, such as one imported directly in code.
+  Specifier,
+}
+
+/// How a package is depended on, starting from a root's specifier.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DependencyPath {
+  pub root: DependencyPathRoot,
+  pub specifier: JsrDepPackageReq,
+  /// Packages from the one the specifier resolves to through to the
+  /// package this path is for.
+  pub packages: Vec<LockfilePackageId>,
+}
+
+impl fmt::Display for DependencyPath {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match &self.root {
+      DependencyPathRoot::WorkspaceRoot => write!(f, "(root) > ")?,
+      DependencyPathRoot::WorkspaceMember(name) => write!(f, "{} > ", name)?,
+      DependencyPathRoot::Specifier => {}
+    }
+    write!(f, "{}", self.specifier.to_string_normalized())?;
+    for package in &self.packages {
+      write!(f, " > {}", package)?;
+    }
+    Ok(())
+  }
+}
+
+/// Finds how packages are depended on in a lockfile.
+pub(crate) struct DependencyPathFinder<'a> {
+  dependents: HashMap<LockfilePackageId, Vec<LockfilePackageId>>,
+  roots:
+    HashMap<LockfilePackageId, Vec<(DependencyPathRoot, &'a JsrDepPackageReq)>>,
+}
+
+impl<'a> DependencyPathFinder<'a> {
+  pub fn new(content: &'a LockfileContent) -> Self {
+    let packages = &content.packages;
+    let mut dependents: HashMap<_, Vec<_>> = HashMap::new();
+    let mut jsr_deps = HashSet::new();
+    for (nv, package) in &packages.jsr {
+      for dep in &package.dependencies {
+        jsr_deps.insert(dep);
+        let Some(version) = packages.specifiers.get(dep) else {
+          continue;
+        };
+        if let Some(id) = LockfilePackageId::from_specifier(dep, version) {
+          dependents
+            .entry(id)
+            .or_default()
+            .push(LockfilePackageId::Jsr(nv.clone()));
+        }
+      }
+    }
+    for (id, package) in &packages.npm {
+      let dep_ids = package
+        .dependencies
+        .values()
+        .chain(package.optional_dependencies.values());
+      for dep_id in dep_ids {
+        dependents
+          .entry(LockfilePackageId::Npm(dep_id.clone()))
+          .or_default()
+          .push(LockfilePackageId::Npm(id.clone()));
+      }
+    }
+
+    let workspace = &content.workspace;
+    let mut root_reqs = Vec::new();
+    let mut sorted_reqs = |root: DependencyPathRoot, reqs: Vec<_>| {
+      let mut reqs = reqs;
+      reqs.sort_by_cached_key(|req: &&JsrDepPackageReq| {
+        req.to_string_normalized()
+      });
+      root_reqs.extend(reqs.into_iter().map(|req| (root.clone(), req)));
+    };
+    sorted_reqs(
+      DependencyPathRoot::WorkspaceRoot,
+      workspace.root.dep_reqs().collect(),
+    );
+    let mut members = workspace.members.iter().collect::<Vec<_>>();
+    members.sort_by_key(|(name, _)| *name);
+    for (name, member) in members {
+      sorted_reqs(
+        DependencyPathRoot::WorkspaceMember(name.clone()),
+        member.dep_reqs().collect(),
+      );
+    }
+    let workspace_reqs = workspace.get_all_dep_reqs().collect::<HashSet<_>>();
+    sorted_reqs(
+      DependencyPathRoot::Specifier,
+      packages
+        .specifiers
+        .keys()
+        .filter(|req| !workspace_reqs.contains(req) && !jsr_deps.contains(req))
+        .collect(),
+    );
+
+    let mut roots: HashMap<_, Vec<_>> = HashMap::new();
+    for (root, req) in root_reqs {
+      let id = packages
+        .specifiers
+        .get(req)
+        .and_then(|version| LockfilePackageId::from_specifier(req, version));
+      if let Some(id) = id {
+        roots.entry(id).or_default().push((root, req));
+      }
+    }
+
+    Self { dependents, roots }
+  }
+
+  /// Gets the packages depended on by the roots that are included and by
+  /// the extra packages.
+  pub fn reachable(
+    &self,
+    include_root: impl Fn(&DependencyPathRoot, &JsrDepPackageReq) -> bool,
+    extra: impl Iterator<Item = LockfilePackageId>,
+  ) -> HashSet<LockfilePackageId> {
+    let mut dependencies: HashMap<_, Vec<_>> = HashMap::new();
+    for (id, dependents) in &self.dependents {
+      for dependent in dependents {
+        dependencies.entry(dependent).or_default().push(id);
+      }
+    }
+    let mut pending = self
+      .roots
+      .iter()
+      .filter(|(_, roots)| {
+        roots.iter().any(|(root, req)| include_root(root, req))
+      })
+      .map(|(id, _)| id.clone())
+      .chain(extra)
+      .collect::<Vec<_>>();
+    let mut visited = HashSet::new();
+    while let Some(id) = pending.pop() {
+      if let Some(deps) = dependencies.get(&id) {
+        pending.extend(
+          deps
+            .iter()
+            .filter(|dep| !visited.contains(**dep))
+            .map(|dep| (*dep).clone()),
+        );
+      }
+      visited.insert(id);
+    }
+    visited
+  }
+
+  /// Gets the packages along with every package that depends on them,
+  /// directly or transitively.
+  pub fn with_dependents(
+    &self,
+    ids: impl Iterator<Item = LockfilePackageId>,
+  ) -> HashSet<LockfilePackageId> {
+    let mut pending = ids.collect::<Vec<_>>();
+    let mut visited = HashSet::new();
+    while let Some(id) = pending.pop() {
+      if let Some(dependents) = self.dependents.get(&id) {
+        pending.extend(
+          dependents
+            .iter()
+            .filter(|dependent| !visited.contains(*dependent))
+            .cloned(),
+        );
+      }
+      visited.insert(id);
+    }
+    visited
+  }
+
+  /// Gets the shortest path to the package from each root that
+  /// depends on it, with the shortest paths first.
+  pub fn paths_to(&self, target: &LockfilePackageId) -> Vec<DependencyPath> {
+    // search from the package up through its dependents, remembering
+    // the next step back towards the package
+    let mut next_step: HashMap<&LockfilePackageId, &LockfilePackageId> =
+      HashMap::new();
+    let mut visited = HashSet::from([target]);
+    let mut pending = VecDeque::from([target]);
+    let mut found_roots = HashSet::new();
+    let mut paths = Vec::new();
+    while let Some(id) = pending.pop_front() {
+      for (root, req) in self.roots.get(id).into_iter().flatten() {
+        if !found_roots.insert(root) {
+          continue;
+        }
+        let mut packages = vec![id.clone()];
+        let mut current = id;
+        while let Some(next) = next_step.get(current) {
+          packages.push((*next).clone());
+          current = next;
+        }
+        paths.push(DependencyPath {
+          root: root.clone(),
+          specifier: (*req).clone(),
+          packages,
+        });
+      }
+      for dependent in self.dependents.get(id).into_iter().flatten() {
+        if visited.insert(dependent) {
+          next_step.insert(dependent, id);
+          pending.push_back(dependent);
+        }
+      }
+    }
+    paths
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use pretty_assertions::assert_eq;
+
+  use super::*;
+
+  #[test]
+  fn finds_shortest_path_per_root() {
+    let content = LockfileContent::from_json(serde_json::json!({
+      "version": "5",
+      "specifiers": {
+        "jsr:@scope/a@1": "1.0.0",
+        "npm:b@1": "1.0.0",
+        "npm:c@1": "1.0.0",
+      },
+      "jsr": {
+        "@scope/a@1.0.0": {
+          "integrity": "sha256",
+          "dependencies": ["npm:b@1"],
+        },
+      },
+      "npm": {
+        "b@1.0.0": { "integrity": "sha512", "dependencies": ["d"] },
+        "c@1.0.0": { "integrity": "sha512", "dependencies": ["b"] },
+        "d@1.0.0": { "integrity": "sha512" },
+      },
+      "workspace": {
+        "members": {
+          "member": { "dependencies": ["jsr:@scope/a@1"] },
+        },
+      },
+    }))
+    .unwrap();
+    let finder = DependencyPathFinder::new(&content);
+    let paths = finder
+      .paths_to(&LockfilePackageId::Npm("d@1.0.0".into()))
+      .into_iter()
+      .map(|path| path.to_string())
+      .collect::<Vec<_>>();
+    assert_eq!(
+      paths,
+      vec![
+        "member > jsr:@scope/a@1 > jsr:@scope/a@1.0.0 > npm:b@1.0.0 > npm:d@1.0.0",
+        "npm:c@1 > npm:c@1.0.0 > npm:b@1.0.0 > npm:d@1.0.0",
+      ]
+    );
+  }
+
+  #[test]
+  fn handles_cycles_and_unreachable_packages() {
+    let content = LockfileContent::from_json(serde_json::json!({
+      "version": "5",
+      "specifiers": {
+        "npm:a@1": "1.0.0",
+        "npm:e@1": "1.0.0",
+      },
+      "npm": {
+        "a@1.0.0": { "integrity": "sha512", "dependencies": ["b"] },
+        "b@1.0.0": { "integrity": "sha512", "dependencies": ["a", "c"] },
+        "c@1.0.0": { "integrity": "sha512", "dependencies": ["b"] },
+        "d@1.0.0": { "integrity": "sha512", "dependencies": ["c"] },
+        "e@1.0.0": { "integrity": "sha512" },
+      },
+      "workspace": {
+        "dependencies": ["npm:a@1"],
+      },
+    }))
+    .unwrap();
+    let finder = DependencyPathFinder::new(&content);
+    let npm = |id: &str| LockfilePackageId::Npm(id.into());
+    let paths_to = |id: &str| {
+      finder
+        .paths_to(&npm(id))
+        .into_iter()
+        .map(|path| path.to_string())
+        .collect::<Vec<_>>()
+    };
+    let sorted = |ids: HashSet<LockfilePackageId>| {
+      let mut ids =
+        ids.into_iter().map(|id| id.to_string()).collect::<Vec<_>>();
+      ids.sort();
+      ids
+    };
+
+    assert_eq!(
+      paths_to("c@1.0.0"),
+      vec!["(root) > npm:a@1 > npm:a@1.0.0 > npm:b@1.0.0 > npm:c@1.0.0"]
+    );
+    // nothing depends on d, which only depends on the cycle
+    assert_eq!(paths_to("d@1.0.0"), Vec::<String>::new());
+
+    assert_eq!(
+      sorted(finder.reachable(|_, _| true, std::iter::empty())),
+      vec!["npm:a@1.0.0", "npm:b@1.0.0", "npm:c@1.0.0", "npm:e@1.0.0"]
+    );
+    assert_eq!(
+      sorted(finder.reachable(
+        |root, _| *root == DependencyPathRoot::Specifier,
+        std::iter::once(npm("d@1.0.0")),
+      )),
+      vec![
+        "npm:a@1.0.0",
+        "npm:b@1.0.0",
+        "npm:c@1.0.0",
+        "npm:d@1.0.0",
+        "npm:e@1.0.0",
+      ]
+    );
+    assert_eq!(
+      sorted(finder.with_dependents(std::iter::once(npm("c@1.0.0")))),
+      vec!["npm:a@1.0.0", "npm:b@1.0.0", "npm:c@1.0.0", "npm:d@1.0.0"]
+    );
+    assert_eq!(
+      sorted(finder.with_dependents(std::iter::once(npm("e@1.0.0")))),
+      vec!["npm:e@1.0.0"]
+    );
+  }
+
+  #[test]
+  fn gets_package_name_and_version() {
+    let name_and_version = |id: LockfilePackageId| {
+      id.name_and_version()
+        .map(|(name, version)| (name.to_string(), version.to_string()))
+    };
+    let expected =
+      |name: &str, version: &str| Some((name.to_string(), version.to_string()));
+    assert_eq!(
+      name_and_version(LockfilePackageId::Npm("a@1.0.0".into())),
+      expected("a", "1.0.0")
+    );
+    assert_eq!(
+      name_and_version(LockfilePackageId::Npm(
+        "@scope/a@1.0.0-beta.1_b@2.0.0".into()
+      )),
+      expected("@scope/a", "1.0.0-beta.1")
+    );
+    assert_eq!(
+      name_and_version(LockfilePackageId::Jsr(
+        PackageNv::from_str("@scope/a@1.0.0").unwrap()
+      )),
+      expected("@scope/a", "1.0.0")
+    );
+    assert_eq!(
+      name_and_version(LockfilePackageId::Npm("@scope/a".into())),
+      None
+    );
+    assert_eq!(
+      name_and_version(LockfilePackageId::Npm("a@latest".into())),
+      None
+    );
+  }
+}
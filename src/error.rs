@@ -5,20 +5,53 @@ use deno_semver::jsr::JsrDepPackageReqParseError;
 use deno_semver::package::PackageNv;
 use thiserror::Error;
 
+use crate::json_path::JsonPath;
+use crate::json_path::SourcePosition;
 use crate::transforms::TransformError;
 
 #[derive(Debug, Error)]
-#[error("Failed reading lockfile at '{file_path}'")]
+#[error(
+  "Failed reading lockfile at '{file_path}{}'",
+  position.map(|p| format!(":{}", p)).unwrap_or_default()
+)]
 pub struct LockfileError {
   pub file_path: String,
+  pub(crate) json_path: Option<JsonPath>,
+  pub(crate) position: Option<SourcePosition>,
   #[source]
   pub source: LockfileErrorReason,
 }
 
+impl LockfileError {
+  pub fn new(file_path: String, source: LockfileErrorReason) -> Self {
+    Self {
+      file_path,
+      json_path: None,
+      position: None,
+      source,
+    }
+  }
+
+  /// Path to the value in the lockfile that caused the error, if known.
+  ///
+  /// For lockfiles that were upgraded from an older version, this may be
+  /// the path in the upgraded lockfile rather than the original.
+  pub fn json_path(&self) -> Option<&JsonPath> {
+    self.json_path.as_ref()
+  }
+
+  /// Position in the lockfile text where the error occurred, if known.
+  pub fn position(&self) -> Option<SourcePosition> {
+    self.position
+  }
+}
+
 #[derive(Debug, Error)]
 pub enum LockfileErrorReason {
   #[error("Lockfile was empty")]
   Empty,
+  #[error("Lockfile was not found")]
+  NotFound,
   #[error("Failed parsing. Lockfile may be corrupt")]
   ParseError(serde_json::Error),
   #[error("Failed deserializing. Lockfile may be corrupt")]
@@ -31,6 +64,8 @@ pub enum LockfileErrorReason {
     "Failed upgrading lockfile to latest version. Lockfile may be corrupt"
   )]
   TransformError(#[source] TransformError),
+  #[error("Failed reading file")]
+  Io(#[source] std::io::Error),
 }
 
 impl From<TransformError> for LockfileErrorReason {
@@ -39,6 +74,48 @@ impl From<TransformError> for LockfileErrorReason {
   }
 }
 
+#[derive(Debug, Error)]
+pub enum LockfileSaveError {
+  #[error("Failed writing lockfile at '{file_path}'")]
+  Io {
+    file_path: String,
+    #[source]
+    source: std::io::Error,
+  },
+  /// The lockfile was modified on disk and reading it for merging failed.
+  #[error(transparent)]
+  Load(Box<LockfileError>),
+}
+
+/// A [`DeserializationError`] along with where it occurred in the lockfile.
+#[derive(Debug)]
+pub struct DeserializationDiagnostic {
+  /// Path to the value in the lockfile that failed deserializing.
+  pub path: JsonPath,
+  pub error: Box<DeserializationError>,
+}
+
+impl DeserializationDiagnostic {
+  pub fn new(path: JsonPath, error: DeserializationError) -> Self {
+    Self {
+      path,
+      error: Box::new(error),
+    }
+  }
+}
+
+impl std::fmt::Display for DeserializationDiagnostic {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    write!(f, "{} at {}", self.error, self.path)
+  }
+}
+
+impl std::error::Error for DeserializationDiagnostic {
+  fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+    self.error.source()
+  }
+}
+
 #[derive(Debug, Error)]
 pub enum DeserializationError {
   #[error("Invalid {0} section: {1:#}")]
@@ -0,0 +1,379 @@
+// Copyright 2018-2024 the Deno authors. MIT license.
+
+use std::collections::BTreeMap;
+use std::collections::HashSet;
+use std::fs::File;
+use std::fs::OpenOptions;
+use std::hash::DefaultHasher;
+use std::hash::Hasher;
+use std::io::ErrorKind;
+use std::io::Write;
+use std::path::Path;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::sync::Mutex;
+
+/// File system operations used for loading and saving lockfiles.
+pub trait LockfileFs {
+  /// Reads the file's text, returning `None` when it doesn't exist.
+  fn read_file(&self, path: &Path) -> std::io::Result<Option<String>>;
+  /// Creates or truncates the file and writes the bytes to it.
+  fn write_file(&self, path: &Path, bytes: &[u8]) -> std::io::Result<()>;
+  fn rename_file(&self, from: &Path, to: &Path) -> std::io::Result<()>;
+  fn remove_file(&self, path: &Path) -> std::io::Result<()>;
+  /// Gives the file at `to` the permissions of the file at `from`, doing
+  /// nothing when `from` doesn't exist.
+  fn copy_permissions(&self, from: &Path, to: &Path) -> std::io::Result<()> {
+    let _ = (from, to);
+    Ok(())
+  }
+  /// Takes an exclusive lock for the path, which is held until the
+  /// returned value is dropped.
+  fn lock_file(&self, path: &Path) -> std::io::Result<Box<dyn LockfileFsLock>>;
+}
+
+/// A lock taken by [`LockfileFs::lock_file`] that's released on drop.
+pub trait LockfileFsLock {}
+
+/// [`LockfileFs`] implementation that uses the real file system.
+///
+/// Locks are advisory locks held on a file beside the locked path
+/// because the lockfile itself gets replaced when it's written. That
+/// file is removed when the lock is released.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct RealLockfileFs;
+
+struct RealLockfileFsLock {
+  path: PathBuf,
+  // held for the lock
+  file: Option<File>,
+}
+
+impl LockfileFsLock for RealLockfileFsLock {}
+
+impl Drop for RealLockfileFsLock {
+  fn drop(&mut self) {
+    if cfg!(unix) {
+      // removed while still locked, so anyone waiting on this file will
+      // see that it's gone once they get the lock and try again
+      let _ = std::fs::remove_file(&self.path);
+    } else {
+      // the file can't be removed while it's open, including by this
+      // handle, so it's closed first and left in place when someone
+      // else is waiting on it
+      self.file.take();
+      let _ = std::fs::remove_file(&self.path);
+    }
+  }
+}
+
+impl LockfileFs for RealLockfileFs {
+  fn read_file(&self, path: &Path) -> std::io::Result<Option<String>> {
+    match std::fs::read_to_string(path) {
+      Ok(text) => Ok(Some(text)),
+      Err(err) if err.kind() == ErrorKind::NotFound => Ok(None),
+      Err(err) => Err(err),
+    }
+  }
+
+  fn write_file(&self, path: &Path, bytes: &[u8]) -> std::io::Result<()> {
+    let mut file = File::create(path)?;
+    file.write_all(bytes)?;
+    file.sync_all()
+  }
+
+  fn rename_file(&self, from: &Path, to: &Path) -> std::io::Result<()> {
+    std::fs::rename(from, to)
+  }
+
+  fn remove_file(&self, path: &Path) -> std::io::Result<()> {
+    std::fs::remove_file(path)
+  }
+
+  fn copy_permissions(&self, from: &Path, to: &Path) -> std::io::Result<()> {
+    match std::fs::metadata(from) {
+      Ok(metadata) => std::fs::set_permissions(to, metadata.permissions()),
+      Err(err) if err.kind() == ErrorKind::NotFound => Ok(()),
+      Err(err) => Err(err),
+    }
+  }
+
+  fn lock_file(&self, path: &Path) -> std::io::Result<Box<dyn LockfileFsLock>> {
+    let lock_path = sibling_path(path, |name| format!("{}.lock", name));
+    loop {
+      let mut options = OpenOptions::new();
+      options.create(true).truncate(false).write(true);
+      #[cfg(windows)]
+      {
+        use std::os::windows::fs::OpenOptionsExt;
+        const FILE_SHARE_READ: u32 = 0x1;
+        const FILE_SHARE_WRITE: u32 = 0x2;
+        // without FILE_SHARE_DELETE, the file can't be removed or replaced
+        // while anyone has it open
+        options.share_mode(FILE_SHARE_READ | FILE_SHARE_WRITE);
+      }
+      let file = options.open(&lock_path)?;
+      file.lock()?;
+      if is_same_file(&file, &lock_path)? {
+        return Ok(Box::new(RealLockfileFsLock {
+          path: lock_path,
+          file: Some(file),
+        }));
+      }
+    }
+  }
+}
+
+/// Gets if the open file is still the one at the path, which it isn't
+/// once the previous holder of the lock removed it.
+#[cfg(unix)]
+fn is_same_file(file: &File, path: &Path) -> std::io::Result<bool> {
+  use std::os::unix::fs::MetadataExt;
+
+  let file_metadata = file.metadata()?;
+  match std::fs::metadata(path) {
+    Ok(path_metadata) => Ok(
+      file_metadata.dev() == path_metadata.dev()
+        && file_metadata.ino() == path_metadata.ino(),
+    ),
+    Err(err) if err.kind() == ErrorKind::NotFound => Ok(false),
+    Err(err) => Err(err),
+  }
+}
+
+/// Gets if the open file is still the one at the path, which it always is
+/// because the lock file is opened without allowing it to be removed or
+/// replaced while open.
+#[cfg(windows)]
+fn is_same_file(_file: &File, _path: &Path) -> std::io::Result<bool> {
+  Ok(true)
+}
+
+/// Gets if the open file is still the one at the path by comparing when
+/// they were created, since there's no file identity to compare.
+#[cfg(not(any(unix, windows)))]
+fn is_same_file(file: &File, path: &Path) -> std::io::Result<bool> {
+  let file_metadata = file.metadata()?;
+  match std::fs::metadata(path) {
+    Ok(path_metadata) => Ok(
+      file_metadata.created().ok() == path_metadata.created().ok()
+        && file_metadata.modified().ok() == path_metadata.modified().ok(),
+    ),
+    Err(err) if err.kind() == ErrorKind::NotFound => Ok(false),
+    Err(err) => Err(err),
+  }
+}
+
+/// In-memory [`LockfileFs`] implementation, which is useful for testing.
+///
+/// Taking a lock that's already held fails rather than blocking.
+#[derive(Debug, Default)]
+pub struct InMemoryLockfileFs {
+  files: Mutex<BTreeMap<PathBuf, Vec<u8>>>,
+  locked: Arc<Mutex<HashSet<PathBuf>>>,
+}
+
+impl InMemoryLockfileFs {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  pub fn with_file(self, path: impl Into<PathBuf>, text: &str) -> Self {
+    self.set_file(path, text);
+    self
+  }
+
+  pub fn set_file(&self, path: impl Into<PathBuf>, text: &str) {
+    self
+      .files
+      .lock()
+      .unwrap()
+      .insert(path.into(), text.as_bytes().to_vec());
+  }
+
+  /// Gets the file's text, if it exists.
+  pub fn file_text(&self, path: &Path) -> Option<String> {
+    let files = self.files.lock().unwrap();
+    files
+      .get(path)
+      .map(|bytes| String::from_utf8_lossy(bytes).into_owned())
+  }
+
+  /// Gets the paths of all the files.
+  pub fn paths(&self) -> Vec<PathBuf> {
+    self.files.lock().unwrap().keys().cloned().collect()
+  }
+}
+
+struct InMemoryLock {
+  path: PathBuf,
+  locked: Arc<Mutex<HashSet<PathBuf>>>,
+}
+
+impl LockfileFsLock for InMemoryLock {}
+
+impl Drop for InMemoryLock {
+  fn drop(&mut self) {
+    self.locked.lock().unwrap().remove(&self.path);
+  }
+}
+
+impl LockfileFs for InMemoryLockfileFs {
+  fn read_file(&self, path: &Path) -> std::io::Result<Option<String>> {
+    let files = self.files.lock().unwrap();
+    match files.get(path) {
+      Some(bytes) => String::from_utf8(bytes.clone())
+        .map(Some)
+        .map_err(|err| std::io::Error::new(ErrorKind::InvalidData, err)),
+      None => Ok(None),
+    }
+  }
+
+  fn write_file(&self, path: &Path, bytes: &[u8]) -> std::io::Result<()> {
+    let mut files = self.files.lock().unwrap();
+    files.insert(path.to_path_buf(), bytes.to_vec());
+    Ok(())
+  }
+
+  fn rename_file(&self, from: &Path, to: &Path) -> std::io::Result<()> {
+    let mut files = self.files.lock().unwrap();
+    let bytes = files
+      .remove(from)
+      .ok_or_else(|| std::io::Error::from(ErrorKind::NotFound))?;
+    files.insert(to.to_path_buf(), bytes);
+    Ok(())
+  }
+
+  fn remove_file(&self, path: &Path) -> std::io::Result<()> {
+    let mut files = self.files.lock().unwrap();
+    match files.remove(path) {
+      Some(_) => Ok(()),
+      None => Err(ErrorKind::NotFound.into()),
+    }
+  }
+
+  fn lock_file(&self, path: &Path) -> std::io::Result<Box<dyn LockfileFsLock>> {
+    if !self.locked.lock().unwrap().insert(path.to_path_buf()) {
+      return Err(ErrorKind::WouldBlock.into());
+    }
+    Ok(Box::new(InMemoryLock {
+      path: path.to_path_buf(),
+      locked: self.locked.clone(),
+    }))
+  }
+}
+
+/// Fingerprint of the lockfile's bytes used to tell when it changed on disk.
+pub(crate) fn fingerprint(bytes: &[u8]) -> u64 {
+  let mut hasher = DefaultHasher::new();
+  hasher.write(bytes);
+  hasher.finish()
+}
+
+/// Writes to a temporary file beside the lockfile, then renames it over
+/// the lockfile so readers never see a partially written file. The
+/// temporary file gets the lockfile's permissions so they're kept.
+pub(crate) fn atomic_write(
+  fs: &dyn LockfileFs,
+  path: &Path,
+  bytes: &[u8],
+) -> std::io::Result<()> {
+  let temp_path =
+    sibling_path(path, |name| format!(".{}.{}.tmp", name, std::process::id()));
+  let result = fs
+    .write_file(&temp_path, bytes)
+    .and_then(|_| fs.copy_permissions(path, &temp_path))
+    .and_then(|_| fs.rename_file(&temp_path, path));
+  if result.is_err() {
+    let _ = fs.remove_file(&temp_path);
+  }
+  result
+}
+
+fn sibling_path(path: &Path, get_name: impl Fn(&str) -> String) -> PathBuf {
+  let name = path
+    .file_name()
+    .map(|name| name.to_string_lossy())
+    .unwrap_or_default();
+  path.with_file_name(get_name(&name))
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+  use crate::test_util::TempDir;
+
+  #[test]
+  fn real_lock_removes_lock_file() {
+    let dir = TempDir::new();
+    let path = dir.path().join("deno.lock");
+    let lock = RealLockfileFs.lock_file(&path).unwrap();
+    assert!(dir.path().join("deno.lock.lock").exists());
+    drop(lock);
+    assert!(!dir.path().join("deno.lock.lock").exists());
+    drop(RealLockfileFs.lock_file(&path).unwrap());
+    assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 0);
+  }
+
+  #[cfg(unix)]
+  #[test]
+  fn atomic_write_keeps_permissions() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let dir = TempDir::new();
+    let path = dir.path().join("deno.lock");
+    std::fs::write(&path, "old").unwrap();
+    std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o600))
+      .unwrap();
+    atomic_write(&RealLockfileFs, &path, b"new").unwrap();
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), "new");
+    assert_eq!(
+      std::fs::metadata(&path).unwrap().permissions().mode() & 0o777,
+      0o600
+    );
+
+    // new lockfiles get the default permissions
+    let new_path = dir.path().join("new.lock");
+    atomic_write(&RealLockfileFs, &new_path, b"new").unwrap();
+    assert!(new_path.exists());
+  }
+
+  #[cfg(unix)]
+  #[test]
+  fn real_lock_retries_replaced_lock_file() {
+    let dir = TempDir::new();
+    let path = dir.path().join("deno.lock");
+    let lock_path = dir.path().join("deno.lock.lock");
+    let file = File::create(&lock_path).unwrap();
+    assert!(is_same_file(&file, &lock_path).unwrap());
+    std::fs::remove_file(&lock_path).unwrap();
+    assert!(!is_same_file(&file, &lock_path).unwrap());
+    std::fs::write(&lock_path, "").unwrap();
+    assert!(!is_same_file(&file, &lock_path).unwrap());
+    drop(RealLockfileFs.lock_file(&path).unwrap());
+  }
+
+  #[test]
+  fn in_memory_lock() {
+    let fs = InMemoryLockfileFs::new();
+    let path = Path::new("/deno.lock");
+    let lock = fs.lock_file(path).unwrap();
+    assert_eq!(
+      fs.lock_file(path).err().unwrap().kind(),
+      ErrorKind::WouldBlock
+    );
+    drop(lock);
+    assert!(fs.lock_file(path).is_ok());
+  }
+
+  #[test]
+  fn in_memory_atomic_write() {
+    let fs = InMemoryLockfileFs::new().with_file("/deno.lock", "old");
+    atomic_write(&fs, Path::new("/deno.lock"), b"new").unwrap();
+    assert_eq!(fs.paths(), vec![PathBuf::from("/deno.lock")]);
+    assert_eq!(
+      fs.read_file(Path::new("/deno.lock")).unwrap(),
+      Some("new".to_string())
+    );
+  }
+}
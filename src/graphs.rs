@@ -13,8 +13,12 @@ use deno_semver::package::PackageKind;
 use deno_semver::package::PackageNv;
 use deno_semver::package::PackageReq;
 
+use crate::JsrPackageInfo;
 use crate::NpmPackageInfo;
+use crate::PackageChanges;
 use crate::PackagesContent;
+use crate::RemovedPackages;
+use crate::UnknownFields;
 
 #[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
 enum LockfilePkgId {
@@ -83,6 +87,7 @@ struct LockfileNpmGraphPackage {
   deprecated: bool,
   scripts: bool,
   bin: bool,
+  unknown_fields: UnknownFields,
 }
 
 impl LockfileNpmGraphPackage {
@@ -95,6 +100,34 @@ impl LockfileNpmGraphPackage {
       .chain(self.optional_dependencies.values())
       .chain(self.optional_peers.values())
   }
+
+  fn into_info(self) -> NpmPackageInfo {
+    NpmPackageInfo {
+      integrity: self.integrity,
+      dependencies: self
+        .dependencies
+        .into_iter()
+        .map(|(name, id)| (name, id.0))
+        .collect(),
+      cpu: self.cpu,
+      os: self.os,
+      tarball: self.tarball,
+      optional_dependencies: self
+        .optional_dependencies
+        .into_iter()
+        .map(|(name, id)| (name, id.0))
+        .collect(),
+      deprecated: self.deprecated,
+      scripts: self.scripts,
+      bin: self.bin,
+      optional_peers: self
+        .optional_peers
+        .into_iter()
+        .map(|(name, id)| (name, id.0))
+        .collect(),
+      unknown_fields: self.unknown_fields,
+    }
+  }
 }
 
 #[derive(Debug)]
@@ -102,6 +135,29 @@ struct LockfileJsrGraphPackage {
   dependents: HashSet<LockfilePkgId>,
   integrity: String,
   dependencies: BTreeSet<LockfilePkgReq>,
+  unknown_fields: UnknownFields,
+}
+
+impl LockfileJsrGraphPackage {
+  fn into_info(self) -> JsrPackageInfo {
+    JsrPackageInfo {
+      integrity: self.integrity,
+      dependencies: self
+        .dependencies
+        .into_iter()
+        .map(|req| req.into_jsr_dep())
+        .collect(),
+      unknown_fields: self.unknown_fields,
+    }
+  }
+}
+
+/// What was reachable in a [`LockfilePackageGraph`] before packages
+/// were removed from it.
+pub struct LockfileOrphanScope {
+  roots: HashSet<LockfilePkgReq>,
+  reachable_reqs: HashSet<LockfilePkgReq>,
+  reachable_ids: HashSet<LockfilePkgId>,
 }
 
 /// Graph used to analyze a lockfile to determine which packages
@@ -110,6 +166,8 @@ pub struct LockfilePackageGraph {
   root_packages: HashMap<LockfilePkgReq, LockfilePkgId>,
   packages: HashMap<LockfilePkgId, LockfileGraphPackage>,
   remotes: BTreeMap<String, String>,
+  /// Specifiers and packages removed from the graph so far.
+  removed: RemovedPackages,
 }
 
 impl LockfilePackageGraph {
@@ -125,10 +183,12 @@ impl LockfilePackageGraph {
     let package_count =
       content.specifiers.len() + content.jsr.len() + content.npm.len();
     let mut packages = HashMap::with_capacity(package_count);
+    let mut removed = RemovedPackages::default();
     for (dep, value) in content.specifiers {
       match dep.kind {
         deno_semver::package::PackageKind::Jsr => {
           let Ok(version) = Version::parse_standard(&value) else {
+            removed.specifiers.insert(dep, value);
             continue;
           };
           let nv = LockfilePkgId::Jsr(LockfileJsrPkgNv(PackageNv {
@@ -163,6 +223,7 @@ impl LockfilePackageGraph {
             .into_iter()
             .map(LockfilePkgReq::from_jsr_dep)
             .collect(),
+          unknown_fields: content_package.unknown_fields,
         }),
       );
     }
@@ -200,6 +261,7 @@ impl LockfilePackageGraph {
               (name.clone(), LockfileNpmPackageId(dep_id.clone()))
             })
             .collect(),
+          unknown_fields: package.unknown_fields,
         }),
       );
     }
@@ -240,6 +302,54 @@ impl LockfilePackageGraph {
       root_packages,
       packages,
       remotes,
+      removed,
+    }
+  }
+
+  fn record_removed_root(&mut self, req: LockfilePkgReq, id: LockfilePkgId) {
+    let value = specifier_value(&req, &id);
+    self.removed.specifiers.insert(req.into_jsr_dep(), value);
+  }
+
+  fn record_removed_package(
+    &mut self,
+    id: LockfilePkgId,
+    package: LockfileGraphPackage,
+  ) {
+    match (id, package) {
+      (LockfilePkgId::Jsr(nv), LockfileGraphPackage::Jsr(package)) => {
+        self.removed.jsr.insert(nv.0, package.into_info());
+      }
+      (LockfilePkgId::Npm(id), LockfileGraphPackage::Npm(package)) => {
+        self.removed.npm.insert(id.0, package.into_info());
+      }
+      _ => unreachable!(),
+    }
+  }
+
+  fn remove_roots_where(
+    &mut self,
+    mut predicate: impl FnMut(&LockfilePkgReq, &LockfilePkgId) -> bool,
+  ) {
+    let removed = self
+      .root_packages
+      .extract_if(|req, id| predicate(req, id))
+      .collect::<Vec<_>>();
+    for (req, id) in removed {
+      self.record_removed_root(req, id);
+    }
+  }
+
+  fn remove_packages_where(
+    &mut self,
+    mut predicate: impl FnMut(&LockfilePkgId) -> bool,
+  ) {
+    let removed = self
+      .packages
+      .extract_if(|id, _| predicate(id))
+      .collect::<Vec<_>>();
+    for (id, package) in removed {
+      self.record_removed_package(id, package);
     }
   }
 
@@ -306,7 +416,8 @@ impl LockfilePackageGraph {
                   .cloned(),
               );
               pending_ids.extend(pkg.dependents.drain());
-              self.packages.remove(&id);
+              let pkg = self.packages.remove(&id).unwrap();
+              self.record_removed_package(id, pkg);
             }
             LockfileGraphPackage::Npm(_) => {}
           }
@@ -315,92 +426,212 @@ impl LockfilePackageGraph {
         // sort and dedup for binary search
         root_ids_to_remove.sort();
         root_ids_to_remove.dedup();
-        self.root_packages.retain(|_, pkg_id| {
-          root_ids_to_remove.binary_search(pkg_id).is_err()
+        self.remove_roots_where(|_, pkg_id| {
+          root_ids_to_remove.binary_search(pkg_id).is_ok()
         });
       }
       LockfilePkgId::Npm(_) => {
-        self.root_packages.retain(|_, pkg_id| pkg_id != id);
+        self.remove_roots_where(|_, pkg_id| pkg_id == id);
       }
     }
   }
 
+  /// Captures what's reachable before removing packages so that
+  /// [`LockfilePackageGraph::remove_orphans`] only removes what the
+  /// removal orphaned.
+  ///
+  /// Specifiers that are workspace dependencies or that no jsr package
+  /// depends on are considered the roots.
+  pub fn orphan_scope(
+    &self,
+    workspace_reqs: impl Iterator<Item = JsrDepPackageReq>,
+  ) -> LockfileOrphanScope {
+    let workspace_reqs = workspace_reqs
+      .map(LockfilePkgReq::from_jsr_dep)
+      .collect::<HashSet<_>>();
+    let jsr_dep_reqs = self
+      .packages
+      .values()
+      .filter_map(|pkg| match pkg {
+        LockfileGraphPackage::Jsr(pkg) => Some(pkg.dependencies.iter()),
+        LockfileGraphPackage::Npm(_) => None,
+      })
+      .flatten()
+      .collect::<HashSet<_>>();
+    let roots = self
+      .root_packages
+      .keys()
+      .filter(|req| {
+        !jsr_dep_reqs.contains(req) || workspace_reqs.contains(*req)
+      })
+      .cloned()
+      .collect::<HashSet<_>>();
+    let (reachable_reqs, reachable_ids) = self.reachable_from(&roots);
+    LockfileOrphanScope {
+      roots,
+      reachable_reqs,
+      reachable_ids,
+    }
+  }
+
+  /// Removes a specifier without removing the package it resolves to.
+  pub fn remove_specifier(&mut self, req: JsrDepPackageReq) {
+    let req = LockfilePkgReq::from_jsr_dep(req);
+    if let Some(id) = self.root_packages.remove(&req) {
+      self.record_removed_root(req, id);
+    }
+  }
+
+  /// Removes a jsr package along with the specifiers that resolve to it.
+  pub fn remove_jsr_package(&mut self, nv: PackageNv) {
+    let id = LockfilePkgId::Jsr(LockfileJsrPkgNv(nv));
+    if let Some(pkg) = self.packages.remove(&id) {
+      self.record_removed_package(id.clone(), pkg);
+    }
+    self.remove_roots_where(|_, pkg_id| *pkg_id == id);
+  }
+
+  /// Removes an npm package along with the packages that depend on it,
+  /// directly or indirectly, and the specifiers that resolve to any of
+  /// them, since those packages can't be installed without it.
+  pub fn remove_npm_package(&mut self, id: StackString) {
+    let mut removed_ids = HashSet::new();
+    let mut pending_ids = vec![LockfilePkgId::Npm(LockfileNpmPackageId(id))];
+    while let Some(id) = pending_ids.pop() {
+      let Some(mut pkg) = self.packages.remove(&id) else {
+        continue;
+      };
+      match &mut pkg {
+        LockfileGraphPackage::Jsr(pkg) => {
+          pending_ids.extend(pkg.dependents.drain())
+        }
+        LockfileGraphPackage::Npm(pkg) => {
+          pending_ids.extend(pkg.dependents.drain())
+        }
+      }
+      removed_ids.insert(id.clone());
+      self.record_removed_package(id, pkg);
+    }
+    self.remove_roots_where(|_, pkg_id| removed_ids.contains(pkg_id));
+  }
+
+  /// Removes the specifiers and packages that were reachable when the
+  /// scope was captured, but no longer are.
+  pub fn remove_orphans(&mut self, scope: LockfileOrphanScope) {
+    let roots = scope
+      .roots
+      .into_iter()
+      .filter(|req| self.root_packages.contains_key(req))
+      .collect();
+    let (reachable_reqs, reachable_ids) = self.reachable_from(&roots);
+    self.remove_roots_where(|req, _| {
+      !reachable_reqs.contains(req) && scope.reachable_reqs.contains(req)
+    });
+    self.remove_packages_where(|id| {
+      !reachable_ids.contains(id) && scope.reachable_ids.contains(id)
+    });
+  }
+
+  fn reachable_from(
+    &self,
+    roots: &HashSet<LockfilePkgReq>,
+  ) -> (HashSet<LockfilePkgReq>, HashSet<LockfilePkgId>) {
+    let mut reqs = HashSet::new();
+    let mut ids = HashSet::new();
+    let mut pending_reqs = roots.iter().collect::<Vec<_>>();
+    let mut pending_ids = Vec::new();
+    loop {
+      if let Some(req) = pending_reqs.pop() {
+        if reqs.insert(req.clone())
+          && let Some(id) = self.root_packages.get(req)
+        {
+          pending_ids.push(id.clone());
+        }
+      } else if let Some(id) = pending_ids.pop() {
+        if !ids.insert(id.clone()) {
+          continue;
+        }
+        match self.packages.get(&id) {
+          Some(LockfileGraphPackage::Jsr(pkg)) => {
+            pending_reqs.extend(pkg.dependencies.iter());
+          }
+          Some(LockfileGraphPackage::Npm(pkg)) => {
+            pending_ids.extend(
+              pkg.all_dependency_ids().cloned().map(LockfilePkgId::Npm),
+            );
+          }
+          None => {}
+        }
+      } else {
+        break;
+      }
+    }
+    (reqs, ids)
+  }
+
+  /// Writes the packages and remotes left in the graph, returning what
+  /// was removed from it.
   pub fn populate_packages(
     self,
     packages: &mut PackagesContent,
     remotes: &mut BTreeMap<String, String>,
-  ) {
+  ) -> PackageChanges {
     *remotes = self.remotes;
+    let mut changes = PackageChanges {
+      removed: self.removed,
+      ..Default::default()
+    };
 
     for (id, package) in self.packages {
-      match package {
-        LockfileGraphPackage::Jsr(package) => {
-          packages.jsr.insert(
-            match id {
-              LockfilePkgId::Jsr(nv) => nv.0,
-              LockfilePkgId::Npm(_) => unreachable!(),
-            },
-            crate::JsrPackageInfo {
-              integrity: package.integrity,
-              dependencies: package
-                .dependencies
+      match (id, package) {
+        (LockfilePkgId::Jsr(nv), LockfileGraphPackage::Jsr(mut package)) => {
+          let (kept_deps, removed_deps): (BTreeSet<_>, BTreeSet<_>) =
+            std::mem::take(&mut package.dependencies)
+              .into_iter()
+              .partition(|dep| self.root_packages.contains_key(dep));
+          package.dependencies = kept_deps;
+          if !removed_deps.is_empty() {
+            changes.removed_jsr_deps.insert(
+              nv.0.clone(),
+              removed_deps
                 .into_iter()
-                .filter(|dep| self.root_packages.contains_key(dep))
-                .map(|req| req.into_jsr_dep())
+                .map(|dep| dep.into_jsr_dep())
                 .collect(),
-            },
-          );
+            );
+          }
+          packages.jsr.insert(nv.0, package.into_info());
         }
-        LockfileGraphPackage::Npm(package) => {
-          packages.npm.insert(
-            match id {
-              LockfilePkgId::Jsr(_) => unreachable!(),
-              LockfilePkgId::Npm(id) => id.0,
-            },
-            NpmPackageInfo {
-              integrity: package.integrity,
-              dependencies: package
-                .dependencies
-                .into_iter()
-                .map(|(name, id)| (name, id.0))
-                .collect(),
-              cpu: package.cpu,
-              os: package.os,
-              tarball: package.tarball.clone(),
-              optional_dependencies: package
-                .optional_dependencies
-                .into_iter()
-                .map(|(name, id)| (name, id.0))
-                .collect(),
-              deprecated: package.deprecated,
-              scripts: package.scripts,
-              bin: package.bin,
-              optional_peers: package
-                .optional_peers
-                .into_iter()
-                .map(|(name, id)| (name, id.0))
-                .collect(),
-            },
-          );
+        (LockfilePkgId::Npm(id), LockfileGraphPackage::Npm(package)) => {
+          packages.npm.insert(id.0, package.into_info());
         }
+        _ => unreachable!(),
       }
     }
 
     for (req, id) in self.root_packages {
-      let value = match &id {
-        LockfilePkgId::Jsr(nv) => {
-          nv.0.version.to_custom_string::<SmallStackString>()
-        }
-        LockfilePkgId::Npm(id) => id
-          .0
-          .as_str()
-          .strip_prefix(req.req().name.as_str())
-          .unwrap()
-          .strip_prefix("@")
-          .unwrap()
-          .into(),
-      };
+      let value = specifier_value(&req, &id);
       packages.specifiers.insert(req.into_jsr_dep(), value);
     }
+    changes
+  }
+}
+
+/// Gets the version a specifier resolves to from the package id.
+fn specifier_value(
+  req: &LockfilePkgReq,
+  id: &LockfilePkgId,
+) -> SmallStackString {
+  match id {
+    LockfilePkgId::Jsr(nv) => {
+      nv.0.version.to_custom_string::<SmallStackString>()
+    }
+    LockfilePkgId::Npm(id) => id
+      .0
+      .as_str()
+      .strip_prefix(req.req().name.as_str())
+      .unwrap()
+      .strip_prefix("@")
+      .unwrap()
+      .into(),
   }
 }
@@ -0,0 +1,362 @@
+// Copyright 2018-2024 the Deno authors. MIT license.
+
+use std::collections::BTreeMap;
+
+use deno_semver::SmallStackString;
+use deno_semver::StackString;
+use deno_semver::jsr::JsrDepPackageReq;
+use deno_semver::package::PackageNv;
+
+use crate::JsrPackageInfo;
+use crate::LockfileContent;
+use crate::NpmPackageInfo;
+use crate::WorkspaceConfig;
+
+/// A change made to the lockfile's content.
+///
+/// An `old` value of `None` means the entry was added and a `new` value
+/// of `None` means it was removed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LockfileMutation {
+  Specifier {
+    req: JsrDepPackageReq,
+    old: Option<SmallStackString>,
+    new: Option<SmallStackString>,
+  },
+  JsrPackage {
+    nv: PackageNv,
+    old: Option<JsrPackageInfo>,
+    new: Option<JsrPackageInfo>,
+  },
+  NpmPackage {
+    id: StackString,
+    old: Option<NpmPackageInfo>,
+    new: Option<NpmPackageInfo>,
+  },
+  Redirect {
+    from: String,
+    old: Option<String>,
+    new: Option<String>,
+  },
+  Remote {
+    specifier: String,
+    old: Option<String>,
+    new: Option<String>,
+  },
+  Workspace {
+    old: Box<WorkspaceConfig>,
+    new: Box<WorkspaceConfig>,
+  },
+}
+
+fn diff_map<K: Ord, V: PartialEq>(
+  old: &BTreeMap<K, V>,
+  new: &BTreeMap<K, V>,
+  mut on_change: impl FnMut(&K, Option<&V>, Option<&V>),
+) {
+  for (key, old_value) in old {
+    let new_value = new.get(key);
+    if new_value != Some(old_value) {
+      on_change(key, Some(old_value), new_value);
+    }
+  }
+  for (key, new_value) in new {
+    if !old.contains_key(key) {
+      on_change(key, None, Some(new_value));
+    }
+  }
+}
+
+fn sorted_specifiers(
+  content: &LockfileContent,
+) -> BTreeMap<StackString, (&JsrDepPackageReq, &SmallStackString)> {
+  content
+    .packages
+    .specifiers
+    .iter()
+    .map(|(req, value)| (req.to_string_normalized(), (req, value)))
+    .collect()
+}
+
+/// Records the changes from the old redirects to the new redirects.
+pub(crate) fn diff_redirects(
+  old: &BTreeMap<String, String>,
+  new: &BTreeMap<String, String>,
+  journal: &mut Vec<LockfileMutation>,
+) {
+  diff_map(old, new, |from, old, new| {
+    journal.push(LockfileMutation::Redirect {
+      from: from.clone(),
+      old: old.cloned(),
+      new: new.cloned(),
+    })
+  });
+}
+
+/// Records the changes from the old remotes to the new remotes.
+pub(crate) fn diff_remote(
+  old: &BTreeMap<String, String>,
+  new: &BTreeMap<String, String>,
+  journal: &mut Vec<LockfileMutation>,
+) {
+  diff_map(old, new, |specifier, old, new| {
+    journal.push(LockfileMutation::Remote {
+      specifier: specifier.clone(),
+      old: old.cloned(),
+      new: new.cloned(),
+    })
+  });
+}
+
+/// Records the changes from the old content to the new content.
+pub(crate) fn diff(
+  old: &LockfileContent,
+  new: &LockfileContent,
+  journal: &mut Vec<LockfileMutation>,
+) {
+  diff_map(
+    &sorted_specifiers(old),
+    &sorted_specifiers(new),
+    |_, old, new| {
+      journal.push(LockfileMutation::Specifier {
+        req: old.or(new).unwrap().0.clone(),
+        old: old.map(|(_, v)| (*v).clone()),
+        new: new.map(|(_, v)| (*v).clone()),
+      })
+    },
+  );
+  diff_map(&old.packages.jsr, &new.packages.jsr, |nv, old, new| {
+    journal.push(LockfileMutation::JsrPackage {
+      nv: nv.clone(),
+      old: old.cloned(),
+      new: new.cloned(),
+    })
+  });
+  diff_map(&old.packages.npm, &new.packages.npm, |id, old, new| {
+    journal.push(LockfileMutation::NpmPackage {
+      id: id.clone(),
+      old: old.cloned(),
+      new: new.cloned(),
+    })
+  });
+  diff_redirects(&old.redirects, &new.redirects, journal);
+  diff_remote(&old.remote, &new.remote, journal);
+  if old.workspace != new.workspace {
+    journal.push(LockfileMutation::Workspace {
+      old: Box::new(old.workspace.to_config()),
+      new: Box::new(new.workspace.to_config()),
+    });
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use std::collections::HashMap;
+  use std::collections::HashSet;
+  use std::path::PathBuf;
+
+  use pretty_assertions::assert_eq;
+
+  use super::*;
+  use crate::Lockfile;
+  use crate::RegistryMirror;
+  use crate::SetWorkspaceConfigOptions;
+  use crate::UrlPrefixMapping;
+  use crate::WorkspaceMemberConfig;
+
+  #[test]
+  fn records_mutations() {
+    let mut lockfile = Lockfile::new_empty(PathBuf::from("/deno.lock"), false);
+    lockfile.insert_remote("https://a.com/a".to_string(), "1".to_string());
+    lockfile.enable_journal();
+    lockfile.insert_remote("https://a.com/a".to_string(), "1".to_string());
+    lockfile.insert_remote("https://a.com/a".to_string(), "2".to_string());
+    assert_eq!(
+      lockfile.take_journal(),
+      vec![LockfileMutation::Remote {
+        specifier: "https://a.com/a".to_string(),
+        old: Some("1".to_string()),
+        new: Some("2".to_string()),
+      }]
+    );
+
+    let req = JsrDepPackageReq::from_str("npm:a@1").unwrap();
+    let config = |deps: &[&JsrDepPackageReq]| WorkspaceConfig {
+      root: WorkspaceMemberConfig {
+        dependencies: deps.iter().map(|req| (*req).clone()).collect(),
+        package_json_deps: HashSet::new(),
+      },
+      members: HashMap::new(),
+      links: HashMap::new(),
+      overrides: HashMap::new(),
+    };
+    lockfile.set_workspace_config(SetWorkspaceConfigOptions {
+      config: config(&[&req]),
+      no_config: false,
+      no_npm: false,
+    });
+    lockfile.insert_package_specifier(req.clone(), "1.0.0".into());
+    lockfile.take_journal();
+
+    lockfile.set_workspace_config(SetWorkspaceConfigOptions {
+      config: config(&[]),
+      no_config: false,
+      no_npm: false,
+    });
+    assert_eq!(
+      lockfile.journal(),
+      &[
+        LockfileMutation::Specifier {
+          req: req.clone(),
+          old: Some("1.0.0".into()),
+          new: None,
+        },
+        LockfileMutation::Workspace {
+          old: Box::new(config(&[&req])),
+          new: Box::new(config(&[])),
+        },
+      ]
+    );
+  }
+
+  #[test]
+  fn records_bulk_changes_like_a_diff() {
+    let mut fixture = Lockfile::new_empty(PathBuf::from("/deno.lock"), false);
+    fixture.content = LockfileContent::from_json(serde_json::json!({
+      "version": "5",
+      "specifiers": {
+        "jsr:@scope/a@1": "1.0.0",
+        "jsr:@scope/b@1": "1.0.0",
+        "npm:c@1": "1.0.0",
+        "npm:d@1": "1.0.0",
+      },
+      "jsr": {
+        "@scope/a@1.0.0": {
+          "integrity": "sha256",
+          "dependencies": ["jsr:@scope/b@1", "npm:c@1"],
+        },
+        "@scope/b@1.0.0": { "integrity": "sha256" },
+      },
+      "npm": {
+        "c@1.0.0": { "integrity": "sha512", "dependencies": ["e"] },
+        "d@1.0.0": { "integrity": "sha512" },
+        "e@1.0.0": { "integrity": "sha512" },
+      },
+      "redirects": {
+        "https://deno.land/x/a/mod.ts": "https://deno.land/x/a@1/mod.ts",
+        "https://deno.land/x/b": "https://deno.land/x/a/mod.ts",
+        "https://a.com/loop_a": "https://a.com/loop_b",
+        "https://a.com/loop_b": "https://a.com/loop_a",
+      },
+      "remote": {
+        "https://deno.land/x/a@1/mod.ts": "checksum",
+        "https://esm.sh/x": "checksum",
+      },
+      "workspace": {
+        "dependencies": ["jsr:@scope/a@1", "npm:d@1"],
+      },
+    }))
+    .unwrap();
+    // dangling entries for repair
+    fixture.insert_package_specifier(
+      JsrDepPackageReq::from_str("npm:missing@1").unwrap(),
+      "1.0.0".into(),
+    );
+    fixture
+      .content
+      .packages
+      .npm
+      .get_mut("d@1.0.0")
+      .unwrap()
+      .dependencies
+      .insert("gone".into(), "gone@1.0.0".into());
+
+    let mirror = RegistryMirror::new(vec![UrlPrefixMapping {
+      from: "https://deno.land/".to_string(),
+      to: "https://mirror.corp/deno/".to_string(),
+    }]);
+    let options = || SetWorkspaceConfigOptions {
+      config: WorkspaceConfig {
+        root: WorkspaceMemberConfig {
+          dependencies: HashSet::from([
+            JsrDepPackageReq::from_str("npm:d@1").unwrap()
+          ]),
+          package_json_deps: HashSet::new(),
+        },
+        members: HashMap::new(),
+        links: HashMap::new(),
+        overrides: HashMap::new(),
+      },
+      no_config: false,
+      no_npm: false,
+    };
+    type Op<'a> = Box<dyn Fn(&mut Lockfile) + 'a>;
+    let ops: Vec<(&str, Op)> = vec![
+      (
+        "repair",
+        Box::new(|lockfile| {
+          lockfile.repair();
+        }),
+      ),
+      (
+        "collapse",
+        Box::new(|lockfile| {
+          lockfile.collapse_redirects();
+        }),
+      ),
+      (
+        "rewrite",
+        Box::new(|lockfile| {
+          lockfile.rewrite_registry_urls(&mirror).unwrap();
+        }),
+      ),
+      (
+        "retain remotes",
+        Box::new(|lockfile| {
+          lockfile.retain_remotes(&HashSet::from([
+            "https://deno.land/x/b".to_string()
+          ]));
+        }),
+      ),
+      (
+        "workspace",
+        Box::new(|lockfile| lockfile.set_workspace_config(options())),
+      ),
+      (
+        "indexed workspace",
+        Box::new(|lockfile| {
+          lockfile.enable_dependency_index();
+          lockfile.set_workspace_config(options());
+        }),
+      ),
+      (
+        "remove specifier",
+        Box::new(|lockfile| {
+          lockfile.remove_package_specifier(
+            &JsrDepPackageReq::from_str("npm:c@1").unwrap(),
+          );
+        }),
+      ),
+      (
+        "remove npm package",
+        Box::new(|lockfile| {
+          lockfile.remove_npm_package("e@1.0.0");
+        }),
+      ),
+    ];
+
+    for (name, op) in ops {
+      let mut lockfile = fixture.clone();
+      lockfile.enable_journal();
+      op(&mut lockfile);
+      let mut expected = Vec::new();
+      diff(&fixture.content, &lockfile.content, &mut expected);
+      assert!(!expected.is_empty(), "{name}");
+      let journal = lockfile.take_journal();
+      assert_eq!(journal.len(), expected.len(), "{name}: {journal:#?}");
+      for mutation in &expected {
+        assert!(journal.contains(mutation), "{name}: {mutation:#?}");
+      }
+    }
+  }
+}
@@ -0,0 +1,266 @@
+// Copyright 2018-2024 the Deno authors. MIT license.
+
+use std::fmt;
+
+use serde::de::DeserializeOwned;
+use serde::de::IgnoredAny;
+
+/// A segment of a [`JsonPath`].
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum JsonPathSegment {
+  Key(String),
+  Index(usize),
+}
+
+/// Location of a value within the lockfile's JSON, such as
+/// `npm["chalk@5.0.0"].dependencies[2]`.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Hash)]
+pub struct JsonPath(Vec<JsonPathSegment>);
+
+impl JsonPath {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  pub fn from_key(key: impl Into<String>) -> Self {
+    Self(vec![JsonPathSegment::Key(key.into())])
+  }
+
+  pub fn segments(&self) -> &[JsonPathSegment] {
+    &self.0
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.0.is_empty()
+  }
+
+  /// Creates a new path with the provided object key appended.
+  pub fn join_key(&self, key: impl Into<String>) -> Self {
+    let mut segments = Vec::with_capacity(self.0.len() + 1);
+    segments.extend(self.0.iter().cloned());
+    segments.push(JsonPathSegment::Key(key.into()));
+    Self(segments)
+  }
+
+  /// Creates a new path with the provided array index appended.
+  pub fn join_index(&self, index: usize) -> Self {
+    let mut segments = Vec::with_capacity(self.0.len() + 1);
+    segments.extend(self.0.iter().cloned());
+    segments.push(JsonPathSegment::Index(index));
+    Self(segments)
+  }
+}
+
+impl fmt::Display for JsonPath {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    fn is_identifier(key: &str) -> bool {
+      let mut chars = key.chars();
+      match chars.next() {
+        Some(c) if c.is_ascii_alphabetic() || c == '_' || c == '$' => {}
+        _ => return false,
+      }
+      chars.all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '$')
+    }
+
+    if self.0.is_empty() {
+      return write!(f, "(root)");
+    }
+    for (i, segment) in self.0.iter().enumerate() {
+      match segment {
+        JsonPathSegment::Key(key) if is_identifier(key) => {
+          if i > 0 {
+            write!(f, ".")?;
+          }
+          write!(f, "{}", key)?;
+        }
+        JsonPathSegment::Key(key) => {
+          write!(f, "[{}]", serde_json::Value::String(key.clone()))?;
+        }
+        JsonPathSegment::Index(index) => write!(f, "[{}]", index)?,
+      }
+    }
+    Ok(())
+  }
+}
+
+/// One-based line and column in the lockfile text.
+///
+/// The column is in bytes to match what `serde_json` reports.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SourcePosition {
+  pub line: usize,
+  pub column: usize,
+}
+
+impl SourcePosition {
+  fn from_offset(text: &str, offset: usize) -> Self {
+    let before = &text[..offset];
+    let line_start = before.rfind('\n').map(|i| i + 1).unwrap_or(0);
+    Self {
+      line: before.matches('\n').count() + 1,
+      column: offset - line_start + 1,
+    }
+  }
+}
+
+impl fmt::Display for SourcePosition {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "{}:{}", self.line, self.column)
+  }
+}
+
+/// Finds the position of the value at the provided path in the JSON text.
+///
+/// For object properties this is the position of the property's key.
+pub(crate) fn find_position(
+  text: &str,
+  path: &JsonPath,
+) -> Option<SourcePosition> {
+  let mut scanner = Scanner {
+    bytes: text.as_bytes(),
+    pos: 0,
+  };
+  scanner.skip_whitespace();
+  let mut target = scanner.pos;
+  for segment in path.segments() {
+    match segment {
+      JsonPathSegment::Key(key) => {
+        scanner.expect(b'{')?;
+        loop {
+          scanner.skip_whitespace();
+          let key_start = scanner.pos;
+          let current_key = scanner.read_string()?;
+          scanner.skip_whitespace();
+          scanner.expect(b':')?;
+          scanner.skip_whitespace();
+          if current_key == *key {
+            target = key_start;
+            break;
+          }
+          scanner.skip_value()?;
+          scanner.skip_whitespace();
+          scanner.expect(b',')?;
+        }
+      }
+      JsonPathSegment::Index(index) => {
+        scanner.expect(b'[')?;
+        let mut current = 0;
+        loop {
+          scanner.skip_whitespace();
+          if scanner.peek()? == b']' {
+            return None;
+          }
+          if current == *index {
+            target = scanner.pos;
+            break;
+          }
+          scanner.skip_value()?;
+          scanner.skip_whitespace();
+          scanner.expect(b',')?;
+          current += 1;
+        }
+      }
+    }
+  }
+  Some(SourcePosition::from_offset(text, target))
+}
+
+struct Scanner<'a> {
+  bytes: &'a [u8],
+  pos: usize,
+}
+
+impl Scanner<'_> {
+  fn peek(&self) -> Option<u8> {
+    self.bytes.get(self.pos).copied()
+  }
+
+  fn next(&mut self) -> Option<u8> {
+    let byte = self.peek()?;
+    self.pos += 1;
+    Some(byte)
+  }
+
+  fn expect(&mut self, byte: u8) -> Option<()> {
+    if self.next()? == byte { Some(()) } else { None }
+  }
+
+  fn skip_whitespace(&mut self) {
+    while let Some(b' ' | b'\n' | b'\r' | b'\t') = self.peek() {
+      self.pos += 1;
+    }
+  }
+
+  /// Deserializes the value at the current position and moves past it.
+  fn read<T: DeserializeOwned>(&mut self) -> Option<T> {
+    let mut values =
+      serde_json::Deserializer::from_slice(&self.bytes[self.pos..])
+        .into_iter::<T>();
+    let value = values.next()?.ok()?;
+    self.pos += values.byte_offset();
+    Some(value)
+  }
+
+  fn read_string(&mut self) -> Option<String> {
+    self.read()
+  }
+
+  fn skip_value(&mut self) -> Option<()> {
+    self.read::<IgnoredAny>().map(|_| ())
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use pretty_assertions::assert_eq;
+
+  use super::*;
+
+  #[test]
+  fn display() {
+    let path = JsonPath::from_key("npm")
+      .join_key("foo@1.0.0")
+      .join_key("dependencies")
+      .join_index(2);
+    assert_eq!(path.to_string(), r#"npm["foo@1.0.0"].dependencies[2]"#);
+    assert_eq!(JsonPath::new().to_string(), "(root)");
+  }
+
+  #[test]
+  fn finds_position() {
+    let text = r#"{
+  "version": "5",
+  "npm": {
+    "a\"b@1.0.0": { "dependencies": ["x", {"y": [1, 2]}] },
+    "foo@1.0.0": {
+      "dependencies": [
+        "a",
+        "b",
+        "c"
+      ]
+    }
+  }
+}"#;
+    let path = JsonPath::from_key("npm")
+      .join_key("foo@1.0.0")
+      .join_key("dependencies")
+      .join_index(2);
+    assert_eq!(
+      find_position(text, &path),
+      Some(SourcePosition { line: 9, column: 9 })
+    );
+    assert_eq!(
+      find_position(text, &JsonPath::from_key("npm").join_key("a\"b@1.0.0")),
+      Some(SourcePosition { line: 4, column: 5 })
+    );
+    assert_eq!(
+      find_position(text, &JsonPath::from_key("npm").join_key("missing")),
+      None
+    );
+    assert_eq!(
+      find_position(text, &path.join_index(0)),
+      None,
+      "indexing into a string"
+    );
+  }
+}
@@ -3,8 +3,16 @@
 #![deny(clippy::print_stderr)]
 #![deny(clippy::print_stdout)]
 
+mod audit;
+mod dependency_index;
+mod dependency_paths;
 mod error;
+mod fs;
 mod graphs;
+mod journal;
+mod json_path;
+mod merge;
+mod mirror;
 
 use std::borrow::Cow;
 use std::collections::BTreeMap;
@@ -12,6 +20,9 @@ use std::collections::HashMap;
 use std::collections::HashSet;
 use std::collections::btree_map::Entry as BTreeMapEntry;
 use std::collections::hash_map::Entry as HashMapEntry;
+use std::convert::Infallible;
+use std::hash::Hash;
+use std::hash::Hasher;
 use std::path::PathBuf;
 
 use deno_semver::SmallStackString;
@@ -24,16 +35,77 @@ use serde::Deserialize;
 use serde::Serialize;
 use serde::de::DeserializeOwned;
 
+mod outdated;
+mod overrides;
+mod policy;
 mod printer;
+mod redirects;
+mod remote_versions;
+#[cfg(test)]
+mod test_util;
+mod transaction;
 mod transforms;
-
+mod upgrade_plan;
+mod validation;
+mod workspace_report;
+
+pub use audit::AdvisoryDatabase;
+pub use audit::AdvisoryLoadError;
+pub use audit::AdvisoryLoadErrorKind;
+pub use audit::AuditFinding;
+pub use dependency_paths::DependencyPath;
+pub use dependency_paths::DependencyPathRoot;
+pub use dependency_paths::LockfilePackageId;
+pub use error::DeserializationDiagnostic;
 pub use error::DeserializationError;
 pub use error::LockfileError;
 pub use error::LockfileErrorReason;
+pub use error::LockfileSaveError;
+pub use fs::InMemoryLockfileFs;
+pub use fs::LockfileFs;
+pub use fs::LockfileFsLock;
+pub use fs::RealLockfileFs;
+pub use journal::LockfileMutation;
+pub use json_path::JsonPath;
+pub use json_path::JsonPathSegment;
+pub use json_path::SourcePosition;
+pub use merge::LockfileMergeConflict;
+pub use mirror::RegistryMirror;
+pub use mirror::RegistryUrlCollisionError;
+pub use mirror::UrlPrefixMapping;
+pub use outdated::DirPackageVersionProvider;
+pub use outdated::InvalidLockedSpecifier;
+pub use outdated::OutdatedPackage;
+pub use outdated::OutdatedReport;
+pub use outdated::OutdatedSpecifier;
+pub use outdated::PackageVersionProvider;
+pub use outdated::PackageVersions;
+pub use outdated::PackageVersionsLoadError;
+pub use outdated::PackageVersionsLoadErrorKind;
+pub use outdated::SatisfyingVersion;
+pub use policy::DeniedPackageRule;
+pub use policy::LockfilePolicy;
+pub use policy::PolicyViolation;
+pub use policy::PolicyViolationKind;
+pub use redirects::RedirectChainError;
+pub use redirects::ResolvedRedirect;
+pub use remote_versions::DuplicateRemoteModule;
+pub use remote_versions::RemoteModuleVersion;
+pub use transaction::LockfileTransaction;
 pub use transforms::Lockfile5NpmInfo;
 pub use transforms::NpmPackageInfoProvider;
-
+pub use upgrade_plan::PlannedUpgrade;
+pub use upgrade_plan::UpgradePlan;
+pub use validation::RepairEdit;
+pub use validation::ValidationDiagnostic;
+pub use validation::ValidationDiagnosticKind;
+pub use workspace_report::WorkspaceConfigDiff;
+pub use workspace_report::WorkspaceConfigReport;
+pub use workspace_report::WorkspaceDepsChange;
+
+use crate::dependency_index::DependencyIndex;
 use crate::graphs::LockfilePackageGraph;
+use crate::transforms::TransformError;
 
 pub struct SetWorkspaceConfigOptions {
   pub config: WorkspaceConfig,
@@ -56,6 +128,9 @@ pub struct WorkspaceConfig {
   pub root: WorkspaceMemberConfig,
   pub members: HashMap<String, WorkspaceMemberConfig>,
   pub links: HashMap<String, LockfileLinkContent>,
+  /// Versions that packages are forced to wherever they're depended on,
+  /// keyed by the package they apply to, such as `"npm:foo@^1": "1.2.3"`.
+  pub overrides: HashMap<String, String>,
 }
 
 #[derive(Default, Debug, Clone, PartialEq, Eq)]
@@ -86,7 +161,9 @@ pub struct NpmPackageDependencyLockfileInfo {
   pub id: StackString,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize, Hash, PartialEq, Eq)]
+#[derive(
+  Debug, Default, Clone, Serialize, Deserialize, Hash, PartialEq, Eq,
+)]
 pub struct NpmPackageInfo {
   /// Will be `None` for patch packages.
   pub integrity: Option<String>,
@@ -108,9 +185,16 @@ pub struct NpmPackageInfo {
   pub scripts: bool,
   #[serde(default, skip_serializing_if = "is_false")]
   pub bin: bool,
+  #[serde(flatten)]
+  pub(crate) unknown_fields: UnknownFields,
 }
 
 impl NpmPackageInfo {
+  /// Properties of the package entry not known to this crate.
+  pub fn unknown_fields(&self) -> &UnknownFields {
+    &self.unknown_fields
+  }
+
   pub fn matches_link(&self, link: &LockfileLinkContent) -> bool {
     fn parse_nv(v: &StackString) -> Option<PackageNv> {
       let v = v.split_once('_').map(|(l, _)| l).unwrap_or(v);
@@ -190,22 +274,63 @@ fn is_false(value: &bool) -> bool {
   !value
 }
 
+/// Properties found in the lockfile that this crate doesn't know about.
+///
+/// These are kept around and written back out so that a lockfile
+/// written by a newer version of Deno doesn't lose information when
+/// it's rewritten by an older version.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct UnknownFields(pub BTreeMap<String, serde_json::Value>);
+
+impl UnknownFields {
+  pub fn is_empty(&self) -> bool {
+    self.0.is_empty()
+  }
+}
+
+impl Hash for UnknownFields {
+  fn hash<H: Hasher>(&self, state: &mut H) {
+    for (key, value) in &self.0 {
+      key.hash(state);
+      value.to_string().hash(state);
+    }
+  }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize, Hash, PartialEq, Eq)]
 pub struct NpmPackageDist {
   pub shasum: String,
   pub integrity: Option<String>,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct JsrPackageInfo {
   pub integrity: String,
   /// List of package requirements found in the dependency.
   ///
   /// This is used to tell when a package can be removed from the lockfile.
   pub dependencies: HashSet<JsrDepPackageReq>,
+  pub(crate) unknown_fields: UnknownFields,
 }
 
 impl JsrPackageInfo {
+  pub fn new(
+    integrity: String,
+    dependencies: HashSet<JsrDepPackageReq>,
+  ) -> Self {
+    Self {
+      integrity,
+      dependencies,
+      unknown_fields: Default::default(),
+    }
+  }
+
+  /// Properties of the package entry not known to this crate.
+  pub fn unknown_fields(&self) -> &UnknownFields {
+    &self.unknown_fields
+  }
+
   pub fn matches_link(&self, link: &LockfileLinkContent) -> bool {
     self.dependencies == link.dependencies
   }
@@ -251,7 +376,7 @@ impl PackagesContent {
   }
 }
 
-#[derive(Debug, Default, Clone, Deserialize)]
+#[derive(Debug, Default, Clone, Deserialize, PartialEq, Eq)]
 pub(crate) struct LockfilePackageJsonContent {
   pub dependencies: HashSet<JsrDepPackageReq>,
 }
@@ -262,7 +387,7 @@ impl LockfilePackageJsonContent {
   }
 }
 
-#[derive(Debug, Default, Clone, Deserialize)]
+#[derive(Debug, Default, Clone, Deserialize, PartialEq, Eq)]
 #[serde(rename_all = "camelCase")]
 pub(crate) struct WorkspaceMemberConfigContent {
   #[serde(default)]
@@ -312,7 +437,7 @@ impl LockfileLinkContent {
   }
 }
 
-#[derive(Debug, Default, Clone, Deserialize)]
+#[derive(Debug, Default, Clone, Deserialize, PartialEq, Eq)]
 #[serde(rename_all = "camelCase")]
 pub(crate) struct WorkspaceConfigContent {
   #[serde(default, flatten)]
@@ -322,11 +447,34 @@ pub(crate) struct WorkspaceConfigContent {
   // todo(dsherret): patches is deprecated, remove in Deno 3.0
   #[serde(default, alias = "patches")]
   pub links: HashMap<String, LockfileLinkContent>,
+  #[serde(default)]
+  pub overrides: HashMap<String, String>,
 }
 
 impl WorkspaceConfigContent {
   pub fn is_empty(&self) -> bool {
-    self.root.is_empty() && self.members.is_empty() && self.links.is_empty()
+    self.root.is_empty()
+      && self.members.is_empty()
+      && self.links.is_empty()
+      && self.overrides.is_empty()
+  }
+
+  pub fn to_config(&self) -> WorkspaceConfig {
+    let member_config =
+      |member: &WorkspaceMemberConfigContent| WorkspaceMemberConfig {
+        dependencies: member.dependencies.clone(),
+        package_json_deps: member.package_json.dependencies.clone(),
+      };
+    WorkspaceConfig {
+      root: member_config(&self.root),
+      members: self
+        .members
+        .iter()
+        .map(|(name, member)| (name.clone(), member_config(member)))
+        .collect(),
+      links: self.links.clone(),
+      overrides: self.overrides.clone(),
+    }
   }
 
   fn get_all_dep_reqs(&self) -> impl Iterator<Item = &JsrDepPackageReq> {
@@ -335,6 +483,348 @@ impl WorkspaceConfigContent {
       .dep_reqs()
       .chain(self.members.values().flat_map(|m| m.dep_reqs()))
   }
+
+  /// Sets the workspace config from the options, returning what changed.
+  ///
+  /// The dependencies that are no longer in the workspace and the links
+  /// that changed are returned so the packages only they used can be
+  /// removed.
+  fn apply(
+    &mut self,
+    packages: &PackagesContent,
+    mut options: SetWorkspaceConfigOptions,
+  ) -> WorkspaceConfigUpdate {
+    fn update_workspace_member(
+      has_content_changed: &mut bool,
+      removed_deps: &mut HashSet<JsrDepPackageReq>,
+      current: &mut WorkspaceMemberConfigContent,
+      new: WorkspaceMemberConfig,
+    ) {
+      if new.dependencies != current.dependencies {
+        let old_deps =
+          std::mem::replace(&mut current.dependencies, new.dependencies);
+
+        removed_deps.extend(old_deps);
+
+        *has_content_changed = true;
+      }
+
+      if new.package_json_deps != current.package_json.dependencies {
+        // update self.content.package_json
+        let old_package_json_deps = std::mem::replace(
+          &mut current.package_json.dependencies,
+          new.package_json_deps,
+        );
+
+        removed_deps.extend(old_package_json_deps);
+
+        *has_content_changed = true;
+      }
+    }
+
+    fn find_renamed_members(
+      current: &HashMap<String, WorkspaceMemberConfigContent>,
+      new: &HashMap<String, WorkspaceMemberConfig>,
+    ) -> BTreeMap<String, String> {
+      let mut candidates = Vec::new();
+      for (old_name, old_member) in current {
+        if new.contains_key(old_name) {
+          continue;
+        }
+        let old_deps = old_member.dep_reqs().collect::<HashSet<_>>();
+        for (new_name, new_member) in new {
+          if current.contains_key(new_name) {
+            continue;
+          }
+          let new_deps = new_member
+            .dependencies
+            .iter()
+            .chain(&new_member.package_json_deps)
+            .collect::<HashSet<_>>();
+          let shared_count = new_deps
+            .iter()
+            .filter(|dep| old_deps.contains(*dep))
+            .count();
+          // a member is only renamed when most of the dependencies on
+          // both sides are the same, otherwise members that happen to
+          // share a common dependency would be paired up
+          if shared_count * 2 > old_deps.len()
+            && shared_count * 2 > new_deps.len()
+          {
+            candidates.push((shared_count, old_name, new_name));
+          }
+        }
+      }
+      // pair up the members that share the most dependencies first
+      candidates.sort_by(|a, b| {
+        b.0.cmp(&a.0).then_with(|| (a.1, a.2).cmp(&(b.1, b.2)))
+      });
+      let mut renames = BTreeMap::new();
+      let mut renamed_to = HashSet::new();
+      for (_, old_name, new_name) in candidates {
+        if !renames.contains_key(old_name) && renamed_to.insert(new_name) {
+          renames.insert(old_name.clone(), new_name.clone());
+        }
+      }
+      renames
+    }
+
+    let mut has_changed = false;
+
+    // if specified, don't modify the package.json dependencies
+    if options.no_npm || options.no_config {
+      if options.config.root.package_json_deps.is_empty() {
+        options
+          .config
+          .root
+          .package_json_deps
+          .clone_from(&self.root.package_json.dependencies);
+      }
+      for (key, value) in options.config.members.iter_mut() {
+        if value.package_json_deps.is_empty() {
+          value.package_json_deps = self
+            .members
+            .get(key)
+            .map(|m| m.package_json.dependencies.clone())
+            .unwrap_or_default();
+        }
+      }
+    }
+    if options.no_config {
+      if options.config.root.dependencies.is_empty() {
+        options
+          .config
+          .root
+          .dependencies
+          .clone_from(&self.root.dependencies);
+      }
+      for (key, value) in options.config.members.iter_mut() {
+        if value.dependencies.is_empty() {
+          value.dependencies = self
+            .members
+            .get(key)
+            .map(|m| m.dependencies.clone())
+            .unwrap_or_default();
+        }
+      }
+      for (key, value) in self.members.iter() {
+        if !options.config.members.contains_key(key) {
+          options.config.members.insert(
+            key.clone(),
+            WorkspaceMemberConfig {
+              dependencies: value.dependencies.clone(),
+              package_json_deps: value.package_json.dependencies.clone(),
+            },
+          );
+        }
+      }
+    }
+
+    let has_any_patch_changed = options.config.links != self.links;
+
+    let mut removed_deps = HashSet::new();
+    let mut changed_links = HashSet::new();
+    if has_any_patch_changed {
+      has_changed = true;
+      let mut unhandled_links =
+        self.links.keys().cloned().collect::<HashSet<_>>();
+      changed_links.reserve(options.config.links.len());
+      for (link_name, new) in options.config.links {
+        if !unhandled_links.remove(&link_name) {
+          if let Ok(dep_req) = JsrDepPackageReq::from_str(&link_name) {
+            let had_change = (|| match dep_req.kind {
+              PackageKind::Jsr => {
+                for (key, package) in &packages.jsr {
+                  if key.name != dep_req.req.name {
+                    continue;
+                  }
+                  if !dep_req.req.version_req.matches(&key.version)
+                    || !package.matches_link(&new)
+                  {
+                    return true;
+                  }
+                }
+                false
+              }
+              PackageKind::Npm => {
+                for (key, package) in &packages.npm {
+                  let Some(key) = key.strip_prefix(dep_req.req.name.as_str())
+                  else {
+                    continue;
+                  };
+                  let Some(key) = key.strip_prefix('@') else {
+                    continue;
+                  };
+                  let version =
+                    key.split_once('_').map(|(l, _)| l).unwrap_or(key);
+                  let Ok(version) = Version::parse_from_npm(version) else {
+                    continue;
+                  };
+                  if !dep_req.req.version_req.matches(&version)
+                    || !package.matches_link(&new)
+                  {
+                    return true;
+                  }
+                }
+                false
+              }
+            })();
+
+            if had_change {
+              changed_links.insert(dep_req);
+            }
+          }
+          self.links.insert(link_name.clone(), new);
+        } else {
+          let current = self.links.entry(link_name.clone()).or_default();
+          if new != *current {
+            *current = new;
+            if let Ok(dep_req) = JsrDepPackageReq::from_str(&link_name) {
+              changed_links.insert(dep_req);
+            }
+          }
+        }
+      }
+
+      for member in unhandled_links {
+        if let Some(member) = self.links.remove(&member) {
+          removed_deps.extend(member.dep_reqs().cloned());
+        }
+      }
+    }
+
+    if options.config.overrides != self.overrides {
+      has_changed = true;
+      self.overrides = options.config.overrides;
+    }
+
+    // set the root
+    update_workspace_member(
+      &mut has_changed,
+      &mut removed_deps,
+      &mut self.root,
+      options.config.root,
+    );
+
+    // treat a removed member and an added member that share dependencies
+    // as a rename (ex. the member's directory was moved) so the member is
+    // updated rather than replaced
+    let renamed_members =
+      find_renamed_members(&self.members, &options.config.members);
+    for (old_name, new_name) in &renamed_members {
+      if let Some(member) = self.members.remove(old_name) {
+        self.members.insert(new_name.clone(), member);
+        has_changed = true;
+      }
+    }
+
+    // now go through the workspaces
+    let mut unhandled_members =
+      self.members.keys().cloned().collect::<HashSet<_>>();
+    for (member_name, new_member) in options.config.members {
+      unhandled_members.remove(&member_name);
+      let current_member = self.members.entry(member_name).or_default();
+      update_workspace_member(
+        &mut has_changed,
+        &mut removed_deps,
+        current_member,
+        new_member,
+      );
+    }
+
+    for member in unhandled_members {
+      if let Some(member) = self.members.remove(&member) {
+        removed_deps.extend(member.dep_reqs().cloned());
+        has_changed = true;
+      }
+    }
+
+    // update the removed deps to keep what's still found in the workspace
+    for dep in self.get_all_dep_reqs() {
+      removed_deps.remove(dep);
+    }
+
+    WorkspaceConfigUpdate {
+      has_changed,
+      removed_deps,
+      changed_links,
+      renamed_members,
+    }
+  }
+}
+
+/// What [`WorkspaceConfigContent::apply`] changed.
+struct WorkspaceConfigUpdate {
+  has_changed: bool,
+  /// Dependencies that are no longer in the workspace.
+  removed_deps: HashSet<JsrDepPackageReq>,
+  changed_links: HashSet<JsrDepPackageReq>,
+  renamed_members: BTreeMap<String, String>,
+}
+
+/// Removes the packages that are no longer used after the dependencies
+/// were removed and the links changed.
+fn prune_with_graph(
+  packages: &mut PackagesContent,
+  removed_deps: HashSet<JsrDepPackageReq>,
+  changed_links: HashSet<JsrDepPackageReq>,
+) -> PackageChanges {
+  // the graph passes remotes through unchanged, so they're left out
+  let mut graph = LockfilePackageGraph::from_lockfile(
+    std::mem::take(packages),
+    BTreeMap::new(),
+  );
+  graph.remove_root_packages(removed_deps.into_iter());
+  graph.remove_links(changed_links.into_iter());
+  graph.populate_packages(packages, &mut BTreeMap::new())
+}
+
+/// Where errors go while deserializing a lockfile.
+trait DiagnosticSink {
+  /// Error that stops deserialization.
+  type Error;
+
+  fn report(
+    &mut self,
+    diagnostic: DeserializationDiagnostic,
+  ) -> Result<(), Self::Error>;
+
+  /// Reports a problem with an entry that's skipped even when loading
+  /// strictly, so it never stops deserialization.
+  fn report_skipped(&mut self, diagnostic: DeserializationDiagnostic);
+}
+
+/// Stops at the first error.
+struct FailFast;
+
+impl DiagnosticSink for FailFast {
+  type Error = DeserializationDiagnostic;
+
+  fn report(
+    &mut self,
+    diagnostic: DeserializationDiagnostic,
+  ) -> Result<(), Self::Error> {
+    Err(diagnostic)
+  }
+
+  fn report_skipped(&mut self, _diagnostic: DeserializationDiagnostic) {}
+}
+
+/// Collects the errors and skips each invalid entry, so never stops.
+impl DiagnosticSink for Vec<DeserializationDiagnostic> {
+  type Error = Infallible;
+
+  fn report(
+    &mut self,
+    diagnostic: DeserializationDiagnostic,
+  ) -> Result<(), Self::Error> {
+    self.push(diagnostic);
+    Ok(())
+  }
+
+  fn report_skipped(&mut self, diagnostic: DeserializationDiagnostic) {
+    self.push(diagnostic);
+  }
 }
 
 #[derive(Debug, Default, Clone)]
@@ -344,12 +834,34 @@ pub struct LockfileContent {
   /// Mapping between URLs and their checksums for "http:" and "https:" deps
   pub(crate) remote: BTreeMap<String, String>,
   pub(crate) workspace: WorkspaceConfigContent,
+  /// Top level keys not known to this crate.
+  pub unknown_fields: UnknownFields,
 }
 
 impl LockfileContent {
   pub fn from_json(
     json: serde_json::Value,
   ) -> Result<Self, DeserializationError> {
+    Self::from_json_inner(json, &mut FailFast)
+      .map_err(|diagnostic| *diagnostic.error)
+  }
+
+  /// Deserializes the lockfile content while skipping any individually
+  /// invalid specifiers, packages, and dependencies.
+  ///
+  /// Returns the content along with an error for each entry that was skipped.
+  pub fn from_json_lenient(
+    json: serde_json::Value,
+  ) -> (Self, Vec<DeserializationDiagnostic>) {
+    let mut errors = Vec::new();
+    let Ok(content) = Self::from_json_inner(json, &mut errors);
+    (content, errors)
+  }
+
+  fn from_json_inner<D: DiagnosticSink>(
+    json: serde_json::Value,
+    diagnostics: &mut D,
+  ) -> Result<Self, D::Error> {
     fn extract_nv_from_id(value: &str) -> Option<(&str, &str)> {
       if value.is_empty() {
         return None;
@@ -397,6 +909,24 @@ impl LockfileContent {
       Ok(())
     }
 
+    fn handle_deps<D: DiagnosticSink>(
+      deps: Vec<StackString>,
+      version_by_dep_name: &HashMap<StackString, StackString>,
+      path: JsonPath,
+      diagnostics: &mut D,
+    ) -> Result<BTreeMap<StackString, StackString>, D::Error> {
+      let mut dependencies = BTreeMap::new();
+      for (i, dep) in deps.into_iter().enumerate() {
+        if let Err(kind) =
+          handle_dep(dep, version_by_dep_name, &mut dependencies)
+        {
+          diagnostics
+            .report(DeserializationDiagnostic::new(path.join_index(i), kind))?;
+        }
+      }
+      Ok(dependencies)
+    }
+
     #[derive(Debug, Deserialize)]
     #[serde(rename_all = "camelCase")]
     struct RawNpmPackageInfo {
@@ -419,6 +949,8 @@ impl LockfileContent {
       pub scripts: bool,
       #[serde(default, skip_serializing_if = "is_false")]
       pub bin: bool,
+      #[serde(flatten)]
+      pub unknown_fields: UnknownFields,
     }
 
     #[derive(Debug, Deserialize)]
@@ -426,77 +958,178 @@ impl LockfileContent {
       pub integrity: String,
       #[serde(default)]
       pub dependencies: Vec<StackString>,
+      #[serde(flatten)]
+      pub unknown_fields: UnknownFields,
+    }
+
+    fn deserialize_value<T: DeserializeOwned>(
+      value: serde_json::Value,
+      section: &'static str,
+      path: impl FnOnce() -> JsonPath,
+    ) -> Result<T, DeserializationDiagnostic> {
+      serde_json::from_value(value).map_err(|err| {
+        DeserializationDiagnostic::new(
+          path(),
+          DeserializationError::FailedDeserializing(section, err),
+        )
+      })
     }
 
-    fn deserialize_section<T: DeserializeOwned + Default>(
+    fn deserialize_section<T: DeserializeOwned + Default, D: DiagnosticSink>(
       json: &mut serde_json::Map<String, serde_json::Value>,
+      parent: &JsonPath,
       key: &'static str,
-    ) -> Result<T, DeserializationError> {
+      diagnostics: &mut D,
+    ) -> Result<T, D::Error> {
       match json.remove(key) {
-        Some(value) => serde_json::from_value(value)
-          .map_err(|err| DeserializationError::FailedDeserializing(key, err)),
+        Some(value) => {
+          match deserialize_value(value, key, || parent.join_key(key)) {
+            Ok(value) => Ok(value),
+            Err(err) => {
+              diagnostics.report(err)?;
+              Ok(Default::default())
+            }
+          }
+        }
         None => Ok(Default::default()),
       }
     }
 
+    /// Deserializes each entry of an object section separately so
+    /// that errors can point at the entry and only that entry is skipped.
+    fn deserialize_entries<T: DeserializeOwned, D: DiagnosticSink>(
+      json: &mut serde_json::Map<String, serde_json::Value>,
+      parent: &JsonPath,
+      key: &'static str,
+      diagnostics: &mut D,
+    ) -> Result<Vec<(String, T)>, D::Error> {
+      let section: serde_json::Map<String, serde_json::Value> =
+        deserialize_section(json, parent, key, diagnostics)?;
+      let mut entries = Vec::with_capacity(section.len());
+      for (entry_key, value) in section {
+        match deserialize_value(value, key, || {
+          parent.join_key(key).join_key(entry_key.as_str())
+        }) {
+          Ok(value) => entries.push((entry_key, value)),
+          Err(err) => diagnostics.report(err)?,
+        }
+      }
+      Ok(entries)
+    }
+
+    fn deserialize_workspace<D: DiagnosticSink>(
+      json: &mut serde_json::Map<String, serde_json::Value>,
+      diagnostics: &mut D,
+    ) -> Result<WorkspaceConfigContent, D::Error> {
+      let path = JsonPath::from_key("workspace");
+      let mut workspace: serde_json::Map<String, serde_json::Value> =
+        deserialize_section(json, &JsonPath::new(), "workspace", diagnostics)?;
+      let members =
+        deserialize_entries(&mut workspace, &path, "members", diagnostics)?;
+      // todo(dsherret): patches is deprecated, remove in Deno 3.0
+      let links_key = if workspace.contains_key("links") {
+        "links"
+      } else {
+        "patches"
+      };
+      let links =
+        deserialize_entries(&mut workspace, &path, links_key, diagnostics)?;
+      let overrides =
+        deserialize_entries(&mut workspace, &path, "overrides", diagnostics)?;
+      // the root member's config is what remains
+      let root = match deserialize_value(
+        serde_json::Value::Object(workspace),
+        "workspace",
+        || path.clone(),
+      ) {
+        Ok(root) => root,
+        Err(err) => {
+          diagnostics.report(err)?;
+          Default::default()
+        }
+      };
+      Ok(WorkspaceConfigContent {
+        root,
+        members: members.into_iter().collect(),
+        links: links.into_iter().collect(),
+        overrides: overrides.into_iter().collect(),
+      })
+    }
+
     use serde_json::Value;
 
     let Value::Object(mut json) = json else {
       return Ok(Self::default());
     };
 
-    Ok(LockfileContent {
+    let mut content = LockfileContent {
       packages: {
-        let deserialized_specifiers: BTreeMap<StackString, SmallStackString> =
-          deserialize_section(&mut json, "specifiers")?;
+        let deserialized_specifiers: Vec<(String, SmallStackString)> =
+          deserialize_entries(
+            &mut json,
+            &JsonPath::new(),
+            "specifiers",
+            diagnostics,
+          )?;
         let mut specifiers =
           HashMap::with_capacity(deserialized_specifiers.len());
         for (key, value) in deserialized_specifiers {
-          let dep = JsrDepPackageReq::from_str_loose(&key)?;
-          specifiers.insert(dep, value);
+          match JsrDepPackageReq::from_str_loose(&key) {
+            Ok(dep) => {
+              specifiers.insert(dep, value);
+            }
+            Err(err) => diagnostics.report(DeserializationDiagnostic::new(
+              JsonPath::from_key("specifiers").join_key(key),
+              err.into(),
+            ))?,
+          }
         }
 
         let mut npm: BTreeMap<StackString, NpmPackageInfo> = Default::default();
-        let raw_npm: BTreeMap<StackString, RawNpmPackageInfo> =
-          deserialize_section(&mut json, "npm")?;
+        let mut raw_npm: Vec<(String, RawNpmPackageInfo)> =
+          deserialize_entries(&mut json, &JsonPath::new(), "npm", diagnostics)?;
         if !raw_npm.is_empty() {
           // collect the versions
           let mut version_by_dep_name: HashMap<StackString, StackString> =
             HashMap::with_capacity(raw_npm.len());
-          for id in raw_npm.keys() {
+          let mut invalid_ids = Vec::new();
+          for (id, _) in &raw_npm {
             let Some((name, version)) = extract_nv_from_id(id) else {
-              return Err(DeserializationError::InvalidNpmPackageId(
-                id.clone(),
-              ));
+              diagnostics.report(DeserializationDiagnostic::new(
+                JsonPath::from_key("npm").join_key(id.as_str()),
+                DeserializationError::InvalidNpmPackageId(id.as_str().into()),
+              ))?;
+              invalid_ids.push(id.clone());
+              continue;
             };
             version_by_dep_name.insert(name.into(), version.into());
           }
+          raw_npm.retain(|(id, _)| !invalid_ids.contains(id));
 
           // now go through and create the resolved npm package information
           for (key, value) in raw_npm {
-            let mut dependencies: BTreeMap<StackString, StackString> =
-              BTreeMap::new();
-            let mut optional_dependencies =
-              BTreeMap::<StackString, StackString>::new();
-            let mut optional_peers =
-              BTreeMap::<StackString, StackString>::new();
-
-            for dep in value.dependencies.into_iter() {
-              handle_dep(dep, &version_by_dep_name, &mut dependencies)?;
-            }
-            for dep in value.optional_dependencies.into_iter() {
-              handle_dep(
-                dep,
-                &version_by_dep_name,
-                &mut optional_dependencies,
-              )?;
-            }
-            for dep in value.optional_peers.into_iter() {
-              handle_dep(dep, &version_by_dep_name, &mut optional_peers)?;
-            }
+            let path = JsonPath::from_key("npm").join_key(key.as_str());
+            let dependencies = handle_deps(
+              value.dependencies,
+              &version_by_dep_name,
+              path.join_key("dependencies"),
+              diagnostics,
+            )?;
+            let optional_dependencies = handle_deps(
+              value.optional_dependencies,
+              &version_by_dep_name,
+              path.join_key("optionalDependencies"),
+              diagnostics,
+            )?;
+            let optional_peers = handle_deps(
+              value.optional_peers,
+              &version_by_dep_name,
+              path.join_key("optionalPeers"),
+              diagnostics,
+            )?;
 
             npm.insert(
-              key,
+              key.as_str().into(),
               NpmPackageInfo {
                 integrity: value.integrity,
                 dependencies,
@@ -508,14 +1141,19 @@ impl LockfileContent {
                 deprecated: value.deprecated,
                 scripts: value.scripts,
                 bin: value.bin,
+                unknown_fields: value.unknown_fields,
               },
             );
           }
         }
         let mut jsr: BTreeMap<PackageNv, JsrPackageInfo> = Default::default();
         {
-          let raw_jsr: BTreeMap<PackageNv, RawJsrPackageInfo> =
-            deserialize_section(&mut json, "jsr")?;
+          let raw_jsr: Vec<(String, RawJsrPackageInfo)> = deserialize_entries(
+            &mut json,
+            &JsonPath::new(),
+            "jsr",
+            diagnostics,
+          )?;
           if !raw_jsr.is_empty() {
             // collect the specifier information
             let mut to_resolved_specifiers: HashMap<
@@ -544,20 +1182,40 @@ impl LockfileContent {
             }
 
             // now go through the dependencies mapping to the new ones
-            for (key, value) in raw_jsr {
+            for (raw_key, value) in raw_jsr {
+              let path = JsonPath::from_key("jsr").join_key(raw_key.as_str());
+              let key: PackageNv =
+                match deserialize_value(Value::String(raw_key), "jsr", || {
+                  path.clone()
+                }) {
+                  Ok(key) => key,
+                  Err(err) => {
+                    diagnostics.report(err)?;
+                    continue;
+                  }
+                };
               let mut dependencies =
                 HashSet::with_capacity(value.dependencies.len());
-              for dep in value.dependencies {
+              for (i, dep) in value.dependencies.into_iter().enumerate() {
                 let raw_dep = dep;
+                let diagnostic = |raw_dep| {
+                  DeserializationDiagnostic::new(
+                    path.join_key("dependencies").join_index(i),
+                    DeserializationError::InvalidJsrDependency {
+                      dependency: raw_dep,
+                      package: key.clone(),
+                    },
+                  )
+                };
                 let Ok(dep) = JsrDepPackageReq::from_str(&raw_dep) else {
-                  continue; // should never happen
+                  // skipped when loading strictly as well
+                  diagnostics.report_skipped(diagnostic(raw_dep));
+                  continue;
                 };
                 let Some(resolved_dep) = to_resolved_specifiers.get(&dep)
                 else {
-                  return Err(DeserializationError::InvalidJsrDependency {
-                    dependency: raw_dep,
-                    package: key,
-                  });
+                  diagnostics.report(diagnostic(raw_dep))?;
+                  continue;
                 };
                 dependencies.insert((*resolved_dep).clone());
               }
@@ -566,6 +1224,7 @@ impl LockfileContent {
                 JsrPackageInfo {
                   integrity: value.integrity,
                   dependencies,
+                  unknown_fields: value.unknown_fields,
                 },
               );
             }
@@ -578,10 +1237,29 @@ impl LockfileContent {
           npm,
         }
       },
-      redirects: deserialize_section(&mut json, "redirects")?,
-      remote: deserialize_section(&mut json, "remote")?,
-      workspace: deserialize_section(&mut json, "workspace")?,
-    })
+      redirects: deserialize_entries(
+        &mut json,
+        &JsonPath::new(),
+        "redirects",
+        diagnostics,
+      )?
+      .into_iter()
+      .collect(),
+      remote: deserialize_entries(
+        &mut json,
+        &JsonPath::new(),
+        "remote",
+        diagnostics,
+      )?
+      .into_iter()
+      .collect(),
+      workspace: deserialize_workspace(&mut json, diagnostics)?,
+      unknown_fields: Default::default(),
+    };
+    // whatever remains was not understood by this crate
+    json.remove("version");
+    content.unknown_fields = UnknownFields(json.into_iter().collect());
+    Ok(content)
   }
 
   pub fn is_empty(&self) -> bool {
@@ -589,6 +1267,15 @@ impl LockfileContent {
       && self.redirects.is_empty()
       && self.remote.is_empty()
       && self.workspace.is_empty()
+      && self.unknown_fields.is_empty()
+  }
+
+  /// Checks the content for internal inconsistencies that are accepted
+  /// when deserializing, such as specifiers that resolve to missing
+  /// packages, locked versions that don't satisfy their specifier, or
+  /// malformed integrity strings.
+  pub fn validate(&self) -> Vec<ValidationDiagnostic> {
+    validation::validate(self)
   }
 }
 
@@ -598,12 +1285,113 @@ pub struct NewLockfileOptions<'a> {
   pub overwrite: bool,
 }
 
+/// What to do when opening a lockfile that can't be used as is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LockfileOpenFallback {
+  /// Return an error.
+  Error,
+  /// Use an empty lockfile, which replaces the file once saved.
+  Empty,
+}
+
+pub struct OpenLockfileOptions {
+  pub file_path: PathBuf,
+  pub overwrite: bool,
+  /// What to do when the file doesn't exist.
+  pub if_missing: LockfileOpenFallback,
+  /// What to do when the file is empty.
+  pub if_empty: LockfileOpenFallback,
+  /// What to do when the file is for an unsupported lockfile version.
+  pub if_unsupported: LockfileOpenFallback,
+}
+
+/// Entries removed from the remote and redirects sections.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct RemovedRemotes {
+  /// Removed urls and their checksums.
+  pub remote: BTreeMap<String, String>,
+  /// Removed redirects.
+  pub redirects: BTreeMap<String, String>,
+}
+
+impl RemovedRemotes {
+  pub fn is_empty(&self) -> bool {
+    self.remote.is_empty() && self.redirects.is_empty()
+  }
+}
+
+/// Entries removed from the specifiers, jsr, and npm sections.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct RemovedPackages {
+  /// Removed specifiers and the versions they resolved to.
+  pub specifiers: HashMap<JsrDepPackageReq, SmallStackString>,
+  pub jsr: BTreeMap<PackageNv, JsrPackageInfo>,
+  pub npm: BTreeMap<StackString, NpmPackageInfo>,
+}
+
+impl RemovedPackages {
+  pub fn is_empty(&self) -> bool {
+    self.specifiers.is_empty() && self.jsr.is_empty() && self.npm.is_empty()
+  }
+}
+
+/// Entries that pruning removed or changed, collected while removing them
+/// so they don't need to be found by comparing the whole lockfile.
+#[derive(Debug, Default)]
+pub(crate) struct PackageChanges {
+  pub removed: RemovedPackages,
+  /// Dependencies removed from the jsr packages that were kept.
+  pub removed_jsr_deps: BTreeMap<PackageNv, Vec<JsrDepPackageReq>>,
+}
+
+impl PackageChanges {
+  pub fn is_empty(&self) -> bool {
+    self.removed.is_empty() && self.removed_jsr_deps.is_empty()
+  }
+}
+
+/// Outcome of [`Lockfile::save`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LockfileSaveOutcome {
+  /// There were no changes to write.
+  Unchanged,
+  /// The lockfile was written.
+  Written,
+  /// The lockfile was modified on disk since it was loaded, so the
+  /// in-memory changes were merged into it before writing.
+  Merged {
+    conflicts: Vec<LockfileMergeConflict>,
+  },
+}
+
+/// Outcome of [`Lockfile::reload`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LockfileReloadOutcome {
+  /// The lockfile had not changed on disk.
+  Unchanged,
+  /// The lockfile was reloaded with the in-memory changes merged in.
+  Reloaded {
+    conflicts: Vec<LockfileMergeConflict>,
+  },
+}
+
+/// State of the lockfile file when it was last loaded or saved.
+#[derive(Debug, Clone)]
+struct DiskState {
+  /// Fingerprint of the file's bytes or `None` when it didn't exist.
+  fingerprint: Option<u64>,
+  content: LockfileContent,
+}
+
 #[derive(Debug, Clone)]
 pub struct Lockfile {
   pub overwrite: bool,
   pub has_content_changed: bool,
   pub content: LockfileContent,
   pub filename: PathBuf,
+  disk_state: Option<Box<DiskState>>,
+  journal: Option<Vec<LockfileMutation>>,
+  dependency_index: Option<Box<DependencyIndex>>,
 }
 
 impl Lockfile {
@@ -613,20 +1401,51 @@ impl Lockfile {
       has_content_changed: false,
       content: LockfileContent::default(),
       filename,
+      disk_state: None,
+      journal: None,
+      dependency_index: None,
     }
   }
 
   pub async fn new(
     opts: NewLockfileOptions<'_>,
     provider: &dyn NpmPackageInfoProvider,
+  ) -> Result<Lockfile, Box<LockfileError>> {
+    Self::new_inner(opts, provider, None).await
+  }
+
+  /// Creates a lockfile like [`Lockfile::new`], but skips over any
+  /// individually invalid specifiers, packages, and dependencies rather
+  /// than failing.
+  ///
+  /// An error is returned for each skipped entry. When there are any,
+  /// the lockfile is marked as changed so that it gets rewritten without
+  /// the invalid entries.
+  pub async fn new_lenient(
+    opts: NewLockfileOptions<'_>,
+    provider: &dyn NpmPackageInfoProvider,
+  ) -> Result<(Lockfile, Vec<DeserializationDiagnostic>), Box<LockfileError>>
+  {
+    let mut errors = Vec::new();
+    let mut lockfile =
+      Self::new_inner(opts, provider, Some(&mut errors)).await?;
+    lockfile.has_content_changed = !errors.is_empty();
+    Ok((lockfile, errors))
+  }
+
+  async fn new_inner(
+    opts: NewLockfileOptions<'_>,
+    provider: &dyn NpmPackageInfoProvider,
+    lenient_errors: Option<&mut Vec<DeserializationDiagnostic>>,
   ) -> Result<Lockfile, Box<LockfileError>> {
     async fn load_content(
       content: &str,
       provider: &dyn NpmPackageInfoProvider,
-    ) -> Result<LockfileContent, LockfileErrorReason> {
+      lenient_errors: Option<&mut Vec<DeserializationDiagnostic>>,
+    ) -> Result<LockfileContent, (LockfileErrorReason, Option<JsonPath>)> {
       let value: serde_json::Map<String, serde_json::Value> =
         serde_json::from_str(content)
-          .map_err(LockfileErrorReason::ParseError)?;
+          .map_err(|err| (LockfileErrorReason::ParseError(err), None))?;
       let version = value.get("version").and_then(|v| v.as_str());
       // When the value is transformed, we don't consider that a lockfile
       // change that should update the lockfile because we want to reduce
@@ -634,44 +1453,99 @@ impl Lockfile {
       // Deno does a PR to a repo that has an old lockfile, but they
       // don't update any dependencies. In that case, we don't want to
       // have that PR include a lockfile change.
+      let transform_error = |err: TransformError| {
+        let path = err.json_path().cloned();
+        (LockfileErrorReason::TransformError(err), path)
+      };
       let value = match version {
         Some("5") => value,
-        Some("4") => transforms::transform4_to_5(value, provider).await?,
-        Some("3") => {
-          transforms::transform4_to_5(
-            transforms::transform3_to_4(value)?,
-            provider,
-          )
-          .await?
-        }
-        Some("2") => {
-          transforms::transform4_to_5(
-            transforms::transform3_to_4(transforms::transform2_to_3(value))?,
-            provider,
-          )
-          .await?
-        }
-        None => {
-          transforms::transform4_to_5(
-            transforms::transform3_to_4(transforms::transform2_to_3(
-              transforms::transform1_to_2(value),
-            ))?,
-            provider,
-          )
-          .await?
-        }
+        Some("4") => transforms::transform4_to_5(value, provider)
+          .await
+          .map_err(transform_error)?,
+        Some("3") => transforms::transform4_to_5(
+          transforms::transform3_to_4(value).map_err(transform_error)?,
+          provider,
+        )
+        .await
+        .map_err(transform_error)?,
+        Some("2") => transforms::transform4_to_5(
+          transforms::transform3_to_4(transforms::transform2_to_3(value))
+            .map_err(transform_error)?,
+          provider,
+        )
+        .await
+        .map_err(transform_error)?,
+        None => transforms::transform4_to_5(
+          transforms::transform3_to_4(transforms::transform2_to_3(
+            transforms::transform1_to_2(value),
+          ))
+          .map_err(transform_error)?,
+          provider,
+        )
+        .await
+        .map_err(transform_error)?,
         Some(version) => {
-          return Err(LockfileErrorReason::UnsupportedVersion {
-            version: version.to_string(),
-          });
+          return Err((
+            LockfileErrorReason::UnsupportedVersion {
+              version: version.to_string(),
+            },
+            None,
+          ));
+        }
+      };
+      let value = value.into();
+      let content = match lenient_errors {
+        Some(errors) => {
+          let Ok(content) = LockfileContent::from_json_inner(value, errors);
+          content
         }
+        None => LockfileContent::from_json_inner(value, &mut FailFast)
+          .map_err(|diagnostic| {
+            (
+              LockfileErrorReason::DeserializationError(*diagnostic.error),
+              Some(diagnostic.path),
+            )
+          })?,
       };
-      let content = LockfileContent::from_json(value.into())
-        .map_err(LockfileErrorReason::DeserializationError)?;
 
       Ok(content)
     }
 
+    fn error_position(
+      text: &str,
+      reason: &LockfileErrorReason,
+      path: Option<&JsonPath>,
+    ) -> Option<SourcePosition> {
+      if let LockfileErrorReason::ParseError(err) = reason {
+        return Some(SourcePosition {
+          line: err.line(),
+          column: err.column(),
+        });
+      }
+      let path = path?;
+      let json = serde_json::from_str::<serde_json::Value>(text).ok()?;
+      let version = json.get("version").and_then(|v| v.as_str());
+      // only look up the path in the lockfile version it's for, otherwise
+      // it might point at the wrong value
+      let path = match (reason, version) {
+        (LockfileErrorReason::TransformError(_), Some("3")) => path.clone(),
+        (LockfileErrorReason::TransformError(_), _) => return None,
+        (_, Some("5")) => path.clone(),
+        // upgrading from version 4 only rearranges the dependencies
+        // within npm packages, so point at the package instead
+        (_, Some("4")) => match path.segments() {
+          [JsonPathSegment::Key(npm), JsonPathSegment::Key(id), ..]
+            if npm == "npm" =>
+          {
+            JsonPath::from_key("npm").join_key(id.as_str())
+          }
+          _ => path.clone(),
+        },
+        _ => return None,
+      };
+      json_path::find_position(text, &path)
+    }
+
     // Writing a lock file always uses the new format.
     if opts.overwrite {
       return Ok(Lockfile {
@@ -679,121 +1553,374 @@ impl Lockfile {
         filename: opts.file_path,
         has_content_changed: false,
         content: LockfileContent::default(),
+        disk_state: None,
+        journal: None,
+        dependency_index: None,
       });
     }
 
     if opts.content.trim().is_empty() {
-      return Err(Box::new(LockfileError {
-        file_path: opts.file_path.display().to_string(),
-        source: LockfileErrorReason::Empty,
-      }));
+      return Err(Box::new(LockfileError::new(
+        opts.file_path.display().to_string(),
+        LockfileErrorReason::Empty,
+      )));
     }
-    let content =
-      load_content(opts.content, provider)
-        .await
-        .map_err(|reason| LockfileError {
-          file_path: opts.file_path.display().to_string(),
-          source: reason,
-        })?;
+    let content = load_content(opts.content, provider, lenient_errors)
+      .await
+      .map_err(|(reason, json_path)| LockfileError {
+        file_path: opts.file_path.display().to_string(),
+        position: error_position(opts.content, &reason, json_path.as_ref()),
+        json_path,
+        source: reason,
+      })?;
     Ok(Lockfile {
       overwrite: opts.overwrite,
       has_content_changed: false,
       content,
       filename: opts.file_path,
+      disk_state: None,
+      journal: None,
+      dependency_index: None,
     })
   }
 
-  pub fn as_json_string(&self) -> String {
-    let mut text = printer::print_v5_content(&self.content);
-    text.reserve(1);
-    text.push('\n');
-    text
+  /// Reads and creates the lockfile at the provided path, or creates an
+  /// empty lockfile when the file doesn't exist.
+  ///
+  /// The loaded content is remembered so that [`Lockfile::save`] can
+  /// merge with changes made on disk by other processes.
+  pub async fn load_from_path(
+    file_path: PathBuf,
+    overwrite: bool,
+    provider: &dyn NpmPackageInfoProvider,
+  ) -> Result<Lockfile, Box<LockfileError>> {
+    Self::open(
+      OpenLockfileOptions {
+        file_path,
+        overwrite,
+        if_missing: LockfileOpenFallback::Empty,
+        if_empty: LockfileOpenFallback::Error,
+        if_unsupported: LockfileOpenFallback::Error,
+      },
+      &RealLockfileFs,
+      provider,
+    )
+    .await
+  }
+
+  /// Reads and creates the lockfile at the provided path using the
+  /// provided file system, falling back to an empty lockfile as specified
+  /// in the options.
+  ///
+  /// The loaded content is remembered so that [`Lockfile::save_with_fs`]
+  /// can merge with changes made on disk by other processes.
+  pub async fn open(
+    opts: OpenLockfileOptions,
+    fs: &dyn LockfileFs,
+    provider: &dyn NpmPackageInfoProvider,
+  ) -> Result<Lockfile, Box<LockfileError>> {
+    let error = |source| {
+      Box::new(LockfileError::new(
+        opts.file_path.display().to_string(),
+        source,
+      ))
+    };
+    let text = fs
+      .read_file(&opts.file_path)
+      .map_err(|err| error(LockfileErrorReason::Io(err)))?;
+    let mut lockfile = match &text {
+      None => match opts.if_missing {
+        LockfileOpenFallback::Error => {
+          return Err(error(LockfileErrorReason::NotFound));
+        }
+        LockfileOpenFallback::Empty => {
+          Lockfile::new_empty(opts.file_path, opts.overwrite)
+        }
+      },
+      Some(text) if text.trim().is_empty() && !opts.overwrite => {
+        match opts.if_empty {
+          LockfileOpenFallback::Error => {
+            return Err(error(LockfileErrorReason::Empty));
+          }
+          LockfileOpenFallback::Empty => {
+            Lockfile::new_empty(opts.file_path, opts.overwrite)
+          }
+        }
+      }
+      Some(text) => {
+        let result = Lockfile::new(
+          NewLockfileOptions {
+            file_path: opts.file_path.clone(),
+            content: text,
+            overwrite: opts.overwrite,
+          },
+          provider,
+        )
+        .await;
+        match result {
+          Err(err)
+            if matches!(
+              err.source,
+              LockfileErrorReason::UnsupportedVersion { .. }
+            ) && opts.if_unsupported == LockfileOpenFallback::Empty =>
+          {
+            Lockfile::new_empty(opts.file_path, opts.overwrite)
+          }
+          result => result?,
+        }
+      }
+    };
+    lockfile.disk_state = Some(Box::new(DiskState {
+      fingerprint: text.map(|text| fs::fingerprint(text.as_bytes())),
+      content: lockfile.content.clone(),
+    }));
+    Ok(lockfile)
   }
 
-  pub fn set_workspace_config(
+  /// Writes the lockfile to disk if it has changed.
+  ///
+  /// This holds an advisory lock while saving so that concurrent processes
+  /// don't interleave, and writes atomically by renaming a temporary file
+  /// over the lockfile. When the lockfile was modified on disk since it
+  /// was loaded or last saved, it's re-read and the in-memory changes are
+  /// merged into it rather than overwriting it. A merge is not done in
+  /// overwrite mode or when the lockfile wasn't loaded from disk.
+  pub async fn save(
     &mut self,
-    mut options: SetWorkspaceConfigOptions,
-  ) {
-    fn update_workspace_member(
-      has_content_changed: &mut bool,
-      removed_deps: &mut HashSet<JsrDepPackageReq>,
-      current: &mut WorkspaceMemberConfigContent,
-      new: WorkspaceMemberConfig,
-    ) {
-      if new.dependencies != current.dependencies {
-        let old_deps =
-          std::mem::replace(&mut current.dependencies, new.dependencies);
+    provider: &dyn NpmPackageInfoProvider,
+  ) -> Result<LockfileSaveOutcome, LockfileSaveError> {
+    self.save_with_fs(&RealLockfileFs, provider).await
+  }
 
-        removed_deps.extend(old_deps);
+  /// Writes the lockfile like [`Lockfile::save`] using the provided
+  /// file system.
+  pub async fn save_with_fs(
+    &mut self,
+    fs: &dyn LockfileFs,
+    provider: &dyn NpmPackageInfoProvider,
+  ) -> Result<LockfileSaveOutcome, LockfileSaveError> {
+    if !self.has_content_changed && !self.overwrite {
+      return Ok(LockfileSaveOutcome::Unchanged);
+    }
+    let file_path = self.filename.display().to_string();
+    let io_err = |source| LockfileSaveError::Io {
+      file_path: file_path.clone(),
+      source,
+    };
 
-        *has_content_changed = true;
+    let _lock = fs.lock_file(&self.filename).map_err(io_err)?;
+    let mut conflicts = None;
+    if let Some(disk_state) = &self.disk_state
+      && !self.overwrite
+    {
+      let text = fs.read_file(&self.filename).map_err(io_err)?;
+      let fingerprint = text.as_ref().map(|t| fs::fingerprint(t.as_bytes()));
+      if let Some(text) = text
+        && fingerprint != disk_state.fingerprint
+      {
+        let theirs = self
+          .load_disk_content(&text, provider)
+          .await
+          .map_err(LockfileSaveError::Load)?;
+        conflicts = Some(self.merge_into(theirs));
       }
+    }
 
-      if new.package_json_deps != current.package_json.dependencies {
-        // update self.content.package_json
-        let old_package_json_deps = std::mem::replace(
-          &mut current.package_json.dependencies,
-          new.package_json_deps,
-        );
+    let text = self.as_json_string();
+    fs::atomic_write(fs, &self.filename, text.as_bytes()).map_err(io_err)?;
+    self.has_content_changed = false;
+    self.disk_state = Some(Box::new(DiskState {
+      fingerprint: Some(fs::fingerprint(text.as_bytes())),
+      content: self.content.clone(),
+    }));
+    Ok(match conflicts {
+      Some(conflicts) => LockfileSaveOutcome::Merged { conflicts },
+      None => LockfileSaveOutcome::Written,
+    })
+  }
 
-        removed_deps.extend(old_package_json_deps);
+  /// Fingerprint of the lockfile's bytes when it was last loaded from or
+  /// saved to disk.
+  ///
+  /// This is `None` when the lockfile wasn't opened from disk or when the
+  /// file didn't exist.
+  pub fn disk_fingerprint(&self) -> Option<u64> {
+    self.disk_state.as_ref().and_then(|s| s.fingerprint)
+  }
 
-        *has_content_changed = true;
-      }
-    }
+  /// Gets if the lockfile on disk is different from when it was last
+  /// loaded or saved.
+  ///
+  /// Always `false` when the lockfile wasn't opened from disk.
+  pub fn has_changed_on_disk(
+    &self,
+    fs: &dyn LockfileFs,
+  ) -> std::io::Result<bool> {
+    let Some(disk_state) = &self.disk_state else {
+      return Ok(false);
+    };
+    let text = fs.read_file(&self.filename)?;
+    let fingerprint = text.map(|text| fs::fingerprint(text.as_bytes()));
+    Ok(fingerprint != disk_state.fingerprint)
+  }
 
-    // if specified, don't modify the package.json dependencies
-    if options.no_npm || options.no_config {
-      if options.config.root.package_json_deps.is_empty() {
-        options
-          .config
-          .root
-          .package_json_deps
-          .clone_from(&self.content.workspace.root.package_json.dependencies);
-      }
-      for (key, value) in options.config.members.iter_mut() {
-        if value.package_json_deps.is_empty() {
-          value.package_json_deps = self
-            .content
-            .workspace
-            .members
-            .get(key)
-            .map(|m| m.package_json.dependencies.clone())
-            .unwrap_or_default();
-        }
-      }
+  /// Reloads the lockfile from disk when it changed since it was last
+  /// loaded or saved, carrying over any in-memory changes that weren't
+  /// saved yet.
+  ///
+  /// A lockfile that was deleted on disk is reloaded as empty. Does
+  /// nothing when the lockfile wasn't opened from disk.
+  pub async fn reload(
+    &mut self,
+    fs: &dyn LockfileFs,
+    provider: &dyn NpmPackageInfoProvider,
+  ) -> Result<LockfileReloadOutcome, Box<LockfileError>> {
+    let Some(disk_state) = &self.disk_state else {
+      return Ok(LockfileReloadOutcome::Unchanged);
+    };
+    let text = fs.read_file(&self.filename).map_err(|err| {
+      Box::new(LockfileError::new(
+        self.filename.display().to_string(),
+        LockfileErrorReason::Io(err),
+      ))
+    })?;
+    let fingerprint = text.as_ref().map(|t| fs::fingerprint(t.as_bytes()));
+    if fingerprint == disk_state.fingerprint {
+      return Ok(LockfileReloadOutcome::Unchanged);
     }
-    if options.no_config {
-      if options.config.root.dependencies.is_empty() {
-        options
-          .config
-          .root
-          .dependencies
-          .clone_from(&self.content.workspace.root.dependencies);
-      }
-      for (key, value) in options.config.members.iter_mut() {
-        if value.dependencies.is_empty() {
-          value.dependencies = self
-            .content
-            .workspace
-            .members
-            .get(key)
-            .map(|m| m.dependencies.clone())
-            .unwrap_or_default();
+    let theirs = match &text {
+      Some(text) => self.load_disk_content(text, provider).await?,
+      None => LockfileContent::default(),
+    };
+    let conflicts = self.merge_into(theirs);
+    let disk_state = self.disk_state.as_mut().unwrap();
+    disk_state.fingerprint = fingerprint;
+    // the unsaved changes may have also been made on disk
+    self.has_content_changed = printer::print_v5_content(&self.content)
+      != printer::print_v5_content(&disk_state.content);
+    Ok(LockfileReloadOutcome::Reloaded { conflicts })
+  }
+
+  async fn load_disk_content(
+    &self,
+    text: &str,
+    provider: &dyn NpmPackageInfoProvider,
+  ) -> Result<LockfileContent, Box<LockfileError>> {
+    let lockfile = Lockfile::new(
+      NewLockfileOptions {
+        file_path: self.filename.clone(),
+        content: text,
+        overwrite: false,
+      },
+      provider,
+    )
+    .await?;
+    Ok(lockfile.content)
+  }
+
+  /// Merges the in-memory changes into the content read from disk and
+  /// makes that the new base for future merges.
+  fn merge_into(
+    &mut self,
+    theirs: LockfileContent,
+  ) -> Vec<LockfileMergeConflict> {
+    let disk_state = self.disk_state.as_mut().unwrap();
+    let (content, conflicts) =
+      merge::merge_content(&disk_state.content, &self.content, theirs.clone());
+    disk_state.content = theirs;
+    if self.journal.is_some() || self.dependency_index.is_some() {
+      let mut mutations = Vec::new();
+      journal::diff(&self.content, &content, &mut mutations);
+      if let Some(index) = &mut self.dependency_index {
+        for mutation in &mutations {
+          index.apply(mutation, false);
         }
       }
-      for (key, value) in self.content.workspace.members.iter() {
-        if !options.config.members.contains_key(key) {
-          options.config.members.insert(
-            key.clone(),
-            WorkspaceMemberConfig {
-              dependencies: value.dependencies.clone(),
-              package_json_deps: value.package_json.dependencies.clone(),
-            },
-          );
-        }
+      if let Some(journal) = &mut self.journal {
+        journal.extend(mutations);
       }
     }
+    self.content = content;
+    conflicts
+  }
+
+  pub fn as_json_string(&self) -> String {
+    let mut text = printer::print_v5_content(&self.content);
+    text.reserve(1);
+    text.push('\n');
+    text
+  }
+
+  /// Gets how the workspace config differs from the one in the lockfile,
+  /// which is empty when [`Lockfile::set_workspace_config`] wouldn't
+  /// change anything.
+  pub fn workspace_config_diff(
+    &self,
+    options: &SetWorkspaceConfigOptions,
+  ) -> WorkspaceConfigDiff {
+    workspace_report::diff_options(&self.content.workspace, options)
+  }
+
+  /// Gets the changes that [`Lockfile::set_workspace_config`] would make
+  /// without changing the lockfile.
+  ///
+  /// Only the workspace config is copied, along with the packages when
+  /// some of them would be removed.
+  pub fn preview_workspace_config(
+    &self,
+    options: SetWorkspaceConfigOptions,
+  ) -> WorkspaceConfigReport {
+    let mut report = WorkspaceConfigReport::default();
+    let mut removed_deps = self.overridden_deps(&options.config.overrides);
+    let mut workspace = self.content.workspace.clone();
+    let update = workspace.apply(&self.content.packages, options);
+    removed_deps.extend(update.removed_deps);
+    if !removed_deps.is_empty() || !update.changed_links.is_empty() {
+      let mut packages = self.content.packages.clone();
+      report.removed_packages =
+        prune_with_graph(&mut packages, removed_deps, update.changed_links)
+          .removed;
+    }
+    report.renamed_members = update.renamed_members;
+    workspace_report::diff_config(
+      &self.content.workspace.to_config(),
+      &workspace.to_config(),
+      &mut report,
+    );
+    report
+  }
+
+  /// Updates the workspace dependencies and links, removing the packages
+  /// that are no longer used because of it.
+  ///
+  /// Use [`Lockfile::set_workspace_config_with_report`] to also get what
+  /// changed.
+  pub fn set_workspace_config(&mut self, options: SetWorkspaceConfigOptions) {
+    self.set_workspace_config_inner(options, None);
+  }
+
+  /// Updates the workspace config like [`Lockfile::set_workspace_config`]
+  /// and reports the changes that were made.
+  pub fn set_workspace_config_with_report(
+    &mut self,
+    options: SetWorkspaceConfigOptions,
+  ) -> WorkspaceConfigReport {
+    let mut report = WorkspaceConfigReport::default();
+    self.set_workspace_config_inner(options, Some(&mut report));
+    report
+  }
+
+  fn set_workspace_config_inner(
+    &mut self,
+    options: SetWorkspaceConfigOptions,
+    report: Option<&mut WorkspaceConfigReport>,
+  ) {
+    let old_workspace = self.journal.is_some().then(|| {
+      // only the workspace is copied as the rest is recorded while pruning
+      self.content.workspace.clone()
+    });
+    let old_workspace_config =
+      report.is_some().then(|| self.content.workspace.to_config());
 
     // If the lockfile is empty, it's most likely not created yet and so
     // we don't want this information being added to the lockfile to cause
@@ -802,168 +1929,198 @@ impl Lockfile {
     let allow_content_changed =
       self.has_content_changed || !self.content.is_empty();
 
-    let has_any_patch_changed =
-      options.config.links != self.content.workspace.links;
+    let mut removed_deps = self.overridden_deps(&options.config.overrides);
+    let update = self
+      .content
+      .workspace
+      .apply(&self.content.packages, options);
+    if update.has_changed {
+      self.has_content_changed = true;
+    }
+    removed_deps.extend(update.removed_deps);
+    let changed_links = update.changed_links;
 
-    let mut removed_deps = HashSet::new();
-    let mut changed_links = HashSet::new();
-    if has_any_patch_changed {
-      self.has_content_changed = true;
-      let mut unhandled_links = self
-        .content
-        .workspace
-        .links
-        .keys()
-        .cloned()
-        .collect::<HashSet<_>>();
-      changed_links.reserve(options.config.links.len());
-      for (link_name, new) in options.config.links {
-        if !unhandled_links.remove(&link_name) {
-          if let Ok(dep_req) = JsrDepPackageReq::from_str(&link_name) {
-            let had_change = (|| match dep_req.kind {
-              PackageKind::Jsr => {
-                for (key, package) in &self.content.packages.jsr {
-                  if key.name != dep_req.req.name {
-                    continue;
-                  }
-                  if !dep_req.req.version_req.matches(&key.version)
-                    || !package.matches_link(&new)
-                  {
-                    return true;
-                  }
-                }
-                false
-              }
-              PackageKind::Npm => {
-                for (key, package) in &self.content.packages.npm {
-                  let Some(key) = key.strip_prefix(dep_req.req.name.as_str())
-                  else {
-                    continue;
-                  };
-                  let Some(key) = key.strip_prefix('@') else {
-                    continue;
-                  };
-                  let version =
-                    key.split_once('_').map(|(l, _)| l).unwrap_or(key);
-                  let Ok(version) = Version::parse_from_npm(version) else {
-                    continue;
-                  };
-                  if !dep_req.req.version_req.matches(&version)
-                    || !package.matches_link(&new)
-                  {
-                    return true;
-                  }
-                }
-                false
-              }
-            })();
-
-            if had_change {
-              changed_links.insert(dep_req);
-            }
-          }
-          self.content.workspace.links.insert(link_name.clone(), new);
-        } else {
-          let current = self
-            .content
-            .workspace
-            .links
-            .entry(link_name.clone())
-            .or_default();
-          if new != *current {
-            *current = new;
-            if let Ok(dep_req) = JsrDepPackageReq::from_str(&link_name) {
-              changed_links.insert(dep_req);
-            }
-          }
-        }
-      }
-
-      for member in unhandled_links {
-        if let Some(member) = self.content.workspace.links.remove(&member) {
-          removed_deps.extend(member.dep_reqs().cloned());
-        }
-      }
+    let mut changes = PackageChanges::default();
+    if let Some(index) = &mut self.dependency_index
+      && (!removed_deps.is_empty() || !changed_links.is_empty())
+    {
+      changes = index.prune(&mut self.content, removed_deps, changed_links);
+    } else if !removed_deps.is_empty() || !changed_links.is_empty() {
+      changes = prune_with_graph(
+        &mut self.content.packages,
+        removed_deps,
+        changed_links,
+      );
     }
 
-    // set the root
-    update_workspace_member(
-      &mut self.has_content_changed,
-      &mut removed_deps,
-      &mut self.content.workspace.root,
-      options.config.root,
-    );
-
-    // now go through the workspaces
-    let mut unhandled_members = self
-      .content
-      .workspace
-      .members
-      .keys()
-      .cloned()
-      .collect::<HashSet<_>>();
-    for (member_name, new_member) in options.config.members {
-      unhandled_members.remove(&member_name);
-      let current_member = self
-        .content
-        .workspace
-        .members
-        .entry(member_name)
-        .or_default();
-      update_workspace_member(
-        &mut self.has_content_changed,
-        &mut removed_deps,
-        current_member,
-        new_member,
+    if !allow_content_changed {
+      // revert it back so this change doesn't by itself cause
+      // a lockfile to be created.
+      self.has_content_changed = false;
+    }
+    self.record_package_changes(&changes);
+    if let Some(old) = old_workspace
+      && old != self.content.workspace
+    {
+      let new = self.content.workspace.to_config();
+      self.record(|| LockfileMutation::Workspace {
+        old: Box::new(old.to_config()),
+        new: Box::new(new),
+      });
+    }
+    if let (Some(report), Some(old_workspace_config)) =
+      (report, old_workspace_config)
+    {
+      report.removed_packages = changes.removed;
+      report.renamed_members = update.renamed_members;
+      workspace_report::diff_config(
+        &old_workspace_config,
+        &self.content.workspace.to_config(),
+        report,
       );
     }
+  }
 
-    for member in unhandled_members {
-      if let Some(member) = self.content.workspace.members.remove(&member) {
-        removed_deps.extend(member.dep_reqs().cloned());
-        self.has_content_changed = true;
-      }
+  /// Gets the specifiers that need to be resolved again for the change to
+  /// the override rules to take effect.
+  fn overridden_deps(
+    &self,
+    overrides: &HashMap<String, String>,
+  ) -> HashSet<JsrDepPackageReq> {
+    if *overrides == self.content.workspace.overrides {
+      return HashSet::new();
     }
+    overrides::affected_specifiers(
+      &self.content,
+      &self.content.workspace.overrides,
+      overrides,
+    )
+  }
 
-    // update the removed deps to keep what's still found in the workspace
-    for dep in self.content.workspace.get_all_dep_reqs() {
-      removed_deps.remove(dep);
-    }
+  /// Starts recording every change made to the content, which can be
+  /// retrieved with [`Lockfile::take_journal`].
+  pub fn enable_journal(&mut self) {
+    self.journal.get_or_insert_with(Vec::new);
+  }
 
-    if !removed_deps.is_empty() || !changed_links.is_empty() {
-      let packages = std::mem::take(&mut self.content.packages);
-      let remotes = std::mem::take(&mut self.content.remote);
+  /// Changes recorded since the journal was enabled or last taken.
+  pub fn journal(&self) -> &[LockfileMutation] {
+    self.journal.as_deref().unwrap_or_default()
+  }
 
-      // create the graph
-      let mut graph = LockfilePackageGraph::from_lockfile(packages, remotes);
+  /// Takes the recorded changes while continuing to record.
+  pub fn take_journal(&mut self) -> Vec<LockfileMutation> {
+    self
+      .journal
+      .as_mut()
+      .map(std::mem::take)
+      .unwrap_or_default()
+  }
 
-      // remove the packages
-      graph.remove_root_packages(removed_deps.into_iter());
+  fn record(&mut self, mutation: impl FnOnce() -> LockfileMutation) {
+    if let Some(journal) = &mut self.journal {
+      journal.push(mutation());
+    }
+  }
 
-      // remove the changed links
-      graph.remove_links(changed_links.into_iter());
+  /// Records the entries that pruning removed or changed.
+  fn record_package_changes(&mut self, changes: &PackageChanges) {
+    let Some(journal) = &mut self.journal else {
+      return;
+    };
+    let mut specifiers = changes.removed.specifiers.iter().collect::<Vec<_>>();
+    specifiers.sort_by_cached_key(|(req, _)| req.to_string_normalized());
+    for (req, version) in specifiers {
+      journal.push(LockfileMutation::Specifier {
+        req: req.clone(),
+        old: Some(version.clone()),
+        new: None,
+      });
+    }
+    for (nv, package) in &changes.removed.jsr {
+      journal.push(LockfileMutation::JsrPackage {
+        nv: nv.clone(),
+        old: Some(package.clone()),
+        new: None,
+      });
+    }
+    for (id, package) in &changes.removed.npm {
+      journal.push(LockfileMutation::NpmPackage {
+        id: id.clone(),
+        old: Some(package.clone()),
+        new: None,
+      });
+    }
+    for (nv, deps) in &changes.removed_jsr_deps {
+      let Some(package) = self.content.packages.jsr.get(nv) else {
+        continue;
+      };
+      let mut old = package.clone();
+      old.dependencies.extend(deps.iter().cloned());
+      journal.push(LockfileMutation::JsrPackage {
+        nv: nv.clone(),
+        old: Some(old),
+        new: Some(package.clone()),
+      });
+    }
+  }
 
-      // now populate the graph back into the packages
-      graph.populate_packages(
-        &mut self.content.packages,
-        &mut self.content.remote,
-      );
+  fn record_removed_remotes(&mut self, removed: &RemovedRemotes) {
+    let Some(journal) = &mut self.journal else {
+      return;
+    };
+    for (from, to) in &removed.redirects {
+      journal.push(LockfileMutation::Redirect {
+        from: from.clone(),
+        old: Some(to.clone()),
+        new: None,
+      });
+    }
+    for (specifier, checksum) in &removed.remote {
+      journal.push(LockfileMutation::Remote {
+        specifier: specifier.clone(),
+        old: Some(checksum.clone()),
+        new: None,
+      });
     }
+  }
 
-    if !allow_content_changed {
-      // revert it back so this change doesn't by itself cause
-      // a lockfile to be created.
-      self.has_content_changed = false;
+  /// Keeps an index of the reverse dependencies of packages up to date as
+  /// packages are inserted, so that [`Lockfile::set_workspace_config`]
+  /// only visits the affected packages when removing the ones that are
+  /// no longer used rather than going over the whole lockfile.
+  ///
+  /// Call [`Lockfile::invalidate_dependency_index`] after changing the
+  /// `content` directly. Specifiers and jsr package dependencies that were
+  /// added or removed directly are noticed and the index is rebuilt, but
+  /// other direct changes, such as to a specifier's version, aren't. Debug
+  /// builds panic when an out of date index is used.
+  pub fn enable_dependency_index(&mut self) {
+    if self.dependency_index.is_none() {
+      self.dependency_index = Some(Box::new(DependencyIndex::new_stale()));
+    }
+  }
+
+  /// Rebuilds the dependency index the next time it's used.
+  pub fn invalidate_dependency_index(&mut self) {
+    if let Some(index) = &mut self.dependency_index {
+      index.invalidate();
     }
   }
 
+  /// Begins a batch of edits that are undone unless the transaction is
+  /// committed, such as when resolution fails part way through.
+  pub fn begin_transaction(&mut self) -> LockfileTransaction<'_> {
+    LockfileTransaction::new(self)
+  }
+
   /// Gets the bytes that should be written to the disk.
   ///
   /// Ideally when the caller should use an "atomic write"
   /// when writing this—write to a temporary file beside the
   /// lockfile, then rename to overwrite. This will make the
   /// lockfile more resilient when multiple processes are
-  /// writing to it.
+  /// writing to it. [`Lockfile::save`] does this for you.
   pub fn resolve_write_bytes(&mut self) -> Option<Vec<u8>> {
     if !self.has_content_changed && !self.overwrite {
       return None;
@@ -982,19 +2139,112 @@ impl Lockfile {
   /// WARNING: It is up to the caller to ensure checksums of remote modules are
   /// valid before it is inserted here.
   pub fn insert_remote(&mut self, specifier: String, hash: String) {
-    let entry = self.content.remote.entry(specifier);
-    match entry {
-      BTreeMapEntry::Vacant(entry) => {
-        entry.insert(hash);
-        self.has_content_changed = true;
+    if self.content.remote.get(&specifier) == Some(&hash) {
+      return;
+    }
+    self.has_content_changed = true;
+    match &mut self.journal {
+      Some(journal) => {
+        let old = self.content.remote.insert(specifier.clone(), hash.clone());
+        journal.push(LockfileMutation::Remote {
+          specifier,
+          old,
+          new: Some(hash),
+        });
       }
-      BTreeMapEntry::Occupied(mut entry) => {
-        if entry.get() != &hash {
-          entry.insert(hash);
-          self.has_content_changed = true;
-        }
+      None => {
+        self.content.remote.insert(specifier, hash);
+      }
+    }
+  }
+
+  /// Gets the remote modules that are in the lockfile at more than one
+  /// version based on the version in their urls, such as
+  /// `https://deno.land/x/oak@v12.1.0/mod.ts`.
+  pub fn duplicate_remote_versions(&self) -> Vec<DuplicateRemoteModule> {
+    remote_versions::find_duplicates(&self.content.remote)
+  }
+
+  /// Removes a remote specifier along with the redirects that lead to it.
+  pub fn remove_remote(&mut self, specifier: &str) -> RemovedRemotes {
+    self.remove_remotes_where(|url, _| url == specifier)
+  }
+
+  /// Removes the remote specifiers that start with the prefix along with
+  /// the redirects that lead to them.
+  pub fn remove_remotes_with_prefix(&mut self, prefix: &str) -> RemovedRemotes {
+    self.remove_remotes_where(|url, _| url.starts_with(prefix))
+  }
+
+  /// Removes the remote specifiers that match the predicate, which is
+  /// provided the url and checksum, along with the redirects that lead
+  /// to them.
+  pub fn remove_remotes_where(
+    &mut self,
+    mut predicate: impl FnMut(&str, &str) -> bool,
+  ) -> RemovedRemotes {
+    let mut removed = RemovedRemotes::default();
+    self.content.remote.retain(|url, checksum| {
+      let remove = predicate(url, checksum);
+      if remove {
+        removed.remote.insert(url.clone(), checksum.clone());
+      }
+      !remove
+    });
+    let dangling_redirects = self
+      .content
+      .redirects
+      .keys()
+      .filter(|from| {
+        redirects::final_url(&self.content.redirects, from)
+          .is_some_and(|url| removed.remote.contains_key(url))
+      })
+      .cloned()
+      .collect::<Vec<_>>();
+    for from in dangling_redirects {
+      let to = self.content.redirects.remove(&from).unwrap();
+      removed.redirects.insert(from, to);
+    }
+    if !removed.is_empty() {
+      self.has_content_changed = true;
+    }
+    self.record_removed_remotes(&removed);
+    removed
+  }
+
+  /// Removes the remote specifiers and redirects that aren't used.
+  ///
+  /// The used specifiers are the ones that were requested, such as the
+  /// remote specifiers in a module graph, and the urls they're
+  /// redirected to are kept as well.
+  pub fn retain_remotes(&mut self, used: &HashSet<String>) -> RemovedRemotes {
+    let reachable = used
+      .iter()
+      .flat_map(|specifier| {
+        redirects::visited_urls(&self.content.redirects, specifier)
+      })
+      .map(|url| url.to_string())
+      .collect::<HashSet<_>>();
+    let mut removed = RemovedRemotes::default();
+    self.content.remote.retain(|url, checksum| {
+      let keep = reachable.contains(url);
+      if !keep {
+        removed.remote.insert(url.clone(), checksum.clone());
+      }
+      keep
+    });
+    self.content.redirects.retain(|from, to| {
+      let keep = reachable.contains(from);
+      if !keep {
+        removed.redirects.insert(from.clone(), to.clone());
       }
+      keep
+    });
+    if !removed.is_empty() {
+      self.has_content_changed = true;
     }
+    self.record_removed_remotes(&removed);
+    removed
   }
 
   /// Inserts an npm package into the lockfile replacing the existing package if it exists.
@@ -1018,8 +2268,8 @@ impl Lockfile {
       .map(|dep| (dep.name, dep.id))
       .collect::<BTreeMap<StackString, StackString>>();
 
-    let entry = self.content.packages.npm.entry(package_info.serialized_id);
-    let package_info = NpmPackageInfo {
+    let id = package_info.serialized_id;
+    let mut package_info = NpmPackageInfo {
       integrity: package_info.integrity,
       dependencies,
       optional_dependencies,
@@ -1030,17 +2280,30 @@ impl Lockfile {
       deprecated: package_info.deprecated,
       scripts: package_info.scripts,
       bin: package_info.bin,
+      unknown_fields: Default::default(),
     };
-    match entry {
-      BTreeMapEntry::Vacant(entry) => {
-        entry.insert(package_info);
-        self.has_content_changed = true;
+    if let Some(existing) = self.content.packages.npm.get(&id) {
+      // the caller doesn't know about these, so keep them around
+      package_info
+        .unknown_fields
+        .clone_from(&existing.unknown_fields);
+      if *existing == package_info {
+        return;
       }
-      BTreeMapEntry::Occupied(mut entry) => {
-        if *entry.get() != package_info {
-          entry.insert(package_info);
-          self.has_content_changed = true;
-        }
+    }
+    self.has_content_changed = true;
+    let npm = &mut self.content.packages.npm;
+    match &mut self.journal {
+      Some(journal) => {
+        let old = npm.insert(id.clone(), package_info.clone());
+        journal.push(LockfileMutation::NpmPackage {
+          id,
+          old,
+          new: Some(package_info),
+        });
+      }
+      None => {
+        npm.insert(id, package_info);
       }
     }
   }
@@ -1051,17 +2314,30 @@ impl Lockfile {
     package_req: JsrDepPackageReq,
     serialized_package_id: SmallStackString,
   ) {
-    let entry = self.content.packages.specifiers.entry(package_req);
-    match entry {
-      HashMapEntry::Vacant(entry) => {
-        entry.insert(serialized_package_id);
-        self.has_content_changed = true;
+    let specifiers = &mut self.content.packages.specifiers;
+    if specifiers.get(&package_req) == Some(&serialized_package_id) {
+      return;
+    }
+    self.has_content_changed = true;
+    if let Some(index) = &mut self.dependency_index {
+      index.set_specifier(
+        &package_req,
+        specifiers.get(&package_req).map(|old| old.as_str()),
+        Some(&serialized_package_id),
+      );
+    }
+    match &mut self.journal {
+      Some(journal) => {
+        let old =
+          specifiers.insert(package_req.clone(), serialized_package_id.clone());
+        journal.push(LockfileMutation::Specifier {
+          req: package_req,
+          old,
+          new: Some(serialized_package_id),
+        });
       }
-      HashMapEntry::Occupied(mut entry) => {
-        if *entry.get() != serialized_package_id {
-          entry.insert(serialized_package_id);
-          self.has_content_changed = true;
-        }
+      None => {
+        specifiers.insert(package_req, serialized_package_id);
       }
     }
   }
@@ -1072,21 +2348,33 @@ impl Lockfile {
   /// WARNING: It is up to the caller to ensure checksums of packages are
   /// valid before it is inserted here.
   pub fn insert_package(&mut self, name: PackageNv, integrity: String) {
-    let entry = self.content.packages.jsr.entry(name);
-    match entry {
+    let recording = self.journal.is_some();
+    let entry = self.content.packages.jsr.entry(name.clone());
+    let (old, new) = match entry {
       BTreeMapEntry::Vacant(entry) => {
-        entry.insert(JsrPackageInfo {
+        let new = entry.insert(JsrPackageInfo {
           integrity,
           dependencies: Default::default(),
+          unknown_fields: Default::default(),
         });
-        self.has_content_changed = true;
+        (None, recording.then(|| new.clone()))
       }
       BTreeMapEntry::Occupied(mut entry) => {
-        if *entry.get().integrity != integrity {
-          entry.get_mut().integrity = integrity;
-          self.has_content_changed = true;
+        if *entry.get().integrity == integrity {
+          return;
         }
+        let old = recording.then(|| entry.get().clone());
+        entry.get_mut().integrity = integrity;
+        (old, recording.then(|| entry.get().clone()))
       }
+    };
+    self.has_content_changed = true;
+    if let Some(new) = new {
+      self.record(|| LockfileMutation::JsrPackage {
+        nv: name,
+        old,
+        new: Some(new),
+      });
     }
   }
 
@@ -1101,6 +2389,7 @@ impl Lockfile {
     deps: impl Iterator<Item = JsrDepPackageReq>,
   ) {
     if let Some(pkg) = self.content.packages.jsr.get_mut(nv) {
+      let old = self.journal.as_ref().map(|_| pkg.clone());
       let start_count = pkg.dependencies.len();
       // don't include unresolved dependendencies
       let resolved_deps =
@@ -1109,8 +2398,180 @@ impl Lockfile {
       let end_count = pkg.dependencies.len();
       if start_count != end_count {
         self.has_content_changed = true;
+        if let Some(index) = &mut self.dependency_index {
+          index.add_jsr_deps(nv, pkg.dependencies.iter());
+        }
+        if let (Some(old), Some(journal)) = (old, &mut self.journal) {
+          journal.push(LockfileMutation::JsrPackage {
+            nv: nv.clone(),
+            old: Some(old),
+            new: Some(pkg.clone()),
+          });
+        }
+      }
+    }
+  }
+
+  /// Plans changing the workspace dependencies on the same packages as
+  /// the requirements to those requirements, such as from `npm:react@^18`
+  /// to `npm:react@^19`, without resolving anything.
+  ///
+  /// The plan says which requirements are satisfied by already locked
+  /// versions, which workspace members are affected, and which packages
+  /// would no longer be used.
+  pub fn plan_upgrade(&self, reqs: &[JsrDepPackageReq]) -> UpgradePlan {
+    upgrade_plan::plan(&self.content, reqs)
+  }
+
+  /// Compares the locked versions of the specifiers and packages to the
+  /// versions from the provider, such as a snapshot of the registries.
+  ///
+  /// Packages whose versions fail to load are reported as unknown and
+  /// their errors are returned along with the report.
+  pub fn outdated(
+    &self,
+    provider: &dyn PackageVersionProvider,
+  ) -> (OutdatedReport, Vec<PackageVersionsLoadError>) {
+    outdated::report(&self.content, provider)
+  }
+
+  /// Removes a package specifier along with the packages and specifiers
+  /// that only it used.
+  ///
+  /// Returns what was removed.
+  pub fn remove_package_specifier(
+    &mut self,
+    package_req: &JsrDepPackageReq,
+  ) -> RemovedPackages {
+    if !self.content.packages.specifiers.contains_key(package_req) {
+      return RemovedPackages::default();
+    }
+    let package_req = package_req.clone();
+    self.remove_from_package_graph(|graph| graph.remove_specifier(package_req))
+  }
+
+  /// Removes a JSR package and the specifiers that resolve to it along with
+  /// the packages and specifiers that only it used.
+  ///
+  /// Returns what was removed.
+  pub fn remove_jsr_package(&mut self, nv: &PackageNv) -> RemovedPackages {
+    if !self.content.packages.jsr.contains_key(nv) {
+      return RemovedPackages::default();
+    }
+    let nv = nv.clone();
+    self.remove_from_package_graph(|graph| graph.remove_jsr_package(nv))
+  }
+
+  /// Removes an npm package and the specifiers that resolve to it along with
+  /// the packages and specifiers that only it used. Packages that depend on
+  /// it can't be installed without it, so they're removed the same way.
+  ///
+  /// Returns what was removed.
+  pub fn remove_npm_package(
+    &mut self,
+    serialized_package_id: &str,
+  ) -> RemovedPackages {
+    if !self
+      .content
+      .packages
+      .npm
+      .contains_key(serialized_package_id)
+    {
+      return RemovedPackages::default();
+    }
+    let id = StackString::from(serialized_package_id);
+    self.remove_from_package_graph(|graph| graph.remove_npm_package(id))
+  }
+
+  fn remove_from_package_graph(
+    &mut self,
+    remove: impl FnOnce(&mut LockfilePackageGraph),
+  ) -> RemovedPackages {
+    let packages = std::mem::take(&mut self.content.packages);
+    let remotes = std::mem::take(&mut self.content.remote);
+    let mut graph = LockfilePackageGraph::from_lockfile(packages, remotes);
+    let scope =
+      graph.orphan_scope(self.content.workspace.get_all_dep_reqs().cloned());
+    remove(&mut graph);
+    graph.remove_orphans(scope);
+    let changes = graph
+      .populate_packages(&mut self.content.packages, &mut self.content.remote);
+    if let Some(index) = &mut self.dependency_index {
+      index.apply_changes(&changes);
+    }
+
+    if !changes.is_empty() {
+      self.has_content_changed = true;
+    }
+    self.record_package_changes(&changes);
+    changes.removed
+  }
+
+  /// Rewrites the npm tarball, remote, and redirect urls in the lockfile
+  /// using the mirror's url prefix mappings, which can be undone by
+  /// rewriting with [`RegistryMirror::reversed`].
+  ///
+  /// Tarball urls of npm packages from the default registry are not
+  /// stored in the lockfile, so they're added when rewritten and removed
+  /// when rewritten back. Returns the number of urls that were changed.
+  ///
+  /// Errors without changing the lockfile when two remote or redirect
+  /// urls would be rewritten to the same url.
+  pub fn rewrite_registry_urls(
+    &mut self,
+    mirror: &RegistryMirror,
+  ) -> Result<usize, RegistryUrlCollisionError> {
+    let count =
+      mirror::rewrite(&mut self.content, mirror, self.journal.as_mut())?;
+    if count > 0 {
+      self.has_content_changed = true;
+    }
+    Ok(count)
+  }
+
+  /// Removes dangling references from the lockfile, such as specifiers
+  /// that resolve to missing packages, dependencies that don't resolve,
+  /// and redirect loops. Specifiers locked to a version that no longer
+  /// satisfies them are removed so they get resolved again.
+  ///
+  /// Returns the edits that were made.
+  pub fn repair(&mut self) -> Vec<RepairEdit> {
+    let edits = validation::repair(&mut self.content, self.journal.as_mut());
+    if let Some(index) = &mut self.dependency_index {
+      for edit in &edits {
+        index.apply_repair(edit);
       }
     }
+    if !edits.is_empty() {
+      self.has_content_changed = true;
+    }
+    edits
+  }
+
+  /// Follows the redirects for the specifier to its final url and gets
+  /// that url's checksum.
+  pub fn resolve_redirects(
+    &self,
+    specifier: &str,
+  ) -> Result<ResolvedRedirect, RedirectChainError> {
+    redirects::resolve(&self.content.redirects, &self.content.remote, specifier)
+  }
+
+  /// Gets the redirect cycles and the redirects that don't end at a
+  /// remote url.
+  pub fn redirect_chain_errors(&self) -> Vec<RedirectChainError> {
+    redirects::chain_errors(&self.content.redirects, &self.content.remote)
+  }
+
+  /// Points redirects that are part of a chain directly at the chain's
+  /// final url. Returns the number of redirects that were changed.
+  pub fn collapse_redirects(&mut self) -> usize {
+    let count =
+      redirects::collapse(&mut self.content.redirects, self.journal.as_mut());
+    if count > 0 {
+      self.has_content_changed = true;
+    }
+    count
   }
 
   pub fn insert_redirect(&mut self, from: String, to: String) {
@@ -1118,17 +2579,21 @@ impl Lockfile {
       return;
     }
 
-    let entry = self.content.redirects.entry(from);
-    match entry {
-      BTreeMapEntry::Vacant(entry) => {
-        entry.insert(to);
-        self.has_content_changed = true;
+    if self.content.redirects.get(&from) == Some(&to) {
+      return;
+    }
+    self.has_content_changed = true;
+    match &mut self.journal {
+      Some(journal) => {
+        let old = self.content.redirects.insert(from.clone(), to.clone());
+        journal.push(LockfileMutation::Redirect {
+          from,
+          old,
+          new: Some(to),
+        });
       }
-      BTreeMapEntry::Occupied(mut entry) => {
-        if *entry.get() != to {
-          entry.insert(to);
-          self.has_content_changed = true;
-        }
+      None => {
+        self.content.redirects.insert(from, to);
       }
     }
   }
@@ -1686,9 +3151,385 @@ mod tests {
   }
 
   #[test]
-  fn empty_lockfile_nicer_error() {
-    let content: &str = r#"  "#;
-    let file_path = PathBuf::from("lockfile.json");
+  fn round_trips_unknown_fields() {
+    let content = r#"{
+  "version": "5",
+  "specifiers": {
+    "jsr:@scope/package@1": "1.0.0",
+    "npm:chalk@5": "5.0.0"
+  },
+  "jsr": {
+    "@scope/package@1.0.0": {
+      "integrity": "sha256-jsr",
+      "newJsrField": [
+        "a"
+      ]
+    }
+  },
+  "npm": {
+    "chalk@5.0.0": {
+      "integrity": "sha512-chalk",
+      "newNpmField": {
+        "value": 1
+      }
+    }
+  },
+  "aNewSection": {
+    "key": "value"
+  },
+  "zNewSection": true
+}
+"#;
+    let mut lockfile = new_lockfile(NewLockfileOptions {
+      file_path: PathBuf::from("/foo/deno.lock"),
+      content,
+      overwrite: false,
+    })
+    .unwrap();
+    assert_eq!(
+      lockfile.as_json_string(),
+      r#"{
+  "version": "5",
+  "specifiers": {
+    "jsr:@scope/package@1": "1.0.0",
+    "npm:chalk@5": "5.0.0"
+  },
+  "jsr": {
+    "@scope/package@1.0.0": {
+      "integrity": "sha256-jsr",
+      "newJsrField": [
+        "a"
+      ]
+    }
+  },
+  "npm": {
+    "chalk@5.0.0": {
+      "integrity": "sha512-chalk",
+      "newNpmField": {
+        "value": 1
+      }
+    }
+  },
+  "aNewSection": {
+    "key": "value"
+  },
+  "zNewSection": true
+}
+"#
+    );
+
+    // inserting over the package maintains the unknown fields
+    lockfile.insert_npm_package(NpmPackageLockfileInfo {
+      serialized_id: "chalk@5.0.0".into(),
+      integrity: Some("sha512-chalk".to_string()),
+      dependencies: vec![],
+      optional_dependencies: vec![],
+      optional_peers: vec![],
+      os: vec![],
+      cpu: vec![],
+      tarball: None,
+      deprecated: false,
+      scripts: false,
+      bin: false,
+    });
+    assert!(!lockfile.has_content_changed);
+    assert_eq!(
+      lockfile.content.packages.npm["chalk@5.0.0"]
+        .unknown_fields()
+        .0
+        .get("newNpmField"),
+      Some(&serde_json::json!({ "value": 1 }))
+    );
+  }
+
+  #[test]
+  fn deserialization_error_location() {
+    let err = new_lockfile(NewLockfileOptions {
+      file_path: PathBuf::from("/foo/deno.lock"),
+      content: r#"{
+  "version": "5",
+  "npm": {
+    "a@1.0.0": {
+      "integrity": "sha512-a",
+      "dependencies": [
+        "b",
+        "missing"
+      ]
+    },
+    "b@1.0.0": {
+      "integrity": "sha512-b"
+    }
+  }
+}"#,
+      overwrite: false,
+    })
+    .unwrap_err();
+    assert_eq!(err.position(), Some(SourcePosition { line: 8, column: 9 }));
+    assert_eq!(
+      err.to_string(),
+      "Failed reading lockfile at '/foo/deno.lock:8:9'"
+    );
+    assert_eq!(
+      err.json_path().unwrap().to_string(),
+      "npm[\"a@1.0.0\"].dependencies[1]"
+    );
+    let LockfileErrorReason::DeserializationError(err) = err.source else {
+      unreachable!();
+    };
+    assert_eq!(
+      err.to_string(),
+      "npm package 'missing' was not found and could not have its version resolved"
+    );
+
+    // version 4 lockfiles have the same layout for jsr packages
+    let err = new_lockfile(NewLockfileOptions {
+      file_path: PathBuf::from("/foo/deno.lock"),
+      content: r#"{
+  "version": "4",
+  "jsr": {
+    "@scope/a@1.0.0": {
+      "integrity": "sha256-a",
+      "dependencies": [
+        "jsr:@scope/b@1"
+      ]
+    }
+  }
+}"#,
+      overwrite: false,
+    })
+    .unwrap_err();
+    assert_eq!(err.position(), Some(SourcePosition { line: 7, column: 9 }));
+    assert_eq!(
+      err.source.to_string(),
+      "Failed deserializing. Lockfile may be corrupt"
+    );
+
+    // parse errors use the position from serde_json
+    let err = new_lockfile(NewLockfileOptions {
+      file_path: PathBuf::from("/foo/deno.lock"),
+      content: "{\n  \"version\": \"5\",,\n}",
+      overwrite: false,
+    })
+    .unwrap_err();
+    assert_eq!(
+      err.position(),
+      Some(SourcePosition {
+        line: 2,
+        column: 18
+      })
+    );
+  }
+
+  #[test]
+  fn lenient_skips_invalid_entries() {
+    let content = r#"{
+  "version": "5",
+  "specifiers": {
+    "jsr:@scope/a@1": "1.0.0",
+    "npm:@bad": "1.0.0",
+    "npm:b@1": "1.0.0"
+  },
+  "jsr": {
+    "@scope/a@1.0.0": {
+      "integrity": "sha256-a",
+      "dependencies": [
+        "jsr:@scope/missing@1",
+        "npm:b@1"
+      ]
+    }
+  },
+  "npm": {
+    "b@1.0.0": {
+      "integrity": "sha512-b",
+      "dependencies": [
+        "missing"
+      ]
+    },
+    "invalid": {
+      "integrity": "sha512-invalid"
+    }
+  }
+}"#;
+    let options = || NewLockfileOptions {
+      file_path: PathBuf::from("/foo/deno.lock"),
+      content,
+      overwrite: false,
+    };
+    assert!(new_lockfile(options()).is_err());
+
+    let (lockfile, errors) =
+      Lockfile::new_lenient(options(), &TestNpmPackageInfoProvider::default())
+        .now_or_never()
+        .unwrap()
+        .unwrap();
+    assert_eq!(
+      errors
+        .iter()
+        .map(|e| e.path.to_string())
+        .collect::<Vec<_>>(),
+      vec![
+        r#"specifiers["npm:@bad"]"#,
+        "npm.invalid",
+        r#"npm["b@1.0.0"].dependencies[0]"#,
+        r#"jsr["@scope/a@1.0.0"].dependencies[0]"#,
+      ]
+    );
+    assert!(lockfile.has_content_changed);
+    assert_eq!(
+      lockfile.as_json_string(),
+      r#"{
+  "version": "5",
+  "specifiers": {
+    "jsr:@scope/a@1": "1.0.0",
+    "npm:b@1": "1.0.0"
+  },
+  "jsr": {
+    "@scope/a@1.0.0": {
+      "integrity": "sha256-a",
+      "dependencies": [
+        "npm:b"
+      ]
+    }
+  },
+  "npm": {
+    "b@1.0.0": {
+      "integrity": "sha512-b"
+    }
+  }
+}
+"#
+    );
+  }
+
+  #[test]
+  fn strict_skips_unparseable_jsr_dependencies() {
+    let content = r#"{
+  "version": "5",
+  "specifiers": {
+    "npm:b@1": "1.0.0"
+  },
+  "jsr": {
+    "@scope/a@1.0.0": {
+      "integrity": "sha256-a",
+      "dependencies": [
+        "not a specifier",
+        "npm:b@1"
+      ]
+    }
+  },
+  "npm": {
+    "b@1.0.0": {
+      "integrity": "sha512-b"
+    }
+  }
+}"#;
+    let options = || NewLockfileOptions {
+      file_path: PathBuf::from("/foo/deno.lock"),
+      content,
+      overwrite: false,
+    };
+    let lockfile = new_lockfile(options()).unwrap();
+    let nv = PackageNv::from_str("@scope/a@1.0.0").unwrap();
+    assert_eq!(
+      lockfile.content.packages.jsr[&nv].dependencies,
+      HashSet::from([JsrDepPackageReq::from_str("npm:b@1").unwrap()])
+    );
+
+    // only lenient loading reports it
+    let (_, errors) =
+      Lockfile::new_lenient(options(), &TestNpmPackageInfoProvider::default())
+        .now_or_never()
+        .unwrap()
+        .unwrap();
+    assert_eq!(
+      errors.iter().map(|e| e.to_string()).collect::<Vec<_>>(),
+      vec![
+        r#"Invalid jsr dependency 'not a specifier' for '@scope/a@1.0.0' at jsr["@scope/a@1.0.0"].dependencies[0]"#
+      ]
+    );
+  }
+
+  #[test]
+  fn lenient_keeps_valid_remote_and_workspace_entries() {
+    let content = r#"{
+  "version": "5",
+  "redirects": {
+    "https://deno.land/x/a": "https://deno.land/x/a@1/mod.ts",
+    "https://deno.land/x/b": 1
+  },
+  "remote": {
+    "https://deno.land/x/a@1/mod.ts": "checksum-a",
+    "https://deno.land/x/b@1/mod.ts": false
+  },
+  "workspace": {
+    "dependencies": [
+      "jsr:@scope/a@1"
+    ],
+    "members": {
+      "member-a": {
+        "dependencies": [
+          "jsr:@scope/b@1"
+        ]
+      },
+      "member-b": {
+        "dependencies": 5
+      }
+    }
+  }
+}"#;
+    let (lockfile, errors) = Lockfile::new_lenient(
+      NewLockfileOptions {
+        file_path: PathBuf::from("/foo/deno.lock"),
+        content,
+        overwrite: false,
+      },
+      &TestNpmPackageInfoProvider::default(),
+    )
+    .now_or_never()
+    .unwrap()
+    .unwrap();
+    assert_eq!(
+      errors
+        .iter()
+        .map(|e| e.path.to_string())
+        .collect::<Vec<_>>(),
+      vec![
+        r#"redirects["https://deno.land/x/b"]"#,
+        r#"remote["https://deno.land/x/b@1/mod.ts"]"#,
+        r#"workspace.members["member-b"]"#,
+      ]
+    );
+    assert_eq!(
+      lockfile.as_json_string(),
+      r#"{
+  "version": "5",
+  "redirects": {
+    "https://deno.land/x/a": "https://deno.land/x/a@1/mod.ts"
+  },
+  "remote": {
+    "https://deno.land/x/a@1/mod.ts": "checksum-a"
+  },
+  "workspace": {
+    "dependencies": [
+      "jsr:@scope/a@1"
+    ],
+    "members": {
+      "member-a": {
+        "dependencies": [
+          "jsr:@scope/b@1"
+        ]
+      }
+    }
+  }
+}
+"#
+    );
+  }
+
+  #[test]
+  fn empty_lockfile_nicer_error() {
+    let content: &str = r#"  "#;
+    let file_path = PathBuf::from("lockfile.json");
     let err = new_lockfile(NewLockfileOptions {
       file_path,
       content,
@@ -1698,4 +3539,480 @@ mod tests {
     .unwrap();
     assert!(matches!(err.source, LockfileErrorReason::Empty));
   }
+
+  #[test]
+  fn save_merges_changes_made_on_disk() {
+    let fs = InMemoryLockfileFs::new();
+    let provider = TestNpmPackageInfoProvider::default();
+    let load = || {
+      Lockfile::open(
+        OpenLockfileOptions {
+          file_path: PathBuf::from("/deno.lock"),
+          overwrite: false,
+          if_missing: LockfileOpenFallback::Empty,
+          if_empty: LockfileOpenFallback::Error,
+          if_unsupported: LockfileOpenFallback::Error,
+        },
+        &fs,
+        &provider,
+      )
+      .now_or_never()
+      .unwrap()
+      .unwrap()
+    };
+
+    let mut first = load();
+    let mut second = load();
+    assert_eq!(
+      first
+        .save_with_fs(&fs, &provider)
+        .now_or_never()
+        .unwrap()
+        .unwrap(),
+      LockfileSaveOutcome::Unchanged
+    );
+    first.insert_remote("https://a.com/a".to_string(), "1".to_string());
+    assert_eq!(
+      first
+        .save_with_fs(&fs, &provider)
+        .now_or_never()
+        .unwrap()
+        .unwrap(),
+      LockfileSaveOutcome::Written
+    );
+    second.insert_remote("https://a.com/a".to_string(), "2".to_string());
+    second.insert_remote("https://a.com/b".to_string(), "1".to_string());
+    // both added the same remote with different checksums
+    assert_eq!(
+      second
+        .save_with_fs(&fs, &provider)
+        .now_or_never()
+        .unwrap()
+        .unwrap(),
+      LockfileSaveOutcome::Merged {
+        conflicts: vec![LockfileMergeConflict {
+          path: JsonPath::from_key("remote").join_key("https://a.com/a"),
+        }]
+      }
+    );
+    assert!(!second.has_content_changed);
+
+    first.insert_remote("https://a.com/c".to_string(), "1".to_string());
+    assert_eq!(
+      first
+        .save_with_fs(&fs, &provider)
+        .now_or_never()
+        .unwrap()
+        .unwrap(),
+      LockfileSaveOutcome::Merged { conflicts: vec![] }
+    );
+    assert_eq!(
+      load().remote(),
+      &BTreeMap::from(
+        [
+          ("https://a.com/a", "2"),
+          ("https://a.com/b", "1"),
+          ("https://a.com/c", "1"),
+        ]
+        .map(|(k, v)| (k.to_string(), v.to_string()))
+      )
+    );
+  }
+
+  #[test]
+  fn open_with_fallbacks() {
+    let provider = TestNpmPackageInfoProvider::default();
+    let open = |fs: &InMemoryLockfileFs, fallback: LockfileOpenFallback| {
+      Lockfile::open(
+        OpenLockfileOptions {
+          file_path: PathBuf::from("/deno.lock"),
+          overwrite: false,
+          if_missing: fallback,
+          if_empty: fallback,
+          if_unsupported: fallback,
+        },
+        fs,
+        &provider,
+      )
+      .now_or_never()
+      .unwrap()
+    };
+
+    let fs = InMemoryLockfileFs::new();
+    let err = open(&fs, LockfileOpenFallback::Error).err().unwrap();
+    assert!(matches!(err.source, LockfileErrorReason::NotFound));
+    let mut lockfile = open(&fs, LockfileOpenFallback::Empty).unwrap();
+    lockfile.insert_remote("https://a.com/a".to_string(), "1".to_string());
+    assert_eq!(
+      lockfile
+        .save_with_fs(&fs, &provider)
+        .now_or_never()
+        .unwrap()
+        .unwrap(),
+      LockfileSaveOutcome::Written
+    );
+    assert_eq!(fs.paths(), vec![PathBuf::from("/deno.lock")]);
+    assert_eq!(
+      open(&fs, LockfileOpenFallback::Error).unwrap().remote(),
+      lockfile.remote()
+    );
+
+    fs.set_file("/deno.lock", "  \n");
+    let err = open(&fs, LockfileOpenFallback::Error).err().unwrap();
+    assert!(matches!(err.source, LockfileErrorReason::Empty));
+    assert!(
+      open(&fs, LockfileOpenFallback::Empty)
+        .unwrap()
+        .content
+        .is_empty()
+    );
+
+    fs.set_file("/deno.lock", r#"{ "version": "100" }"#);
+    let err = open(&fs, LockfileOpenFallback::Error).err().unwrap();
+    assert!(matches!(
+      err.source,
+      LockfileErrorReason::UnsupportedVersion { .. }
+    ));
+    let mut lockfile = open(&fs, LockfileOpenFallback::Empty).unwrap();
+    lockfile.insert_remote("https://a.com/a".to_string(), "1".to_string());
+    assert_eq!(
+      lockfile
+        .save_with_fs(&fs, &provider)
+        .now_or_never()
+        .unwrap()
+        .unwrap(),
+      LockfileSaveOutcome::Written,
+      "should replace the unsupported lockfile rather than merge with it"
+    );
+  }
+
+  #[test]
+  fn reload_carries_over_unsaved_changes() {
+    let provider = TestNpmPackageInfoProvider::default();
+    let file_path = PathBuf::from("/deno.lock");
+    let fs = InMemoryLockfileFs::new().with_file(
+      &file_path,
+      r#"{
+  "version": "5",
+  "remote": {
+    "https://a.com/a": "1",
+    "https://a.com/b": "1"
+  }
+}"#,
+    );
+    let mut lockfile = Lockfile::open(
+      OpenLockfileOptions {
+        file_path: file_path.clone(),
+        overwrite: false,
+        if_missing: LockfileOpenFallback::Error,
+        if_empty: LockfileOpenFallback::Error,
+        if_unsupported: LockfileOpenFallback::Error,
+      },
+      &fs,
+      &provider,
+    )
+    .now_or_never()
+    .unwrap()
+    .unwrap();
+    assert!(lockfile.disk_fingerprint().is_some());
+    assert!(!lockfile.has_changed_on_disk(&fs).unwrap());
+    let reload = |lockfile: &mut Lockfile| {
+      lockfile
+        .reload(&fs, &provider)
+        .now_or_never()
+        .unwrap()
+        .unwrap()
+    };
+    assert_eq!(reload(&mut lockfile), LockfileReloadOutcome::Unchanged);
+
+    lockfile.insert_remote("https://a.com/b".to_string(), "2".to_string());
+    lockfile.insert_remote("https://a.com/c".to_string(), "2".to_string());
+    fs.set_file(
+      &file_path,
+      r#"{
+  "version": "5",
+  "remote": {
+    "https://a.com/b": "3",
+    "https://a.com/d": "3"
+  }
+}"#,
+    );
+    assert!(lockfile.has_changed_on_disk(&fs).unwrap());
+    assert_eq!(
+      reload(&mut lockfile),
+      LockfileReloadOutcome::Reloaded {
+        conflicts: vec![LockfileMergeConflict {
+          path: JsonPath::from_key("remote").join_key("https://a.com/b"),
+        }]
+      }
+    );
+    assert!(!lockfile.has_changed_on_disk(&fs).unwrap());
+    assert!(lockfile.has_content_changed);
+    assert_eq!(
+      lockfile.remote(),
+      &BTreeMap::from(
+        [
+          ("https://a.com/b", "2"),
+          ("https://a.com/c", "2"),
+          ("https://a.com/d", "3"),
+        ]
+        .map(|(k, v)| (k.to_string(), v.to_string()))
+      )
+    );
+    // the reloaded file is now the base, so saving doesn't merge again
+    assert_eq!(
+      lockfile
+        .save_with_fs(&fs, &provider)
+        .now_or_never()
+        .unwrap()
+        .unwrap(),
+      LockfileSaveOutcome::Written
+    );
+
+    // the same change was made on disk, so nothing is left to save
+    lockfile.insert_remote("https://a.com/e".to_string(), "4".to_string());
+    fs.set_file(
+      &file_path,
+      r#"{
+  "version": "5",
+  "remote": {
+    "https://a.com/b": "2",
+    "https://a.com/c": "2",
+    "https://a.com/d": "3",
+    "https://a.com/e": "4"
+  }
+}"#,
+    );
+    assert_eq!(
+      reload(&mut lockfile),
+      LockfileReloadOutcome::Reloaded { conflicts: vec![] }
+    );
+    assert!(!lockfile.has_content_changed);
+  }
+
+  #[test]
+  fn remove_and_retain_remotes() {
+    let mut lockfile = Lockfile::new_empty(PathBuf::from("/deno.lock"), false);
+    for url in [
+      "https://deno.land/x/a@1/mod.ts",
+      "https://deno.land/x/b@1/mod.ts",
+      "https://esm.sh/c@1",
+      "https://esm.sh/d@1",
+    ] {
+      lockfile.insert_remote(url.to_string(), "checksum".to_string());
+    }
+    lockfile.insert_redirect(
+      "https://deno.land/x/a/mod.ts".to_string(),
+      "https://deno.land/x/a@1/mod.ts".to_string(),
+    );
+    lockfile.insert_redirect(
+      "https://esm.sh/c".to_string(),
+      "https://esm.sh/c@1".to_string(),
+    );
+    lockfile.has_content_changed = false;
+
+    assert!(lockfile.remove_remote("https://missing.com").is_empty());
+    assert!(!lockfile.has_content_changed);
+
+    let removed = lockfile.remove_remotes_with_prefix("https://deno.land/x/");
+    assert_eq!(
+      removed.remote.keys().collect::<Vec<_>>(),
+      vec![
+        "https://deno.land/x/a@1/mod.ts",
+        "https://deno.land/x/b@1/mod.ts"
+      ]
+    );
+    assert_eq!(
+      removed.redirects.keys().collect::<Vec<_>>(),
+      vec!["https://deno.land/x/a/mod.ts"]
+    );
+    assert!(lockfile.has_content_changed);
+
+    let removed =
+      lockfile.retain_remotes(&HashSet::from(["https://esm.sh/c".to_string()]));
+    assert_eq!(
+      removed.remote.keys().collect::<Vec<_>>(),
+      vec!["https://esm.sh/d@1"]
+    );
+    assert!(removed.redirects.is_empty());
+    assert_eq!(
+      lockfile.remote().keys().collect::<Vec<_>>(),
+      vec!["https://esm.sh/c@1"]
+    );
+    assert_eq!(lockfile.content.redirects.len(), 1);
+
+    let removed =
+      lockfile.remove_remotes_where(|_, checksum| checksum == "checksum");
+    assert_eq!(removed.remote.len(), 1);
+    assert_eq!(removed.redirects.len(), 1);
+    assert!(lockfile.content.is_empty());
+  }
+
+  #[test]
+  fn remove_packages_with_orphans() {
+    let content = serde_json::json!({
+      "version": "5",
+      "specifiers": {
+        "jsr:@scope/a@1": "1.0.0",
+        "jsr:@scope/b@1": "1.0.0",
+        "npm:c@1": "1.0.0",
+        "npm:shared@1": "1.0.0",
+      },
+      "jsr": {
+        "@scope/a@1.0.0": {
+          "integrity": "sha256",
+          "dependencies": ["jsr:@scope/b@1", "npm:shared@1"],
+        },
+        "@scope/b@1.0.0": { "integrity": "sha256" },
+      },
+      "npm": {
+        "c@1.0.0": { "integrity": "sha512", "dependencies": ["d"] },
+        "d@1.0.0": { "integrity": "sha512" },
+        "shared@1.0.0": { "integrity": "sha512" },
+        "unused@1.0.0": { "integrity": "sha512" },
+      },
+      "workspace": {
+        "dependencies": ["jsr:@scope/a@1", "npm:c@1", "npm:shared@1"],
+      },
+    })
+    .to_string();
+    let mut lockfile = new_lockfile(NewLockfileOptions {
+      file_path: PathBuf::from("/deno.lock"),
+      content: &content,
+      overwrite: false,
+    })
+    .unwrap();
+
+    let missing = JsrDepPackageReq::from_str("jsr:@scope/missing@1").unwrap();
+    assert!(lockfile.remove_package_specifier(&missing).is_empty());
+    assert!(!lockfile.has_content_changed);
+
+    let removed = lockfile.remove_package_specifier(
+      &JsrDepPackageReq::from_str("jsr:@scope/a@1").unwrap(),
+    );
+    let mut specifiers = removed
+      .specifiers
+      .keys()
+      .map(|req| req.to_string())
+      .collect::<Vec<_>>();
+    specifiers.sort();
+    assert_eq!(specifiers, vec!["jsr:@scope/a@1", "jsr:@scope/b@1"]);
+    assert_eq!(
+      removed
+        .jsr
+        .keys()
+        .map(|nv| nv.to_string())
+        .collect::<Vec<_>>(),
+      vec!["@scope/a@1.0.0", "@scope/b@1.0.0"]
+    );
+    assert!(removed.npm.is_empty());
+    // removed entries are reported as they were in the lockfile
+    assert_eq!(
+      removed.specifiers
+        [&JsrDepPackageReq::from_str("jsr:@scope/a@1").unwrap()],
+      "1.0.0"
+    );
+    assert_eq!(
+      removed.jsr[&PackageNv::from_str("@scope/a@1.0.0").unwrap()]
+        .dependencies
+        .len(),
+      2
+    );
+    assert!(lockfile.has_content_changed);
+
+    let removed = lockfile.remove_npm_package("c@1.0.0");
+    assert_eq!(
+      removed
+        .specifiers
+        .keys()
+        .map(|req| req.to_string())
+        .collect::<Vec<_>>(),
+      vec!["npm:c@1"]
+    );
+    assert_eq!(
+      removed.npm.keys().collect::<Vec<_>>(),
+      vec!["c@1.0.0", "d@1.0.0"]
+    );
+    // packages that were already unused are left alone
+    assert_eq!(
+      lockfile.content.packages.npm.keys().collect::<Vec<_>>(),
+      vec!["shared@1.0.0", "unused@1.0.0"]
+    );
+
+    let removed = lockfile
+      .remove_jsr_package(&PackageNv::from_str("@scope/a@1.0.0").unwrap());
+    assert!(removed.is_empty());
+  }
+
+  #[test]
+  fn remove_npm_package_with_dependents() {
+    let content = serde_json::json!({
+      "version": "5",
+      "specifiers": {
+        "jsr:@scope/x@1": "1.0.0",
+        "npm:a@1": "1.0.0",
+        "npm:b@1": "1.0.0",
+        "npm:other@1": "1.0.0",
+      },
+      "jsr": {
+        "@scope/x@1.0.0": {
+          "integrity": "sha256",
+          "dependencies": ["npm:b@1"],
+        },
+      },
+      "npm": {
+        "a@1.0.0": { "integrity": "sha512", "dependencies": ["b"] },
+        "b@1.0.0": { "integrity": "sha512", "dependencies": ["c"] },
+        "c@1.0.0": { "integrity": "sha512" },
+        "other@1.0.0": { "integrity": "sha512" },
+      },
+      "workspace": {
+        "dependencies": ["jsr:@scope/x@1", "npm:a@1", "npm:other@1"],
+      },
+    })
+    .to_string();
+    let mut lockfile = new_lockfile(NewLockfileOptions {
+      file_path: PathBuf::from("/deno.lock"),
+      content: &content,
+      overwrite: false,
+    })
+    .unwrap();
+
+    let removed = lockfile.remove_npm_package("b@1.0.0");
+    let mut specifiers = removed
+      .specifiers
+      .keys()
+      .map(|req| req.to_string())
+      .collect::<Vec<_>>();
+    specifiers.sort();
+    assert_eq!(specifiers, vec!["jsr:@scope/x@1", "npm:a@1", "npm:b@1"]);
+    assert_eq!(
+      removed
+        .jsr
+        .keys()
+        .map(|nv| nv.to_string())
+        .collect::<Vec<_>>(),
+      vec!["@scope/x@1.0.0"]
+    );
+    assert_eq!(
+      removed.npm.keys().collect::<Vec<_>>(),
+      vec!["a@1.0.0", "b@1.0.0", "c@1.0.0"]
+    );
+    // nothing is left depending on a removed package
+    assert_eq!(
+      lockfile
+        .content
+        .packages
+        .specifiers
+        .keys()
+        .map(|req| req.to_string())
+        .collect::<Vec<_>>(),
+      vec!["npm:other@1"]
+    );
+    assert!(lockfile.content.packages.jsr.is_empty());
+    assert_eq!(
+      lockfile.content.packages.npm.keys().collect::<Vec<_>>(),
+      vec!["other@1.0.0"]
+    );
+  }
 }
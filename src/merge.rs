@@ -0,0 +1,321 @@
+// Copyright 2018-2024 the Deno authors. MIT license.
+
+use std::collections::BTreeMap;
+use std::collections::HashMap;
+use std::hash::Hash;
+
+use crate::JsonPath;
+use crate::LockfileContent;
+
+/// A value that was changed differently in memory and on disk.
+///
+/// The in-memory value is kept when this happens.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LockfileMergeConflict {
+  /// Path to the conflicting value in the lockfile.
+  pub path: JsonPath,
+}
+
+/// Does a three-way merge of the changes made from `base` to `ours` onto
+/// `theirs`, where `base` is the content both were derived from.
+pub(crate) fn merge_content(
+  base: &LockfileContent,
+  ours: &LockfileContent,
+  mut theirs: LockfileContent,
+) -> (LockfileContent, Vec<LockfileMergeConflict>) {
+  let mut conflicts = Vec::new();
+  merge_map(
+    &base.packages.specifiers,
+    &ours.packages.specifiers,
+    &mut theirs.packages.specifiers,
+    |req| {
+      JsonPath::from_key("specifiers")
+        .join_key(req.to_string_normalized().as_str())
+    },
+    &mut conflicts,
+  );
+  merge_map(
+    &base.packages.jsr,
+    &ours.packages.jsr,
+    &mut theirs.packages.jsr,
+    |nv| JsonPath::from_key("jsr").join_key(nv.to_string()),
+    &mut conflicts,
+  );
+  merge_map(
+    &base.packages.npm,
+    &ours.packages.npm,
+    &mut theirs.packages.npm,
+    |id| JsonPath::from_key("npm").join_key(id.as_str()),
+    &mut conflicts,
+  );
+  merge_map(
+    &base.redirects,
+    &ours.redirects,
+    &mut theirs.redirects,
+    |from| JsonPath::from_key("redirects").join_key(from.as_str()),
+    &mut conflicts,
+  );
+  merge_map(
+    &base.remote,
+    &ours.remote,
+    &mut theirs.remote,
+    |url| JsonPath::from_key("remote").join_key(url.as_str()),
+    &mut conflicts,
+  );
+  merge_map(
+    &base.unknown_fields.0,
+    &ours.unknown_fields.0,
+    &mut theirs.unknown_fields.0,
+    |key| JsonPath::from_key(key.as_str()),
+    &mut conflicts,
+  );
+  // the workspace config is always set as a whole
+  if base.workspace != ours.workspace {
+    if base.workspace != theirs.workspace && ours.workspace != theirs.workspace
+    {
+      conflicts.push(LockfileMergeConflict {
+        path: JsonPath::from_key("workspace"),
+      });
+    }
+    theirs.workspace = ours.workspace.clone();
+  }
+  conflicts.sort_by_cached_key(|c| c.path.to_string());
+  (theirs, conflicts)
+}
+
+trait MergeMap<K, V> {
+  fn get_value(&self, key: &K) -> Option<&V>;
+  fn set_value(&mut self, key: &K, value: Option<&V>);
+  fn keys_vec(&self) -> Vec<&K>;
+}
+
+impl<K: Ord + Clone, V: Clone> MergeMap<K, V> for BTreeMap<K, V> {
+  fn get_value(&self, key: &K) -> Option<&V> {
+    self.get(key)
+  }
+
+  fn set_value(&mut self, key: &K, value: Option<&V>) {
+    match value {
+      Some(value) => self.insert(key.clone(), value.clone()),
+      None => self.remove(key),
+    };
+  }
+
+  fn keys_vec(&self) -> Vec<&K> {
+    self.keys().collect()
+  }
+}
+
+impl<K: Hash + Eq + Clone, V: Clone> MergeMap<K, V> for HashMap<K, V> {
+  fn get_value(&self, key: &K) -> Option<&V> {
+    self.get(key)
+  }
+
+  fn set_value(&mut self, key: &K, value: Option<&V>) {
+    match value {
+      Some(value) => self.insert(key.clone(), value.clone()),
+      None => self.remove(key),
+    };
+  }
+
+  fn keys_vec(&self) -> Vec<&K> {
+    self.keys().collect()
+  }
+}
+
+fn merge_map<K, V: PartialEq>(
+  base: &impl MergeMap<K, V>,
+  ours: &impl MergeMap<K, V>,
+  theirs: &mut impl MergeMap<K, V>,
+  get_path: impl Fn(&K) -> JsonPath,
+  conflicts: &mut Vec<LockfileMergeConflict>,
+) {
+  let added_keys = ours
+    .keys_vec()
+    .into_iter()
+    .filter(|key| base.get_value(key).is_none());
+  for key in base.keys_vec().into_iter().chain(added_keys) {
+    let base_value = base.get_value(key);
+    let our_value = ours.get_value(key);
+    if base_value == our_value {
+      continue; // not changed in memory
+    }
+    let their_value = theirs.get_value(key);
+    if their_value == our_value {
+      continue; // same change made on disk
+    }
+    if their_value != base_value {
+      conflicts.push(LockfileMergeConflict {
+        path: get_path(key),
+      });
+    }
+    theirs.set_value(key, our_value);
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use pretty_assertions::assert_eq;
+
+  use super::*;
+
+  fn content(json: serde_json::Value) -> LockfileContent {
+    LockfileContent::from_json(json).unwrap()
+  }
+
+  #[test]
+  fn merges_changes() {
+    let base = content(serde_json::json!({
+      "remote": {
+        "https://a.com/kept": "1",
+        "https://a.com/removed_by_us": "1",
+        "https://a.com/removed_by_them": "1",
+        "https://a.com/changed_by_both": "1",
+      },
+    }));
+    let ours = content(serde_json::json!({
+      "remote": {
+        "https://a.com/kept": "1",
+        "https://a.com/removed_by_them": "1",
+        "https://a.com/changed_by_both": "2",
+        "https://a.com/added_by_us": "1",
+      },
+    }));
+    let theirs = content(serde_json::json!({
+      "remote": {
+        "https://a.com/kept": "1",
+        "https://a.com/removed_by_us": "1",
+        "https://a.com/changed_by_both": "3",
+        "https://a.com/added_by_them": "1",
+      },
+      "newField": true,
+    }));
+    let (merged, conflicts) = merge_content(&base, &ours, theirs);
+    assert_eq!(
+      merged.remote,
+      BTreeMap::from(
+        [
+          ("https://a.com/kept", "1"),
+          ("https://a.com/changed_by_both", "2"),
+          ("https://a.com/added_by_us", "1"),
+          ("https://a.com/added_by_them", "1"),
+        ]
+        .map(|(k, v)| (k.to_string(), v.to_string()))
+      )
+    );
+    assert_eq!(
+      merged.unknown_fields.0.get("newField"),
+      Some(&serde_json::Value::Bool(true))
+    );
+    assert_eq!(
+      conflicts,
+      vec![LockfileMergeConflict {
+        path: JsonPath::from_key("remote")
+          .join_key("https://a.com/changed_by_both"),
+      }]
+    );
+  }
+
+  #[test]
+  fn merges_workspace_changes() {
+    let workspace = |deps: &[&str]| {
+      content(serde_json::json!({
+        "version": "5",
+        "workspace": { "dependencies": deps },
+      }))
+    };
+    let base = workspace(&["npm:a@1"]);
+    let ours = workspace(&["npm:a@1", "npm:b@1"]);
+    let workspace_conflict = vec![LockfileMergeConflict {
+      path: JsonPath::from_key("workspace"),
+    }];
+
+    // only changed on disk
+    let theirs = workspace(&["npm:c@1"]);
+    let (merged, conflicts) = merge_content(&base, &base, theirs.clone());
+    assert_eq!(merged.workspace, theirs.workspace);
+    assert!(conflicts.is_empty());
+
+    // changed the same way on disk
+    let (merged, conflicts) = merge_content(&base, &ours, ours.clone());
+    assert_eq!(merged.workspace, ours.workspace);
+    assert!(conflicts.is_empty());
+
+    // changed differently, where ours is kept as a whole rather than
+    // combining the dependencies
+    let (merged, conflicts) = merge_content(&base, &ours, theirs);
+    assert_eq!(merged.workspace, ours.workspace);
+    assert_eq!(conflicts, workspace_conflict);
+
+    // removed in memory, but changed on disk
+    let (merged, conflicts) =
+      merge_content(&base, &workspace(&[]), workspace(&["npm:c@1"]));
+    assert_eq!(merged.workspace, workspace(&[]).workspace);
+    assert_eq!(conflicts, workspace_conflict);
+  }
+
+  #[test]
+  fn merges_package_changes() {
+    let base = content(serde_json::json!({
+      "version": "5",
+      "specifiers": {
+        "npm:a@1": "1.0.0",
+        "npm:b@1": "1.0.0",
+      },
+      "npm": {
+        "a@1.0.0": { "integrity": "sha512-a" },
+        "b@1.0.0": { "integrity": "sha512-b" },
+      },
+    }));
+    // removes a and updates b
+    let ours = content(serde_json::json!({
+      "version": "5",
+      "specifiers": {
+        "npm:b@1": "1.0.1",
+      },
+      "npm": {
+        "b@1.0.0": { "integrity": "sha512-b" },
+        "b@1.0.1": { "integrity": "sha512-b1" },
+      },
+    }));
+    // changes a's integrity and updates b to a different version
+    let theirs = content(serde_json::json!({
+      "version": "5",
+      "specifiers": {
+        "npm:a@1": "1.0.0",
+        "npm:b@1": "1.0.2",
+      },
+      "npm": {
+        "a@1.0.0": { "integrity": "sha512-a2" },
+        "b@1.0.0": { "integrity": "sha512-b" },
+        "b@1.0.2": { "integrity": "sha512-b2" },
+      },
+    }));
+    let (merged, conflicts) = merge_content(&base, &ours, theirs);
+    assert_eq!(
+      merged
+        .packages
+        .specifiers
+        .iter()
+        .map(|(req, version)| (req.to_string(), version.to_string()))
+        .collect::<Vec<_>>(),
+      vec![("npm:b@1".to_string(), "1.0.1".to_string())]
+    );
+    // both versions of b are kept since each side added one
+    assert_eq!(
+      merged.packages.npm.keys().collect::<Vec<_>>(),
+      vec!["b@1.0.0", "b@1.0.1", "b@1.0.2"]
+    );
+    assert_eq!(
+      conflicts,
+      vec![
+        LockfileMergeConflict {
+          path: JsonPath::from_key("npm").join_key("a@1.0.0"),
+        },
+        LockfileMergeConflict {
+          path: JsonPath::from_key("specifiers").join_key("npm:b@1"),
+        },
+      ]
+    );
+  }
+}
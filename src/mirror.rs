@@ -0,0 +1,315 @@
+// Copyright 2018-2024 the Deno authors. MIT license.
+
+use std::collections::BTreeMap;
+use std::collections::btree_map::Entry;
+
+use thiserror::Error;
+
+use crate::LockfileContent;
+use crate::LockfileMutation;
+use crate::LockfilePackageId;
+use crate::journal;
+
+/// Registry that npm packages are downloaded from when none is configured.
+pub(crate) const DEFAULT_NPM_REGISTRY: &str = "https://registry.npmjs.org/";
+
+/// Replaces the `from` url prefix with the `to` url prefix.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UrlPrefixMapping {
+  pub from: String,
+  pub to: String,
+}
+
+/// Url prefix mappings for rewriting the urls in a lockfile between
+/// registries, such as from the public registries to an internal mirror.
+///
+/// The first mapping that matches a url is used. For rewriting to be
+/// reversible, each url should be mapped to a different url.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RegistryMirror {
+  pub mappings: Vec<UrlPrefixMapping>,
+  /// Registry that npm packages without a `tarball` are downloaded from.
+  pub default_npm_registry: String,
+}
+
+impl RegistryMirror {
+  pub fn new(mappings: Vec<UrlPrefixMapping>) -> Self {
+    Self {
+      mappings,
+      default_npm_registry: DEFAULT_NPM_REGISTRY.to_string(),
+    }
+  }
+
+  /// Gets the mirror that undoes this one's rewriting.
+  pub fn reversed(&self) -> Self {
+    Self {
+      mappings: self
+        .mappings
+        .iter()
+        .map(|m| UrlPrefixMapping {
+          from: m.to.clone(),
+          to: m.from.clone(),
+        })
+        .collect(),
+      default_npm_registry: self.default_npm_registry.clone(),
+    }
+  }
+
+  fn rewrite_url(&self, url: &str) -> Option<String> {
+    self.mappings.iter().find_map(|m| {
+      let rest = url.strip_prefix(m.from.as_str())?;
+      Some(format!("{}{}", m.to, rest))
+    })
+  }
+
+  fn default_tarball_url(&self, id: &str) -> Option<String> {
+    default_tarball_url(&self.default_npm_registry, id)
+  }
+}
+
+/// Gets the url of the tarball for an npm package id that's downloaded
+/// from the registry, which is used for packages without a `tarball`.
+pub(crate) fn default_tarball_url(registry: &str, id: &str) -> Option<String> {
+  let id = LockfilePackageId::Npm(id.into());
+  let (name, version) = id.name_and_version()?;
+  let unscoped_name = name.rsplit('/').next().unwrap();
+  let registry = registry.trim_end_matches('/');
+  Some(format!(
+    "{}/{}/-/{}-{}.tgz",
+    registry, name, unscoped_name, version
+  ))
+}
+
+/// Two urls that would be rewritten to the same url, so one of the
+/// entries would be lost.
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+#[error(
+  "Urls '{first}' and '{second}' in the {section} section would both be rewritten to '{url}'"
+)]
+pub struct RegistryUrlCollisionError {
+  pub section: &'static str,
+  pub first: String,
+  pub second: String,
+  pub url: String,
+}
+
+/// Rewrites the npm tarball, remote, and redirect urls in the content,
+/// returning the number of urls that were changed.
+///
+/// Integrity values are left as is because the content is the same. The
+/// content is not changed when the rewriting has a collision.
+pub(crate) fn rewrite(
+  content: &mut LockfileContent,
+  mirror: &RegistryMirror,
+  mut journal: Option<&mut Vec<LockfileMutation>>,
+) -> Result<usize, RegistryUrlCollisionError> {
+  let mut count = 0;
+  let remote =
+    rewrite_map(&content.remote, "remote", mirror, false, &mut count)?;
+  let redirects =
+    rewrite_map(&content.redirects, "redirects", mirror, true, &mut count)?;
+  if let Some(journal) = journal.as_deref_mut() {
+    journal::diff_remote(&content.remote, &remote, journal);
+    journal::diff_redirects(&content.redirects, &redirects, journal);
+  }
+  content.remote = remote;
+  content.redirects = redirects;
+
+  for (id, package) in &mut content.packages.npm {
+    // patch packages don't have a tarball
+    if package.integrity.is_none() {
+      continue;
+    }
+    let default_url = mirror.default_tarball_url(id);
+    let current_url = match &package.tarball {
+      Some(tarball) => tarball.to_string(),
+      None => match &default_url {
+        Some(url) => url.clone(),
+        None => continue,
+      },
+    };
+    if let Some(new_url) = mirror.rewrite_url(&current_url) {
+      count += 1;
+      let old = journal.is_some().then(|| package.clone());
+      // keep the lockfile the same as one generated against the
+      // default registry
+      package.tarball = if Some(&new_url) == default_url.as_ref() {
+        None
+      } else {
+        Some(new_url.as_str().into())
+      };
+      if let (Some(journal), Some(old)) = (journal.as_deref_mut(), old)
+        && old != *package
+      {
+        journal.push(LockfileMutation::NpmPackage {
+          id: id.clone(),
+          old: Some(old),
+          new: Some(package.clone()),
+        });
+      }
+    }
+  }
+
+  Ok(count)
+}
+
+fn rewrite_map(
+  map: &BTreeMap<String, String>,
+  section: &'static str,
+  mirror: &RegistryMirror,
+  values: bool,
+  count: &mut usize,
+) -> Result<BTreeMap<String, String>, RegistryUrlCollisionError> {
+  // keeps the original key to report collisions
+  let mut new_map = BTreeMap::<String, (&String, String)>::new();
+  for (key, value) in map {
+    let new_key = match mirror.rewrite_url(key) {
+      Some(new_key) => {
+        *count += 1;
+        new_key
+      }
+      None => key.clone(),
+    };
+    let new_value = match values.then(|| mirror.rewrite_url(value)).flatten() {
+      Some(new_value) => {
+        *count += 1;
+        new_value
+      }
+      None => value.clone(),
+    };
+    match new_map.entry(new_key) {
+      Entry::Vacant(entry) => {
+        entry.insert((key, new_value));
+      }
+      Entry::Occupied(entry) => {
+        return Err(RegistryUrlCollisionError {
+          section,
+          first: entry.get().0.clone(),
+          second: key.clone(),
+          url: entry.key().clone(),
+        });
+      }
+    }
+  }
+  Ok(
+    new_map
+      .into_iter()
+      .map(|(key, (_, value))| (key, value))
+      .collect(),
+  )
+}
+
+#[cfg(test)]
+mod test {
+  use pretty_assertions::assert_eq;
+
+  use super::*;
+
+  #[test]
+  fn rewrites_and_reverses() {
+    let json = serde_json::json!({
+      "version": "5",
+      "npm": {
+        "@scope/a@1.0.0": { "integrity": "sha512" },
+        "b@1.0.0_@scope+a@1.0.0": { "integrity": "sha512" },
+        "c@1.0.0": {
+          "integrity": "sha512",
+          "tarball": "https://other.com/c.tgz",
+        },
+        "patch@1.0.0": {},
+      },
+      "redirects": {
+        "https://deno.land/x/a/mod.ts": "https://deno.land/x/a@1/mod.ts",
+      },
+      "remote": {
+        "https://deno.land/x/a@1/mod.ts": "checksum",
+        "https://esm.sh/a": "checksum",
+      },
+    });
+    let original = LockfileContent::from_json(json.clone()).unwrap();
+    let mut content = LockfileContent::from_json(json).unwrap();
+    let mirror = RegistryMirror::new(vec![
+      UrlPrefixMapping {
+        from: "https://registry.npmjs.org/".to_string(),
+        to: "https://mirror.corp/npm/".to_string(),
+      },
+      UrlPrefixMapping {
+        from: "https://deno.land/".to_string(),
+        to: "https://mirror.corp/deno/".to_string(),
+      },
+    ]);
+    assert_eq!(rewrite(&mut content, &mirror, None).unwrap(), 5);
+    let tarballs = content
+      .packages
+      .npm
+      .values()
+      .map(|p| p.tarball.as_ref().map(|t| t.to_string()))
+      .collect::<Vec<_>>();
+    assert_eq!(
+      tarballs,
+      vec![
+        Some("https://mirror.corp/npm/@scope/a/-/a-1.0.0.tgz".to_string()),
+        Some("https://mirror.corp/npm/b/-/b-1.0.0.tgz".to_string()),
+        Some("https://other.com/c.tgz".to_string()),
+        None,
+      ]
+    );
+    assert_eq!(
+      content
+        .redirects
+        .get("https://mirror.corp/deno/x/a/mod.ts")
+        .unwrap(),
+      "https://mirror.corp/deno/x/a@1/mod.ts"
+    );
+    assert_eq!(
+      content.remote.keys().collect::<Vec<_>>(),
+      vec!["https://esm.sh/a", "https://mirror.corp/deno/x/a@1/mod.ts"]
+    );
+
+    assert_eq!(rewrite(&mut content, &mirror.reversed(), None).unwrap(), 5);
+    assert_eq!(
+      crate::printer::print_v5_content(&content),
+      crate::printer::print_v5_content(&original)
+    );
+  }
+
+  #[test]
+  fn collision_leaves_content_untouched() {
+    let json = serde_json::json!({
+      "version": "5",
+      "npm": {
+        "a@1.0.0": { "integrity": "sha512" },
+      },
+      "remote": {
+        "https://deno.land/x/a@1/mod.ts": "checksum-a",
+        "https://mirror.corp/deno/x/a@1/mod.ts": "checksum-b",
+      },
+    });
+    let mut content = LockfileContent::from_json(json.clone()).unwrap();
+    let mirror = RegistryMirror::new(vec![
+      UrlPrefixMapping {
+        from: "https://registry.npmjs.org/".to_string(),
+        to: "https://mirror.corp/npm/".to_string(),
+      },
+      UrlPrefixMapping {
+        from: "https://deno.land/".to_string(),
+        to: "https://mirror.corp/deno/".to_string(),
+      },
+    ]);
+    assert_eq!(
+      rewrite(&mut content, &mirror, None).unwrap_err(),
+      RegistryUrlCollisionError {
+        section: "remote",
+        first: "https://deno.land/x/a@1/mod.ts".to_string(),
+        second: "https://mirror.corp/deno/x/a@1/mod.ts".to_string(),
+        url: "https://mirror.corp/deno/x/a@1/mod.ts".to_string(),
+      }
+    );
+    assert_eq!(
+      crate::printer::print_v5_content(&content),
+      crate::printer::print_v5_content(
+        &LockfileContent::from_json(json).unwrap()
+      )
+    );
+  }
+}
@@ -0,0 +1,670 @@
+// Copyright 2018-2024 the Deno authors. MIT license.
+
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+use deno_semver::SmallStackString;
+use deno_semver::Version;
+use deno_semver::VersionReq;
+use deno_semver::jsr::JsrDepPackageReq;
+use deno_semver::package::PackageKind;
+use serde::Deserialize;
+use thiserror::Error;
+
+use crate::LockfileContent;
+use crate::LockfilePackageId;
+
+#[derive(Debug, Error)]
+#[error("Failed loading package versions at '{}'", path.display())]
+pub struct PackageVersionsLoadError {
+  pub path: PathBuf,
+  #[source]
+  pub source: PackageVersionsLoadErrorKind,
+}
+
+#[derive(Debug, Error)]
+pub enum PackageVersionsLoadErrorKind {
+  #[error(transparent)]
+  Io(#[from] std::io::Error),
+  #[error(transparent)]
+  Parse(#[from] serde_json::Error),
+  #[error("Invalid package name '{0}'")]
+  InvalidName(String),
+}
+
+/// Versions of a package published to a registry.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PackageVersions {
+  pub versions: Vec<Version>,
+  /// The versions that tags, such as `latest`, point at.
+  pub dist_tags: HashMap<String, Version>,
+}
+
+/// Provides the published versions of packages without resolving
+/// anything, such as from a snapshot of the registries.
+pub trait PackageVersionProvider {
+  /// Gets the package's versions, returning `None` when the package is
+  /// unknown.
+  fn versions(
+    &self,
+    kind: PackageKind,
+    name: &str,
+  ) -> Result<Option<PackageVersions>, PackageVersionsLoadError>;
+}
+
+/// [`PackageVersionProvider`] that reads the registries' package metadata
+/// from a directory laid out like the registries:
+///
+/// - `jsr/@scope/name/meta.json` for jsr packages.
+/// - `npm/name.json` or `npm/@scope/name.json` for npm packages.
+///
+/// Yanked jsr versions and versions that can't be parsed are ignored. The
+/// tags come from npm's `dist-tags` and jsr's `latest`.
+#[derive(Debug, Clone)]
+pub struct DirPackageVersionProvider {
+  dir: PathBuf,
+}
+
+#[derive(Debug, Deserialize)]
+struct PackageMetadata {
+  #[serde(default)]
+  versions: HashMap<String, PackageVersionMetadata>,
+  #[serde(default, rename = "dist-tags")]
+  dist_tags: HashMap<String, String>,
+  /// jsr only has the latest version rather than tags.
+  #[serde(default)]
+  latest: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct PackageVersionMetadata {
+  #[serde(default)]
+  yanked: bool,
+}
+
+impl DirPackageVersionProvider {
+  pub fn new(dir: impl Into<PathBuf>) -> Self {
+    Self { dir: dir.into() }
+  }
+
+  fn metadata_path(
+    &self,
+    kind: PackageKind,
+    name: &str,
+  ) -> Result<PathBuf, PackageVersionsLoadError> {
+    if !is_valid_name(kind, name) {
+      return Err(PackageVersionsLoadError {
+        path: self.dir.clone(),
+        source: PackageVersionsLoadErrorKind::InvalidName(name.to_string()),
+      });
+    }
+    Ok(match kind {
+      PackageKind::Jsr => self.dir.join("jsr").join(name).join("meta.json"),
+      PackageKind::Npm => self.dir.join("npm").join(format!("{}.json", name)),
+    })
+  }
+}
+
+/// Gets if the name is a package name that can be joined to the directory
+/// rather than a path that could point outside of it, such as `../x`.
+fn is_valid_name(kind: PackageKind, name: &str) -> bool {
+  fn is_valid_part(part: &str) -> bool {
+    !part.is_empty()
+      && part != "."
+      && part != ".."
+      && !part.contains(['\\', ':', '\0'])
+  }
+
+  let mut parts = name.split('/');
+  match (parts.next(), parts.next(), parts.next()) {
+    (Some(scope), Some(name), None) => scope
+      .strip_prefix('@')
+      .is_some_and(|scope| is_valid_part(scope) && is_valid_part(name)),
+    // jsr packages are always scoped
+    (Some(name), None, None) => {
+      kind == PackageKind::Npm && !name.starts_with('@') && is_valid_part(name)
+    }
+    _ => false,
+  }
+}
+
+impl PackageVersionProvider for DirPackageVersionProvider {
+  fn versions(
+    &self,
+    kind: PackageKind,
+    name: &str,
+  ) -> Result<Option<PackageVersions>, PackageVersionsLoadError> {
+    let path = self.metadata_path(kind, name)?;
+    let error =
+      |source: PackageVersionsLoadErrorKind| PackageVersionsLoadError {
+        path: path.clone(),
+        source,
+      };
+    let text = match std::fs::read_to_string(&path) {
+      Ok(text) => text,
+      Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
+        return Ok(None);
+      }
+      Err(err) => return Err(error(err.into())),
+    };
+    let metadata: PackageMetadata =
+      serde_json::from_str(&text).map_err(|err| error(err.into()))?;
+    let parse = |version: &str| match kind {
+      PackageKind::Jsr => Version::parse_standard(version).ok(),
+      PackageKind::Npm => Version::parse_from_npm(version).ok(),
+    };
+    let versions = metadata
+      .versions
+      .into_iter()
+      .filter(|(_, metadata)| !metadata.yanked)
+      .filter_map(|(version, _)| parse(&version))
+      .collect();
+    let dist_tags = metadata
+      .dist_tags
+      .into_iter()
+      .chain(metadata.latest.map(|latest| ("latest".to_string(), latest)))
+      .filter_map(|(tag, version)| Some((tag, parse(&version)?)))
+      .collect();
+    Ok(Some(PackageVersions {
+      versions,
+      dist_tags,
+    }))
+  }
+}
+
+/// How far behind the published versions the lockfile is.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct OutdatedReport {
+  /// An entry for each specifier, sorted by specifier.
+  pub specifiers: Vec<OutdatedSpecifier>,
+  /// An entry for each jsr and npm package, including the ones that are
+  /// only transitive dependencies, sorted by package.
+  pub packages: Vec<OutdatedPackage>,
+  /// Specifiers whose locked version can't be parsed, so can't be
+  /// compared to the published versions, sorted by specifier.
+  pub invalid_specifiers: Vec<InvalidLockedSpecifier>,
+  /// npm packages whose version can't be parsed from their id, sorted by
+  /// package.
+  pub invalid_packages: Vec<LockfilePackageId>,
+}
+
+impl OutdatedReport {
+  /// Gets the specifiers that could be updated without changing their
+  /// requirement.
+  pub fn behind_specifiers(&self) -> impl Iterator<Item = &OutdatedSpecifier> {
+    self.specifiers.iter().filter(|s| s.is_behind())
+  }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OutdatedSpecifier {
+  pub req: JsrDepPackageReq,
+  pub locked: Version,
+  /// The latest version that satisfies the requirement or `None` when
+  /// none do, the requirement is a tag the provider doesn't know, or the
+  /// package is unknown.
+  pub latest_satisfying: Option<Version>,
+  /// The latest version or `None` when the package is unknown.
+  pub latest: Option<Version>,
+}
+
+impl OutdatedSpecifier {
+  /// Gets if a later version satisfies the requirement.
+  pub fn is_behind(&self) -> bool {
+    self
+      .latest_satisfying
+      .as_ref()
+      .is_some_and(|latest| *latest > self.locked)
+  }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InvalidLockedSpecifier {
+  pub req: JsrDepPackageReq,
+  /// The locked version as it is in the lockfile.
+  pub version: SmallStackString,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OutdatedPackage {
+  pub id: LockfilePackageId,
+  pub locked: Version,
+  /// The latest version that satisfies each specifier resolving to the
+  /// package, sorted by specifier. Specifiers can disagree, such as
+  /// `npm:foo@beta` and `npm:foo@latest`, so each is reported on its own.
+  ///
+  /// Empty when no specifier resolves to the package, such as for npm
+  /// packages only depended on by other npm packages.
+  pub latest_satisfying: Vec<SatisfyingVersion>,
+  /// The latest version or `None` when the package is unknown.
+  pub latest: Option<Version>,
+}
+
+impl OutdatedPackage {
+  /// Gets if there's a later version, even if its specifiers don't allow
+  /// updating to it.
+  pub fn is_behind(&self) -> bool {
+    self
+      .latest
+      .as_ref()
+      .is_some_and(|latest| *latest > self.locked)
+  }
+}
+
+/// The latest version that satisfies a specifier's requirement.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SatisfyingVersion {
+  pub req: JsrDepPackageReq,
+  /// The latest satisfying version or `None` when none do, the
+  /// requirement is a tag the provider doesn't know, or the package is
+  /// unknown.
+  pub version: Option<Version>,
+}
+
+/// Versions of a package looked up from the provider.
+struct KnownVersions {
+  /// Sorted from highest to lowest.
+  versions: Vec<Version>,
+  dist_tags: HashMap<String, Version>,
+}
+
+impl KnownVersions {
+  /// Gets the version the `latest` tag points at, otherwise the highest
+  /// stable version, or the highest pre-release when there are only
+  /// pre-releases.
+  fn latest(&self) -> Option<Version> {
+    self
+      .dist_tags
+      .get("latest")
+      .or_else(|| self.versions.iter().find(|v| v.pre.is_empty()))
+      .or_else(|| self.versions.first())
+      .cloned()
+  }
+
+  /// Gets the highest version that satisfies the requirement, where a tag
+  /// is only satisfied by the version it points at.
+  fn latest_satisfying(&self, req: &VersionReq) -> Option<Version> {
+    if let Some(tag) = req.tag() {
+      return self.dist_tags.get(tag).cloned();
+    }
+    self.versions.iter().find(|v| req.matches(v)).cloned()
+  }
+}
+
+type KnownVersionsCache = HashMap<(PackageKind, String), Option<KnownVersions>>;
+
+/// Gets the package's versions from the provider the first time the
+/// package is seen and from the cache after that.
+///
+/// Packages whose versions fail to load are treated as unknown and the
+/// error is kept.
+fn lookup<'a>(
+  known: &'a mut KnownVersionsCache,
+  provider: &dyn PackageVersionProvider,
+  kind: PackageKind,
+  name: &str,
+  errors: &mut Vec<PackageVersionsLoadError>,
+) -> Option<&'a KnownVersions> {
+  let key = (kind, name.to_string());
+  if !known.contains_key(&key) {
+    let versions = provider
+      .versions(kind, name)
+      .unwrap_or_else(|err| {
+        errors.push(err);
+        None
+      })
+      .map(|mut package| {
+        package.versions.sort_by(|a, b| b.cmp(a));
+        KnownVersions {
+          versions: package.versions,
+          dist_tags: package.dist_tags,
+        }
+      });
+    known.insert(key.clone(), versions);
+  }
+  known[&key].as_ref()
+}
+
+pub(crate) fn report(
+  content: &LockfileContent,
+  provider: &dyn PackageVersionProvider,
+) -> (OutdatedReport, Vec<PackageVersionsLoadError>) {
+  let mut known = KnownVersionsCache::new();
+  let mut errors = Vec::new();
+
+  let mut satisfying_by_package: HashMap<
+    LockfilePackageId,
+    Vec<SatisfyingVersion>,
+  > = HashMap::new();
+  let mut specifiers = Vec::new();
+  let mut invalid_specifiers = Vec::new();
+  for (req, version) in &content.packages.specifiers {
+    let id = LockfilePackageId::from_specifier(req, version);
+    let Some((id, (name, locked))) = id
+      .as_ref()
+      .and_then(|id| Some((id.clone(), id.name_and_version()?)))
+    else {
+      invalid_specifiers.push(InvalidLockedSpecifier {
+        req: req.clone(),
+        version: version.clone(),
+      });
+      continue;
+    };
+    let versions = lookup(&mut known, provider, req.kind, name, &mut errors);
+    let latest_satisfying =
+      versions.and_then(|v| v.latest_satisfying(&req.req.version_req));
+    specifiers.push(OutdatedSpecifier {
+      req: req.clone(),
+      locked,
+      latest_satisfying: latest_satisfying.clone(),
+      latest: versions.and_then(|v| v.latest()),
+    });
+    satisfying_by_package
+      .entry(id)
+      .or_default()
+      .push(SatisfyingVersion {
+        req: req.clone(),
+        version: latest_satisfying,
+      });
+  }
+  specifiers.sort_by_cached_key(|s| s.req.to_string_normalized());
+  invalid_specifiers.sort_by_cached_key(|s| s.req.to_string_normalized());
+
+  let ids = content
+    .packages
+    .jsr
+    .keys()
+    .map(|nv| LockfilePackageId::Jsr(nv.clone()))
+    .chain(
+      content
+        .packages
+        .npm
+        .keys()
+        .map(|id| LockfilePackageId::Npm(id.clone())),
+    );
+  let mut packages = Vec::new();
+  let mut invalid_packages = Vec::new();
+  for id in ids {
+    let Some((name, locked)) = id.name_and_version() else {
+      invalid_packages.push(id);
+      continue;
+    };
+    let versions = lookup(&mut known, provider, id.kind(), name, &mut errors);
+    let mut latest_satisfying =
+      satisfying_by_package.remove(&id).unwrap_or_default();
+    latest_satisfying.sort_by_cached_key(|s| s.req.to_string_normalized());
+    packages.push(OutdatedPackage {
+      latest_satisfying,
+      latest: versions.and_then(|v| v.latest()),
+      id,
+      locked,
+    });
+  }
+
+  let report = OutdatedReport {
+    specifiers,
+    packages,
+    invalid_specifiers,
+    invalid_packages,
+  };
+  (report, errors)
+}
+
+#[cfg(test)]
+mod test {
+  use pretty_assertions::assert_eq;
+
+  use super::*;
+  use crate::test_util::TempDir;
+
+  fn version(text: &str) -> Version {
+    Version::parse_standard(text).unwrap()
+  }
+
+  #[test]
+  fn reports_outdated_packages() {
+    let temp_dir = TempDir::new();
+    let dir = temp_dir.path();
+    let write = |path: &str, text: String| {
+      let path = dir.join(path);
+      std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+      std::fs::write(path, text).unwrap();
+    };
+    write(
+      "jsr/@scope/a/meta.json",
+      serde_json::json!({
+        "versions": {
+          "1.0.0": {},
+          "1.1.0": {},
+          "1.2.0": { "yanked": true },
+          "2.0.0": {},
+        },
+      })
+      .to_string(),
+    );
+    write(
+      "npm/b.json",
+      serde_json::json!({
+        "versions": { "1.0.0": {}, "1.0.1": {}, "2.0.0-beta.1": {} },
+        "dist-tags": { "latest": "1.0.1", "beta": "2.0.0-beta.1" },
+      })
+      .to_string(),
+    );
+    write(
+      "npm/@types/c.json",
+      serde_json::json!({ "versions": { "1.0.0": {}, "1.1.0": {} } })
+        .to_string(),
+    );
+    write("npm/broken.json", "{".to_string());
+    let mut content = LockfileContent::from_json(serde_json::json!({
+      "version": "5",
+      "specifiers": {
+        "jsr:@scope/a@1": "1.0.0",
+        "npm:b@1.0.1": "1.0.1",
+        "npm:b@beta": "1.0.1",
+        "npm:b@latest": "1.0.1",
+        "npm:broken@1": "1.0.0",
+        "npm:unknown@1": "1.0.0",
+      },
+      "jsr": {
+        "@scope/a@1.0.0": {
+          "integrity": "sha256",
+          "dependencies": ["npm:b@1.0.1"],
+        },
+      },
+      "npm": {
+        "b@1.0.1": { "integrity": "sha512", "dependencies": ["@types/c"] },
+        "@types/c@1.0.0": { "integrity": "sha512" },
+        "broken@1.0.0": { "integrity": "sha512" },
+        "unknown@1.0.0": { "integrity": "sha512" },
+      },
+    }))
+    .unwrap();
+    // locked versions that can't be compared
+    let req = |text: &str| JsrDepPackageReq::from_str(text).unwrap();
+    content
+      .packages
+      .specifiers
+      .insert(req("npm:invalid@1"), "not-a-version".into());
+    content
+      .packages
+      .specifiers
+      .insert(req("jsr:@scope/x@1"), "1".into());
+    content.packages.npm.insert(
+      "invalid@not-a-version".into(),
+      content.packages.npm["broken@1.0.0"].clone(),
+    );
+
+    let provider = DirPackageVersionProvider::new(dir);
+    let (report, errors) = report(&content, &provider);
+    // the file that can't be parsed doesn't stop the others from loading
+    assert_eq!(
+      errors.iter().map(|e| e.path.clone()).collect::<Vec<_>>(),
+      vec![dir.join("npm/broken.json")]
+    );
+
+    assert_eq!(
+      report.specifiers,
+      vec![
+        OutdatedSpecifier {
+          req: req("jsr:@scope/a@1"),
+          locked: version("1.0.0"),
+          latest_satisfying: Some(version("1.1.0")),
+          latest: Some(version("2.0.0")),
+        },
+        OutdatedSpecifier {
+          req: req("npm:b@1.0.1"),
+          locked: version("1.0.1"),
+          latest_satisfying: Some(version("1.0.1")),
+          latest: Some(version("1.0.1")),
+        },
+        OutdatedSpecifier {
+          req: req("npm:b@beta"),
+          locked: version("1.0.1"),
+          latest_satisfying: Some(version("2.0.0-beta.1")),
+          latest: Some(version("1.0.1")),
+        },
+        OutdatedSpecifier {
+          req: req("npm:b@latest"),
+          locked: version("1.0.1"),
+          latest_satisfying: Some(version("1.0.1")),
+          latest: Some(version("1.0.1")),
+        },
+        OutdatedSpecifier {
+          req: req("npm:broken@1"),
+          locked: version("1.0.0"),
+          latest_satisfying: None,
+          latest: None,
+        },
+        OutdatedSpecifier {
+          req: req("npm:unknown@1"),
+          locked: version("1.0.0"),
+          latest_satisfying: None,
+          latest: None,
+        },
+      ]
+    );
+    assert_eq!(
+      report
+        .behind_specifiers()
+        .map(|s| s.req.to_string_normalized())
+        .collect::<Vec<_>>(),
+      vec!["jsr:@scope/a@1", "npm:b@beta"]
+    );
+    assert_eq!(
+      report.packages,
+      vec![
+        OutdatedPackage {
+          id: LockfilePackageId::Jsr(
+            deno_semver::package::PackageNv::from_str("@scope/a@1.0.0")
+              .unwrap()
+          ),
+          locked: version("1.0.0"),
+          latest_satisfying: vec![SatisfyingVersion {
+            req: req("jsr:@scope/a@1"),
+            version: Some(version("1.1.0")),
+          }],
+          latest: Some(version("2.0.0")),
+        },
+        OutdatedPackage {
+          id: LockfilePackageId::Npm("@types/c@1.0.0".into()),
+          locked: version("1.0.0"),
+          latest_satisfying: Vec::new(),
+          latest: Some(version("1.1.0")),
+        },
+        // the tags point at different versions, so each is reported
+        OutdatedPackage {
+          id: LockfilePackageId::Npm("b@1.0.1".into()),
+          locked: version("1.0.1"),
+          latest_satisfying: vec![
+            SatisfyingVersion {
+              req: req("npm:b@1.0.1"),
+              version: Some(version("1.0.1")),
+            },
+            SatisfyingVersion {
+              req: req("npm:b@beta"),
+              version: Some(version("2.0.0-beta.1")),
+            },
+            SatisfyingVersion {
+              req: req("npm:b@latest"),
+              version: Some(version("1.0.1")),
+            },
+          ],
+          latest: Some(version("1.0.1")),
+        },
+        OutdatedPackage {
+          id: LockfilePackageId::Npm("broken@1.0.0".into()),
+          locked: version("1.0.0"),
+          latest_satisfying: vec![SatisfyingVersion {
+            req: req("npm:broken@1"),
+            version: None,
+          }],
+          latest: None,
+        },
+        OutdatedPackage {
+          id: LockfilePackageId::Npm("unknown@1.0.0".into()),
+          locked: version("1.0.0"),
+          latest_satisfying: vec![SatisfyingVersion {
+            req: req("npm:unknown@1"),
+            version: None,
+          }],
+          latest: None,
+        },
+      ]
+    );
+    assert_eq!(
+      report.invalid_specifiers,
+      vec![
+        InvalidLockedSpecifier {
+          req: req("jsr:@scope/x@1"),
+          version: "1".into(),
+        },
+        InvalidLockedSpecifier {
+          req: req("npm:invalid@1"),
+          version: "not-a-version".into(),
+        },
+      ]
+    );
+    assert_eq!(
+      report.invalid_packages,
+      vec![LockfilePackageId::Npm("invalid@not-a-version".into())]
+    );
+    assert_eq!(
+      report
+        .packages
+        .iter()
+        .filter(|p| p.is_behind())
+        .map(|p| p.id.to_string())
+        .collect::<Vec<_>>(),
+      vec!["jsr:@scope/a@1.0.0", "npm:@types/c@1.0.0"]
+    );
+  }
+
+  #[test]
+  fn rejects_names_outside_dir() {
+    let temp_dir = TempDir::new();
+    let provider = DirPackageVersionProvider::new(temp_dir.path());
+    for (kind, name) in [
+      (PackageKind::Npm, "../x"),
+      (PackageKind::Npm, "@scope/../../x"),
+      (PackageKind::Npm, "@../x"),
+      (PackageKind::Npm, "a\\b"),
+      (PackageKind::Jsr, "x"),
+      (PackageKind::Jsr, "@scope/.."),
+    ] {
+      let err = provider.versions(kind, name).unwrap_err();
+      assert!(
+        matches!(
+          &err.source,
+          PackageVersionsLoadErrorKind::InvalidName(n) if n == name
+        ),
+        "{name}"
+      );
+    }
+    assert_eq!(provider.versions(PackageKind::Npm, "a.b").unwrap(), None);
+    assert_eq!(
+      provider.versions(PackageKind::Jsr, "@scope/a").unwrap(),
+      None
+    );
+  }
+}
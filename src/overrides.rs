@@ -0,0 +1,99 @@
+// Copyright 2018-2024 the Deno authors. MIT license.
+
+use std::collections::HashMap;
+use std::collections::HashSet;
+
+use deno_semver::VersionReq;
+use deno_semver::jsr::JsrDepPackageReq;
+use deno_semver::package::PackageKind;
+
+use crate::LockfileContent;
+use crate::LockfilePackageId;
+use crate::dependency_paths::DependencyPathFinder;
+use crate::workspace_report;
+
+/// An override rule, such as `"npm:foo@^1": "1.2.0"`, along with the
+/// versions it forced before and after it changed.
+struct ChangedRule {
+  req: JsrDepPackageReq,
+  forced: Vec<VersionReq>,
+}
+
+impl ChangedRule {
+  /// Gets if the rule applies to the package or forced it to its version,
+  /// since a package locked at a forced version no longer satisfies the
+  /// rule itself, such as `npm:foo@^1` forced to `2.0.0`.
+  fn matches(&self, id: &LockfilePackageId) -> bool {
+    let Some((name, version)) = id.name_and_version() else {
+      return false;
+    };
+    self.req.kind == id.kind()
+      && self.req.req.name == name
+      && (self.req.req.version_req.tag().is_some()
+        || self.req.req.version_req.matches(&version)
+        || self.forced.iter().any(|forced| forced.matches(&version)))
+  }
+}
+
+/// Gets the names of the override rules that aren't package requirements,
+/// which can't be matched against the lockfile's packages.
+pub(crate) fn invalid_rules(
+  overrides: &HashMap<String, String>,
+) -> impl Iterator<Item = &String> {
+  overrides
+    .keys()
+    .filter(|name| JsrDepPackageReq::from_str(name).is_err())
+}
+
+/// Gets the specifiers that resolve to a package matched by one of the
+/// override rules that changed or to a package that depends on one, which
+/// need to be resolved again for the change to take effect.
+///
+/// Rules that aren't package requirements are ignored here and reported by
+/// [`crate::LockfileContent::validate`] instead.
+pub(crate) fn affected_specifiers(
+  content: &LockfileContent,
+  old: &HashMap<String, String>,
+  new: &HashMap<String, String>,
+) -> HashSet<JsrDepPackageReq> {
+  let rules = workspace_report::changed_names(old, new)
+    .into_iter()
+    .filter_map(|name| {
+      let req = JsrDepPackageReq::from_str(&name).ok()?;
+      let forced = [old.get(&name), new.get(&name)]
+        .into_iter()
+        .flatten()
+        .filter_map(|value| match req.kind {
+          PackageKind::Jsr => VersionReq::parse_from_specifier(value).ok(),
+          PackageKind::Npm => VersionReq::parse_from_npm(value).ok(),
+        })
+        .collect();
+      Some(ChangedRule { req, forced })
+    })
+    .collect::<Vec<_>>();
+  if rules.is_empty() {
+    return HashSet::new();
+  }
+  let packages = &content.packages;
+  let matched = packages
+    .jsr
+    .keys()
+    .map(|nv| LockfilePackageId::Jsr(nv.clone()))
+    .chain(
+      packages
+        .npm
+        .keys()
+        .map(|id| LockfilePackageId::Npm(id.clone())),
+    )
+    .filter(|id| rules.iter().any(|rule| rule.matches(id)));
+  let affected = DependencyPathFinder::new(content).with_dependents(matched);
+  packages
+    .specifiers
+    .iter()
+    .filter(|(req, version)| {
+      LockfilePackageId::from_specifier(req, version)
+        .is_some_and(|id| affected.contains(&id))
+    })
+    .map(|(req, _)| req.clone())
+    .collect()
+}
@@ -17,6 +17,7 @@ use crate::LockfileContent;
 use crate::LockfileLinkContent;
 use crate::LockfilePackageJsonContent;
 use crate::NpmPackageInfo;
+use crate::UnknownFields;
 use crate::WorkspaceConfigContent;
 use crate::WorkspaceMemberConfigContent;
 
@@ -25,6 +26,8 @@ struct SerializedJsrPkg<'a> {
   integrity: &'a str,
   #[serde(skip_serializing_if = "Vec::is_empty")]
   dependencies: Vec<StackString>,
+  #[serde(flatten)]
+  unknown_fields: &'a UnknownFields,
 }
 
 #[derive(Serialize)]
@@ -51,6 +54,8 @@ struct SerializedNpmPkg<'a> {
   bin: bool,
   #[serde(skip_serializing_if = "Option::is_none")]
   tarball: Option<&'a str>,
+  #[serde(flatten)]
+  unknown_fields: &'a UnknownFields,
 }
 
 fn is_false(value: &bool) -> bool {
@@ -131,11 +136,17 @@ struct SerializedWorkspaceConfigContent<'a> {
   #[serde(skip_serializing_if = "BTreeMap::is_empty")]
   #[serde(default)]
   pub links: BTreeMap<&'a str, SerializedLockfileLinkContent>,
+  #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+  #[serde(default)]
+  pub overrides: BTreeMap<&'a str, &'a str>,
 }
 
 impl SerializedWorkspaceConfigContent<'_> {
   pub fn is_empty(&self) -> bool {
-    self.root.is_empty() && self.members.is_empty() && self.links.is_empty()
+    self.root.is_empty()
+      && self.members.is_empty()
+      && self.links.is_empty()
+      && self.overrides.is_empty()
   }
 }
 
@@ -155,6 +166,9 @@ struct LockfileV5<'a> {
   remote: &'a BTreeMap<String, String>,
   #[serde(skip_serializing_if = "SerializedWorkspaceConfigContent::is_empty")]
   workspace: SerializedWorkspaceConfigContent<'a>,
+  // unknown keys always go last so they have a stable position
+  #[serde(flatten)]
+  unknown_fields: &'a UnknownFields,
 }
 
 pub fn print_v5_content(content: &LockfileContent) -> String {
@@ -210,6 +224,7 @@ pub fn print_v5_content(content: &LockfileContent) -> String {
               dependencies.sort();
               dependencies
             },
+            unknown_fields: &value.unknown_fields,
           },
         )
       })
@@ -288,6 +303,7 @@ pub fn print_v5_content(content: &LockfileContent) -> String {
             deprecated: value.deprecated,
             scripts: value.scripts,
             bin: value.bin,
+            unknown_fields: &value.unknown_fields,
           },
         )
       })
@@ -352,6 +368,11 @@ pub fn print_v5_content(content: &LockfileContent) -> String {
         .iter()
         .map(|(key, value)| (key.as_str(), handle_patch_content(value)))
         .collect(),
+      overrides: content
+        .overrides
+        .iter()
+        .map(|(key, value)| (key.as_str(), value.as_str()))
+        .collect(),
     }
   }
 
@@ -370,6 +391,7 @@ pub fn print_v5_content(content: &LockfileContent) -> String {
     redirects: &content.redirects,
     remote: &content.remote,
     workspace: handle_workspace(&content.workspace),
+    unknown_fields: &content.unknown_fields,
   };
   let mut writer = Vec::with_capacity(1024);
   let mut serializer =
@@ -0,0 +1,256 @@
+// Copyright 2018-2024 the Deno authors. MIT license.
+
+use std::collections::BTreeMap;
+use std::collections::BTreeSet;
+use std::collections::HashMap;
+
+use thiserror::Error;
+
+use crate::LockfileMutation;
+
+/// A specifier resolved through the lockfile's redirects.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ResolvedRedirect {
+  /// The final url after following all the redirects.
+  pub url: String,
+  /// Checksum of the final url in the remote section.
+  pub checksum: String,
+  /// The urls that were redirected from, starting with the specifier.
+  pub hops: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum RedirectChainError {
+  #[error("Redirect cycle: {}", format_cycle(urls))]
+  Cycle {
+    /// Urls in the cycle in order, starting with the lowest sorting one.
+    urls: Vec<String>,
+  },
+  #[error(
+    "Redirects from '{specifier}' end at '{url}', which is not in the lockfile"
+  )]
+  Dangling { specifier: String, url: String },
+}
+
+fn format_cycle(urls: &[String]) -> String {
+  let mut text = urls.join(" -> ");
+  if let Some(first) = urls.first() {
+    text.push_str(" -> ");
+    text.push_str(first);
+  }
+  text
+}
+
+enum Chain<'a> {
+  /// Urls from the start through to the final url.
+  End(Vec<&'a str>),
+  /// The cycle that was reached, starting with the lowest sorting url.
+  Cycle(Vec<&'a str>),
+}
+
+/// Follows the redirects from the start url.
+fn follow<'a>(
+  redirects: &'a BTreeMap<String, String>,
+  start: &'a str,
+) -> Chain<'a> {
+  let mut urls = vec![start];
+  let mut indexes = HashMap::from([(start, 0)]);
+  let mut current = start;
+  while let Some(next) = redirects.get(current) {
+    if let Some(index) = indexes.get(next.as_str()) {
+      let mut cycle = urls.split_off(*index);
+      let min_index = cycle
+        .iter()
+        .enumerate()
+        .min_by_key(|(_, url)| **url)
+        .map(|(i, _)| i)
+        .unwrap();
+      cycle.rotate_left(min_index);
+      return Chain::Cycle(cycle);
+    }
+    indexes.insert(next, urls.len());
+    urls.push(next);
+    current = next;
+  }
+  Chain::End(urls)
+}
+
+/// Gets the url that the redirects from the start url end at or `None`
+/// when they lead to a cycle.
+pub(crate) fn final_url<'a>(
+  redirects: &'a BTreeMap<String, String>,
+  start: &'a str,
+) -> Option<&'a str> {
+  match follow(redirects, start) {
+    Chain::End(urls) => urls.last().copied(),
+    Chain::Cycle(_) => None,
+  }
+}
+
+/// Gets the start url and every url it's redirected to.
+pub(crate) fn visited_urls<'a>(
+  redirects: &'a BTreeMap<String, String>,
+  start: &'a str,
+) -> Vec<&'a str> {
+  let mut urls = vec![start];
+  let mut current = start;
+  while let Some(next) = redirects.get(current) {
+    if urls.contains(&next.as_str()) {
+      break;
+    }
+    urls.push(next);
+    current = next;
+  }
+  urls
+}
+
+/// Gets the distinct redirect cycles.
+pub(crate) fn cycles(redirects: &BTreeMap<String, String>) -> Vec<Vec<&str>> {
+  let mut found = BTreeSet::new();
+  for from in redirects.keys() {
+    if let Chain::Cycle(cycle) = follow(redirects, from) {
+      found.insert(cycle);
+    }
+  }
+  found.into_iter().collect()
+}
+
+pub(crate) fn resolve(
+  redirects: &BTreeMap<String, String>,
+  remote: &BTreeMap<String, String>,
+  specifier: &str,
+) -> Result<ResolvedRedirect, RedirectChainError> {
+  match follow(redirects, specifier) {
+    Chain::End(mut urls) => {
+      let url = urls.pop().unwrap();
+      match remote.get(url) {
+        Some(checksum) => Ok(ResolvedRedirect {
+          url: url.to_string(),
+          checksum: checksum.clone(),
+          hops: urls.into_iter().map(|u| u.to_string()).collect(),
+        }),
+        None => Err(RedirectChainError::Dangling {
+          specifier: specifier.to_string(),
+          url: url.to_string(),
+        }),
+      }
+    }
+    Chain::Cycle(cycle) => Err(RedirectChainError::Cycle {
+      urls: cycle.into_iter().map(|u| u.to_string()).collect(),
+    }),
+  }
+}
+
+/// Gets the cycles in the redirects and the redirects that don't end at
+/// a remote url.
+pub(crate) fn chain_errors(
+  redirects: &BTreeMap<String, String>,
+  remote: &BTreeMap<String, String>,
+) -> Vec<RedirectChainError> {
+  let mut errors = Vec::new();
+  for from in redirects.keys() {
+    if let Chain::End(urls) = follow(redirects, from) {
+      let url = urls.last().unwrap();
+      if !remote.contains_key(*url) {
+        errors.push(RedirectChainError::Dangling {
+          specifier: from.clone(),
+          url: url.to_string(),
+        });
+      }
+    }
+  }
+  errors.extend(cycles(redirects).into_iter().map(|cycle| {
+    RedirectChainError::Cycle {
+      urls: cycle.into_iter().map(|u| u.to_string()).collect(),
+    }
+  }));
+  errors
+}
+
+/// Points each redirect that's part of a chain directly at the chain's
+/// final url, returning the number of redirects that were changed.
+pub(crate) fn collapse(
+  redirects: &mut BTreeMap<String, String>,
+  mut journal: Option<&mut Vec<LockfileMutation>>,
+) -> usize {
+  let mut final_urls = Vec::new();
+  for (from, to) in redirects.iter() {
+    if let Chain::End(urls) = follow(redirects, from) {
+      let url = *urls.last().unwrap();
+      if url != to {
+        final_urls.push((from.clone(), url.to_string()));
+      }
+    }
+  }
+  let count = final_urls.len();
+  for (from, url) in final_urls {
+    let old = redirects.insert(from.clone(), url.clone());
+    if let Some(journal) = journal.as_deref_mut() {
+      journal.push(LockfileMutation::Redirect {
+        from,
+        old,
+        new: Some(url),
+      });
+    }
+  }
+  count
+}
+
+#[cfg(test)]
+mod test {
+  use pretty_assertions::assert_eq;
+
+  use super::*;
+
+  fn map(entries: &[(&str, &str)]) -> BTreeMap<String, String> {
+    entries
+      .iter()
+      .map(|(k, v)| (k.to_string(), v.to_string()))
+      .collect()
+  }
+
+  #[test]
+  fn resolves_chains() {
+    let mut redirects = map(&[
+      ("https://a.com/1", "https://a.com/2"),
+      ("https://a.com/2", "https://a.com/3"),
+      ("https://a.com/dangling", "https://a.com/missing"),
+      ("https://a.com/loop_c", "https://a.com/loop_b"),
+      ("https://a.com/loop_b", "https://a.com/loop_c"),
+      ("https://a.com/into_loop", "https://a.com/loop_c"),
+    ]);
+    let remote = map(&[("https://a.com/3", "checksum")]);
+
+    assert_eq!(
+      resolve(&redirects, &remote, "https://a.com/1"),
+      Ok(ResolvedRedirect {
+        url: "https://a.com/3".to_string(),
+        checksum: "checksum".to_string(),
+        hops: vec![
+          "https://a.com/1".to_string(),
+          "https://a.com/2".to_string()
+        ],
+      })
+    );
+    assert_eq!(
+      resolve(&redirects, &remote, "https://a.com/3")
+        .unwrap()
+        .hops,
+      Vec::<String>::new()
+    );
+    assert_eq!(
+      chain_errors(&redirects, &remote)
+        .into_iter()
+        .map(|e| e.to_string())
+        .collect::<Vec<_>>(),
+      vec![
+        "Redirects from 'https://a.com/dangling' end at 'https://a.com/missing', which is not in the lockfile",
+        "Redirect cycle: https://a.com/loop_b -> https://a.com/loop_c -> https://a.com/loop_b",
+      ]
+    );
+
+    assert_eq!(collapse(&mut redirects, None), 1);
+    assert_eq!(redirects.get("https://a.com/1").unwrap(), "https://a.com/3");
+    assert_eq!(collapse(&mut redirects, None), 0);
+  }
+}
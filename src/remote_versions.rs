@@ -0,0 +1,178 @@
+// Copyright 2018-2024 the Deno authors. MIT license.
+
+use std::collections::BTreeMap;
+
+use deno_semver::Version;
+
+/// A remote module that's in the lockfile at multiple versions, such as
+/// `https://deno.land/x/oak@v12.1.0/mod.ts` and
+/// `https://deno.land/x/oak@v12.6.1/mod.ts`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DuplicateRemoteModule {
+  pub host: String,
+  /// The module's name including any path before it, such as `x/oak`
+  /// or `std`.
+  pub name: String,
+  /// The versions sorted from lowest to highest.
+  pub versions: Vec<RemoteModuleVersion>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RemoteModuleVersion {
+  pub version: String,
+  /// Number of remote urls for this version.
+  pub file_count: usize,
+}
+
+struct VersionedUrl<'a> {
+  host: &'a str,
+  name: String,
+  version: &'a str,
+}
+
+/// Parses a url with a version in its path, such as
+/// `https://deno.land/x/oak@v12.1.0/mod.ts` or
+/// `https://esm.sh/v135/@scope/pkg@1.0.0/index.js`.
+fn parse_versioned_url(url: &str) -> Option<VersionedUrl<'_>> {
+  let (_, rest) = url.split_once("://")?;
+  let (host, path) = rest.split_once('/')?;
+  let path = path.split(['?', '#']).next()?;
+  let mut segments = path.split('/').peekable();
+  // esm.sh urls may have a build version or "stable" before the package
+  if host == "esm.sh"
+    && segments.peek().is_some_and(|s| {
+      *s == "stable"
+        || s.strip_prefix('v').is_some_and(|n| {
+          !n.is_empty() && n.chars().all(|c| c.is_ascii_digit())
+        })
+    })
+  {
+    segments.next();
+  }
+  let mut name_parts = Vec::new();
+  for segment in segments {
+    let (name, version) = match segment.strip_prefix('@') {
+      // a scope is followed by the name, such as @scope/pkg@1.0.0
+      Some(_) if !segment[1..].contains('@') => {
+        name_parts.push(segment);
+        continue;
+      }
+      Some(scoped) => {
+        let (name, version) = scoped.split_once('@')?;
+        (&segment[..name.len() + 1], version)
+      }
+      None => match segment.split_once('@') {
+        Some((name, version)) if !name.is_empty() => (name, version),
+        _ => {
+          name_parts.push(segment);
+          continue;
+        }
+      },
+    };
+    if version.is_empty() {
+      return None;
+    }
+    name_parts.push(name);
+    return Some(VersionedUrl {
+      host,
+      name: name_parts.join("/"),
+      version,
+    });
+  }
+  None
+}
+
+/// Groups the remote urls by module and gets the ones that are in the
+/// lockfile at more than one version.
+pub(crate) fn find_duplicates(
+  remote: &BTreeMap<String, String>,
+) -> Vec<DuplicateRemoteModule> {
+  let mut modules: BTreeMap<(&str, String), BTreeMap<&str, usize>> =
+    BTreeMap::new();
+  for url in remote.keys() {
+    if let Some(versioned) = parse_versioned_url(url) {
+      *modules
+        .entry((versioned.host, versioned.name))
+        .or_default()
+        .entry(versioned.version)
+        .or_default() += 1;
+    }
+  }
+  modules
+    .into_iter()
+    .filter(|(_, versions)| versions.len() > 1)
+    .map(|((host, name), versions)| {
+      let mut versions = versions
+        .into_iter()
+        .map(|(version, file_count)| RemoteModuleVersion {
+          version: version.to_string(),
+          file_count,
+        })
+        .collect::<Vec<_>>();
+      versions.sort_by_cached_key(|v| {
+        let text = v.version.strip_prefix('v').unwrap_or(&v.version);
+        (Version::parse_standard(text).ok(), v.version.clone())
+      });
+      DuplicateRemoteModule {
+        host: host.to_string(),
+        name,
+        versions,
+      }
+    })
+    .collect()
+}
+
+#[cfg(test)]
+mod test {
+  use pretty_assertions::assert_eq;
+
+  use super::*;
+
+  #[test]
+  fn finds_duplicates() {
+    let remote = [
+      "https://deno.land/x/oak@v12.1.0/mod.ts",
+      "https://deno.land/x/oak@v12.1.0/router.ts",
+      "https://deno.land/x/oak@v12.6.1/mod.ts",
+      "https://deno.land/x/oak@v9.0.0/mod.ts",
+      "https://deno.land/x/other@v1.0.0/mod.ts",
+      "https://deno.land/std@0.200.0/path/mod.ts",
+      "https://deno.land/std@0.190.0/path/mod.ts",
+      "https://esm.sh/v135/@scope/pkg@1.0.0/index.js",
+      "https://esm.sh/@scope/pkg@2.0.0",
+      "https://esm.sh/preact@10.0.0",
+      "https://example.com/mod.ts",
+    ]
+    .into_iter()
+    .map(|url| (url.to_string(), "checksum".to_string()))
+    .collect();
+    let version = |version: &str, file_count| RemoteModuleVersion {
+      version: version.to_string(),
+      file_count,
+    };
+    assert_eq!(
+      find_duplicates(&remote),
+      vec![
+        DuplicateRemoteModule {
+          host: "deno.land".to_string(),
+          name: "std".to_string(),
+          versions: vec![version("0.190.0", 1), version("0.200.0", 1)],
+        },
+        DuplicateRemoteModule {
+          host: "deno.land".to_string(),
+          name: "x/oak".to_string(),
+          versions: vec![
+            version("v9.0.0", 1),
+            version("v12.1.0", 2),
+            version("v12.6.1", 1),
+          ],
+        },
+        DuplicateRemoteModule {
+          host: "esm.sh".to_string(),
+          name: "@scope/pkg".to_string(),
+          versions: vec![version("1.0.0", 1), version("2.0.0", 1)],
+        },
+      ]
+    );
+  }
+}
@@ -0,0 +1,441 @@
+// Copyright 2018-2024 the Deno authors. MIT license.
+
+use std::collections::HashSet;
+use std::ops::Deref;
+
+use deno_semver::SmallStackString;
+use deno_semver::jsr::JsrDepPackageReq;
+use deno_semver::package::PackageNv;
+
+use crate::Lockfile;
+use crate::LockfileMutation;
+use crate::NpmPackageLockfileInfo;
+use crate::RegistryMirror;
+use crate::RegistryUrlCollisionError;
+use crate::RemovedPackages;
+use crate::RemovedRemotes;
+use crate::RepairEdit;
+use crate::SetWorkspaceConfigOptions;
+use crate::WorkspaceConfigContent;
+use crate::WorkspaceConfigReport;
+
+/// Edits to a [`Lockfile`] that are kept when committed and undone when
+/// rolled back.
+///
+/// Dropping the transaction without committing it rolls it back, so an
+/// error returned part way through a batch of edits leaves the lockfile
+/// as it was.
+///
+/// Only the methods that edit the lockfile's content are available, so
+/// the lockfile can't be saved or reloaded part way through. The edits
+/// are recorded in the lockfile's journal, which is what's used to undo
+/// them.
+pub struct LockfileTransaction<'a> {
+  lockfile: &'a mut Lockfile,
+  state: Option<RollbackState>,
+}
+
+struct RollbackState {
+  has_content_changed: bool,
+  /// Length of the journal when the transaction began or `None` when
+  /// the journal was only enabled for the transaction.
+  journal_len: Option<usize>,
+  /// The workspace config when the transaction began, because the
+  /// journal only has what the config was rather than its content.
+  workspace: WorkspaceConfigContent,
+}
+
+impl<'a> LockfileTransaction<'a> {
+  pub(crate) fn new(lockfile: &'a mut Lockfile) -> Self {
+    let state = RollbackState {
+      has_content_changed: lockfile.has_content_changed,
+      journal_len: lockfile.journal.as_ref().map(|j| j.len()),
+      workspace: lockfile.content.workspace.clone(),
+    };
+    lockfile.enable_journal();
+    Self {
+      lockfile,
+      state: Some(state),
+    }
+  }
+
+  /// Keeps the edits made in the transaction.
+  pub fn commit(mut self) {
+    if let Some(state) = self.state.take()
+      && state.journal_len.is_none()
+    {
+      self.lockfile.journal = None;
+    }
+  }
+
+  /// Restores the content and `has_content_changed` flag from when the
+  /// transaction began, discarding any changes it added to the journal.
+  pub fn rollback(self) {
+    // done on drop
+  }
+
+  /// See [`Lockfile::set_workspace_config`].
+  pub fn set_workspace_config(&mut self, options: SetWorkspaceConfigOptions) {
+    self.lockfile.set_workspace_config(options)
+  }
+
+  /// See [`Lockfile::set_workspace_config_with_report`].
+  pub fn set_workspace_config_with_report(
+    &mut self,
+    options: SetWorkspaceConfigOptions,
+  ) -> WorkspaceConfigReport {
+    self.lockfile.set_workspace_config_with_report(options)
+  }
+
+  /// See [`Lockfile::insert_remote`].
+  pub fn insert_remote(&mut self, specifier: String, hash: String) {
+    self.lockfile.insert_remote(specifier, hash)
+  }
+
+  /// See [`Lockfile::remove_remote`].
+  pub fn remove_remote(&mut self, specifier: &str) -> RemovedRemotes {
+    self.lockfile.remove_remote(specifier)
+  }
+
+  /// See [`Lockfile::remove_remotes_with_prefix`].
+  pub fn remove_remotes_with_prefix(&mut self, prefix: &str) -> RemovedRemotes {
+    self.lockfile.remove_remotes_with_prefix(prefix)
+  }
+
+  /// See [`Lockfile::remove_remotes_where`].
+  pub fn remove_remotes_where(
+    &mut self,
+    predicate: impl FnMut(&str, &str) -> bool,
+  ) -> RemovedRemotes {
+    self.lockfile.remove_remotes_where(predicate)
+  }
+
+  /// See [`Lockfile::retain_remotes`].
+  pub fn retain_remotes(&mut self, used: &HashSet<String>) -> RemovedRemotes {
+    self.lockfile.retain_remotes(used)
+  }
+
+  /// See [`Lockfile::insert_npm_package`].
+  pub fn insert_npm_package(&mut self, package_info: NpmPackageLockfileInfo) {
+    self.lockfile.insert_npm_package(package_info)
+  }
+
+  /// See [`Lockfile::insert_package_specifier`].
+  pub fn insert_package_specifier(
+    &mut self,
+    package_req: JsrDepPackageReq,
+    serialized_package_id: SmallStackString,
+  ) {
+    self
+      .lockfile
+      .insert_package_specifier(package_req, serialized_package_id)
+  }
+
+  /// See [`Lockfile::insert_package`].
+  pub fn insert_package(&mut self, name: PackageNv, integrity: String) {
+    self.lockfile.insert_package(name, integrity)
+  }
+
+  /// See [`Lockfile::add_package_deps`].
+  pub fn add_package_deps(
+    &mut self,
+    nv: &PackageNv,
+    deps: impl Iterator<Item = JsrDepPackageReq>,
+  ) {
+    self.lockfile.add_package_deps(nv, deps)
+  }
+
+  /// See [`Lockfile::remove_package_specifier`].
+  pub fn remove_package_specifier(
+    &mut self,
+    package_req: &JsrDepPackageReq,
+  ) -> RemovedPackages {
+    self.lockfile.remove_package_specifier(package_req)
+  }
+
+  /// See [`Lockfile::remove_jsr_package`].
+  pub fn remove_jsr_package(&mut self, nv: &PackageNv) -> RemovedPackages {
+    self.lockfile.remove_jsr_package(nv)
+  }
+
+  /// See [`Lockfile::remove_npm_package`].
+  pub fn remove_npm_package(
+    &mut self,
+    serialized_package_id: &str,
+  ) -> RemovedPackages {
+    self.lockfile.remove_npm_package(serialized_package_id)
+  }
+
+  /// See [`Lockfile::rewrite_registry_urls`].
+  pub fn rewrite_registry_urls(
+    &mut self,
+    mirror: &RegistryMirror,
+  ) -> Result<usize, RegistryUrlCollisionError> {
+    self.lockfile.rewrite_registry_urls(mirror)
+  }
+
+  /// See [`Lockfile::repair`].
+  pub fn repair(&mut self) -> Vec<RepairEdit> {
+    self.lockfile.repair()
+  }
+
+  /// See [`Lockfile::collapse_redirects`].
+  pub fn collapse_redirects(&mut self) -> usize {
+    self.lockfile.collapse_redirects()
+  }
+
+  /// See [`Lockfile::insert_redirect`].
+  pub fn insert_redirect(&mut self, from: String, to: String) {
+    self.lockfile.insert_redirect(from, to)
+  }
+}
+
+impl Deref for LockfileTransaction<'_> {
+  type Target = Lockfile;
+
+  fn deref(&self) -> &Lockfile {
+    self.lockfile
+  }
+}
+
+impl Drop for LockfileTransaction<'_> {
+  fn drop(&mut self) {
+    let Some(state) = self.state.take() else {
+      return;
+    };
+    let lockfile = &mut *self.lockfile;
+    let mutations = match (&mut lockfile.journal, state.journal_len) {
+      (Some(journal), Some(len)) => journal.split_off(len),
+      (journal, None) => journal.take().unwrap_or_default(),
+      (None, Some(_)) => Vec::new(),
+    };
+    let content = &mut lockfile.content;
+    for mutation in mutations.into_iter().rev() {
+      if let Some(index) = &mut lockfile.dependency_index {
+        index.apply(&mutation, true);
+      }
+      match mutation {
+        LockfileMutation::Specifier { req, old, .. } => match old {
+          Some(old) => {
+            content.packages.specifiers.insert(req, old);
+          }
+          None => {
+            content.packages.specifiers.remove(&req);
+          }
+        },
+        LockfileMutation::JsrPackage { nv, old, .. } => match old {
+          Some(old) => {
+            content.packages.jsr.insert(nv, old);
+          }
+          None => {
+            content.packages.jsr.remove(&nv);
+          }
+        },
+        LockfileMutation::NpmPackage { id, old, .. } => match old {
+          Some(old) => {
+            content.packages.npm.insert(id, old);
+          }
+          None => {
+            content.packages.npm.remove(&id);
+          }
+        },
+        LockfileMutation::Redirect { from, old, .. } => match old {
+          Some(old) => {
+            content.redirects.insert(from, old);
+          }
+          None => {
+            content.redirects.remove(&from);
+          }
+        },
+        LockfileMutation::Remote { specifier, old, .. } => match old {
+          Some(old) => {
+            content.remote.insert(specifier, old);
+          }
+          None => {
+            content.remote.remove(&specifier);
+          }
+        },
+        // restored from the state below
+        LockfileMutation::Workspace { .. } => {}
+      }
+    }
+    content.workspace = state.workspace;
+    lockfile.has_content_changed = state.has_content_changed;
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use std::collections::HashMap;
+  use std::path::PathBuf;
+
+  use pretty_assertions::assert_eq;
+
+  use super::*;
+  use crate::LockfileContent;
+  use crate::UrlPrefixMapping;
+  use crate::WorkspaceConfig;
+  use crate::WorkspaceMemberConfig;
+
+  fn insert_remotes(
+    lockfile: &mut Lockfile,
+    fail: bool,
+  ) -> Result<(), &'static str> {
+    let mut transaction = lockfile.begin_transaction();
+    transaction.insert_remote("https://a.com/a".to_string(), "a".to_string());
+    if fail {
+      return Err("resolution failed");
+    }
+    transaction.insert_remote("https://a.com/b".to_string(), "b".to_string());
+    transaction.commit();
+    Ok(())
+  }
+
+  #[test]
+  fn commits_and_rolls_back() {
+    let mut lockfile = Lockfile::new_empty(PathBuf::from("/deno.lock"), false);
+
+    assert!(insert_remotes(&mut lockfile, true).is_err());
+    assert!(lockfile.content.is_empty());
+    assert!(!lockfile.has_content_changed);
+
+    let mut transaction = lockfile.begin_transaction();
+    transaction.insert_remote("https://a.com/c".to_string(), "c".to_string());
+    transaction.rollback();
+    assert!(lockfile.content.is_empty());
+
+    insert_remotes(&mut lockfile, false).unwrap();
+    assert_eq!(lockfile.remote().len(), 2);
+    assert!(lockfile.has_content_changed);
+    // the journal was only enabled for the transaction
+    assert!(lockfile.journal.is_none());
+  }
+
+  #[test]
+  fn rolls_back_workspace_and_package_changes() {
+    let mut lockfile = Lockfile::new_empty(PathBuf::from("/deno.lock"), false);
+    let req = JsrDepPackageReq::from_str("jsr:@scope/a@1").unwrap();
+    let nv = PackageNv::from_str("@scope/a@1.0.0").unwrap();
+    let config = |deps: &[&JsrDepPackageReq]| WorkspaceConfig {
+      root: WorkspaceMemberConfig {
+        dependencies: deps.iter().map(|req| (*req).clone()).collect(),
+        package_json_deps: HashSet::new(),
+      },
+      members: HashMap::new(),
+      links: HashMap::new(),
+      overrides: HashMap::new(),
+    };
+    lockfile.set_workspace_config(SetWorkspaceConfigOptions {
+      config: config(&[&req]),
+      no_config: false,
+      no_npm: false,
+    });
+    lockfile.insert_package_specifier(req.clone(), "1.0.0".into());
+    lockfile.insert_package(nv.clone(), "sha256-a".to_string());
+    lockfile.enable_journal();
+    lockfile.take_journal();
+    let original = lockfile.as_json_string();
+
+    let mut transaction = lockfile.begin_transaction();
+    transaction.insert_package(nv.clone(), "sha256-b".to_string());
+    transaction.set_workspace_config(SetWorkspaceConfigOptions {
+      config: config(&[]),
+      no_config: false,
+      no_npm: false,
+    });
+    assert!(transaction.content.packages.specifiers.is_empty());
+    drop(transaction);
+
+    assert_eq!(lockfile.as_json_string(), original);
+    // the journal was already enabled, so it's kept without the changes
+    assert_eq!(lockfile.journal(), &[]);
+  }
+
+  #[test]
+  fn rolls_back_removals_and_repairs() {
+    let mut lockfile = Lockfile::new_empty(PathBuf::from("/deno.lock"), false);
+    lockfile.content = LockfileContent::from_json(serde_json::json!({
+      "version": "5",
+      "specifiers": {
+        "jsr:@scope/a@1": "1.0.0",
+        "jsr:@scope/b@1": "1.0.0",
+        "npm:c@1": "1.0.0",
+        "npm:d@1": "1.0.0",
+      },
+      "jsr": {
+        "@scope/a@1.0.0": {
+          "integrity": "sha256",
+          "dependencies": ["jsr:@scope/b@1", "npm:c@1"],
+        },
+        "@scope/b@1.0.0": { "integrity": "sha256" },
+      },
+      "npm": {
+        "c@1.0.0": { "integrity": "sha512" },
+        "d@1.0.0": { "integrity": "sha512" },
+      },
+      "redirects": {
+        "https://deno.land/x/a": "https://deno.land/x/b",
+        "https://deno.land/x/b": "https://deno.land/x/c",
+        "https://a.com/loop_a": "https://a.com/loop_b",
+        "https://a.com/loop_b": "https://a.com/loop_a",
+      },
+      "remote": {
+        "https://deno.land/x/c": "checksum",
+        "https://esm.sh/e": "checksum",
+      },
+      "workspace": {
+        "dependencies": ["jsr:@scope/a@1", "npm:d@1"],
+      },
+    }))
+    .unwrap();
+    lockfile.enable_dependency_index();
+    let original = lockfile.as_json_string();
+
+    let mut transaction = lockfile.begin_transaction();
+    transaction.set_workspace_config(SetWorkspaceConfigOptions {
+      config: WorkspaceConfig {
+        root: WorkspaceMemberConfig {
+          dependencies: HashSet::from([JsrDepPackageReq::from_str(
+            "jsr:@scope/a@1",
+          )
+          .unwrap()]),
+          package_json_deps: HashSet::new(),
+        },
+        members: HashMap::new(),
+        links: HashMap::new(),
+        overrides: HashMap::new(),
+      },
+      no_config: false,
+      no_npm: false,
+    });
+    assert!(!transaction.remove_npm_package("c@1.0.0").is_empty());
+    assert!(!transaction.remove_remote("https://esm.sh/e").is_empty());
+    assert_eq!(transaction.collapse_redirects(), 1);
+    assert_eq!(transaction.repair().len(), 2);
+    transaction
+      .rewrite_registry_urls(&RegistryMirror::new(vec![UrlPrefixMapping {
+        from: "https://deno.land/".to_string(),
+        to: "https://mirror.corp/deno/".to_string(),
+      }]))
+      .unwrap();
+    assert!(transaction.content.packages.jsr.is_empty());
+    transaction.rollback();
+
+    assert_eq!(lockfile.as_json_string(), original);
+    assert!(!lockfile.has_content_changed);
+    // the index was restored along with the packages, which debug builds
+    // check when pruning
+    let removed =
+      lockfile.set_workspace_config_with_report(SetWorkspaceConfigOptions {
+        config: WorkspaceConfig {
+          root: WorkspaceMemberConfig::default(),
+          members: HashMap::new(),
+          links: HashMap::new(),
+          overrides: HashMap::new(),
+        },
+        no_config: false,
+        no_npm: false,
+      });
+    assert_eq!(removed.removed_packages.jsr.len(), 2);
+  }
+}
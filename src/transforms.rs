@@ -4,6 +4,8 @@ use deno_semver::{Version, package::PackageNv};
 use serde_json::Value;
 use thiserror::Error;
 
+use crate::json_path::JsonPath;
+
 pub type JsonMap = serde_json::Map<String, Value>;
 
 pub fn transform1_to_2(json: JsonMap) -> JsonMap {
@@ -41,7 +43,12 @@ pub fn transform2_to_3(mut json: JsonMap) -> JsonMap {
 #[derive(Debug, Error)]
 pub enum TransformError {
   #[error("Failed extracting npm name and version from dep '{id}'.")]
-  FailedExtractingV3NpmDepNv { id: String },
+  #[non_exhaustive]
+  FailedExtractingV3NpmDepNv {
+    id: String,
+    /// Path to the dependency in the version 3 lockfile.
+    path: JsonPath,
+  },
   #[error("Failed getting npm package info: {source}")]
   FailedGettingNpmPackageInfo {
     #[source]
@@ -49,6 +56,15 @@ pub enum TransformError {
   },
 }
 
+impl TransformError {
+  pub fn json_path(&self) -> Option<&JsonPath> {
+    match self {
+      TransformError::FailedExtractingV3NpmDepNv { path, .. } => Some(path),
+      TransformError::FailedGettingNpmPackageInfo { .. } => None,
+    }
+  }
+}
+
 // note: although these functions are found elsewhere in this repo,
 // it is purposefully duplicated here to ensure it never changes
 // for these transforms
@@ -93,7 +109,7 @@ pub fn transform3_to_4(mut json: JsonMap) -> Result<JsonMap, TransformError> {
           .and_modify(|v| *v = true)
           .or_default();
       }
-      for value in npm.values_mut() {
+      for (pkg_id, value) in npm.iter_mut() {
         let Value::Object(value) = value else {
           continue;
         };
@@ -108,6 +124,11 @@ pub fn transform3_to_4(mut json: JsonMap) -> Result<JsonMap, TransformError> {
           let Some((name, version)) = extract_nv_from_id(&id) else {
             // corrupt
             return Err(TransformError::FailedExtractingV3NpmDepNv {
+              path: JsonPath::from_key("packages")
+                .join_key("npm")
+                .join_key(pkg_id.as_str())
+                .join_key("dependencies")
+                .join_key(key),
               id: id.to_string(),
             });
           };
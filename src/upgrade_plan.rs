@@ -0,0 +1,306 @@
+// Copyright 2018-2024 the Deno authors. MIT license.
+
+use std::collections::HashSet;
+
+use deno_semver::jsr::JsrDepPackageReq;
+use deno_semver::package::PackageKind;
+
+use crate::DependencyPathRoot;
+use crate::LockfileContent;
+use crate::LockfilePackageId;
+use crate::dependency_paths::DependencyPathFinder;
+
+/// The impact of changing the version requirements of workspace
+/// dependencies, computed without resolving anything.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UpgradePlan {
+  pub upgrades: Vec<PlannedUpgrade>,
+  /// Packages that nothing would depend on after the upgrades, sorted by
+  /// their ids.
+  pub orphaned_packages: Vec<LockfilePackageId>,
+}
+
+impl UpgradePlan {
+  /// Requirements that no locked package satisfies, which would need to
+  /// be resolved.
+  pub fn unresolved(&self) -> impl Iterator<Item = &JsrDepPackageReq> {
+    self
+      .upgrades
+      .iter()
+      .filter(|upgrade| upgrade.locked.is_none())
+      .map(|upgrade| &upgrade.req)
+  }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PlannedUpgrade {
+  /// The new version requirement.
+  pub req: JsrDepPackageReq,
+  /// Workspace dependencies on the same package that the requirement
+  /// replaces.
+  pub replaced: Vec<JsrDepPackageReq>,
+  /// The highest locked version of the package that satisfies the
+  /// requirement or `None` when it would need to be resolved.
+  pub locked: Option<LockfilePackageId>,
+  /// The workspace root and members that depend on the replaced
+  /// requirements.
+  pub affected: Vec<DependencyPathRoot>,
+}
+
+fn locked_package(
+  content: &LockfileContent,
+  req: &JsrDepPackageReq,
+) -> Option<LockfilePackageId> {
+  // a tag, such as "latest", can't be checked without the registry
+  if req.req.version_req.tag().is_some() {
+    return None;
+  }
+  let ids: Box<dyn Iterator<Item = LockfilePackageId>> = match req.kind {
+    PackageKind::Jsr => Box::new(
+      content
+        .packages
+        .jsr
+        .keys()
+        .cloned()
+        .map(LockfilePackageId::Jsr),
+    ),
+    PackageKind::Npm => Box::new(
+      content
+        .packages
+        .npm
+        .keys()
+        .cloned()
+        .map(LockfilePackageId::Npm),
+    ),
+  };
+  ids
+    .filter_map(|id| {
+      let (name, version) = id.name_and_version()?;
+      (name == req.req.name.as_str() && req.req.version_req.matches(&version))
+        .then_some((version, id))
+    })
+    // prefer the highest version, then the id without peer dependencies
+    .max_by(|(a_version, a_id), (b_version, b_id)| {
+      a_version.cmp(b_version).then_with(|| b_id.cmp(a_id))
+    })
+    .map(|(_, id)| id)
+}
+
+pub(crate) fn plan(
+  content: &LockfileContent,
+  reqs: &[JsrDepPackageReq],
+) -> UpgradePlan {
+  let workspace = &content.workspace;
+  let mut members = workspace.members.iter().collect::<Vec<_>>();
+  members.sort_by_key(|(name, _)| *name);
+  let members =
+    std::iter::once((DependencyPathRoot::WorkspaceRoot, &workspace.root))
+      .chain(members.into_iter().map(|(name, member)| {
+        (DependencyPathRoot::WorkspaceMember(name.clone()), member)
+      }))
+      .collect::<Vec<_>>();
+
+  let mut replaced_reqs = HashSet::new();
+  let mut upgrades = Vec::with_capacity(reqs.len());
+  for req in reqs {
+    let is_replaced = |dep: &JsrDepPackageReq| {
+      dep.kind == req.kind && dep.req.name == req.req.name && dep != req
+    };
+    let mut replaced = Vec::new();
+    let mut affected = Vec::new();
+    for (root, member) in &members {
+      let member_replaced = member
+        .dep_reqs()
+        .filter(|dep| is_replaced(dep))
+        .collect::<Vec<_>>();
+      if !member_replaced.is_empty() {
+        affected.push(root.clone());
+      }
+      for dep in member_replaced {
+        replaced_reqs.insert((root.clone(), dep.clone()));
+        if !replaced.contains(dep) {
+          replaced.push(dep.clone());
+        }
+      }
+    }
+    replaced.sort_by_cached_key(|dep| dep.to_string_normalized());
+    upgrades.push(PlannedUpgrade {
+      req: req.clone(),
+      replaced,
+      locked: locked_package(content, req),
+      affected,
+    });
+  }
+
+  let finder = DependencyPathFinder::new(content);
+  let reachable_before = finder.reachable(|_, _| true, std::iter::empty());
+  let reachable_after = finder.reachable(
+    |root, req| !replaced_reqs.contains(&(root.clone(), req.clone())),
+    upgrades.iter().filter_map(|upgrade| upgrade.locked.clone()),
+  );
+  let mut orphaned_packages = reachable_before
+    .into_iter()
+    .filter(|id| !reachable_after.contains(id))
+    .collect::<Vec<_>>();
+  orphaned_packages.sort();
+  UpgradePlan {
+    upgrades,
+    orphaned_packages,
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use pretty_assertions::assert_eq;
+
+  use deno_semver::package::PackageNv;
+
+  use super::*;
+
+  #[test]
+  fn plans_upgrades() {
+    let content = LockfileContent::from_json(serde_json::json!({
+      "version": "5",
+      "specifiers": {
+        "jsr:@std/path@1": "1.0.0",
+        "npm:react@18": "18.2.0",
+        "npm:react@19": "19.0.0",
+      },
+      "jsr": {
+        "@std/path@1.0.0": { "integrity": "sha256" },
+      },
+      "npm": {
+        "loose-envify@1.4.0": { "integrity": "sha512" },
+        "react@18.2.0": {
+          "integrity": "sha512",
+          "dependencies": ["loose-envify"],
+        },
+        "react@19.0.0": { "integrity": "sha512" },
+      },
+      "workspace": {
+        "dependencies": ["jsr:@std/path@1", "npm:react@18"],
+        "members": {
+          "a": { "dependencies": ["npm:react@18"] },
+          "b": { "dependencies": ["npm:react@19"] },
+        },
+      },
+    }))
+    .unwrap();
+    let req = |text: &str| JsrDepPackageReq::from_str(text).unwrap();
+    let plan = plan(
+      &content,
+      &[
+        req("npm:react@19"),
+        req("jsr:@std/path@2"),
+        req("npm:loose-envify@latest"),
+      ],
+    );
+    assert_eq!(
+      plan.upgrades,
+      vec![
+        PlannedUpgrade {
+          req: req("npm:react@19"),
+          replaced: vec![req("npm:react@18")],
+          locked: Some(LockfilePackageId::Npm("react@19.0.0".into())),
+          affected: vec![
+            DependencyPathRoot::WorkspaceRoot,
+            DependencyPathRoot::WorkspaceMember("a".to_string()),
+          ],
+        },
+        PlannedUpgrade {
+          req: req("jsr:@std/path@2"),
+          replaced: vec![req("jsr:@std/path@1")],
+          locked: None,
+          affected: vec![DependencyPathRoot::WorkspaceRoot],
+        },
+        PlannedUpgrade {
+          req: req("npm:loose-envify@latest"),
+          replaced: Vec::new(),
+          locked: None,
+          affected: Vec::new(),
+        },
+      ]
+    );
+    assert_eq!(
+      plan
+        .orphaned_packages
+        .iter()
+        .map(|id| id.to_string())
+        .collect::<Vec<_>>(),
+      vec![
+        "jsr:@std/path@1.0.0",
+        "npm:loose-envify@1.4.0",
+        "npm:react@18.2.0",
+      ]
+    );
+    assert_eq!(
+      plan.unresolved().collect::<Vec<_>>(),
+      vec![&req("jsr:@std/path@2"), &req("npm:loose-envify@latest")]
+    );
+  }
+
+  #[test]
+  fn keeps_packages_depended_on_elsewhere() {
+    let content = LockfileContent::from_json(serde_json::json!({
+      "version": "5",
+      "specifiers": {
+        "jsr:@scope/a@1": "1.0.0",
+        "npm:b@1": "1.0.0",
+        "npm:b@2": "2.0.0",
+      },
+      "jsr": {
+        "@scope/a@1.0.0": {
+          "integrity": "sha256",
+          "dependencies": ["npm:b@1"],
+        },
+      },
+      "npm": {
+        "b@1.0.0": { "integrity": "sha512", "dependencies": ["c"] },
+        "b@2.0.0": { "integrity": "sha512" },
+        "b@2.1.0": { "integrity": "sha512" },
+        "b@2.1.0_c@1.0.0": { "integrity": "sha512", "dependencies": ["c"] },
+        "c@1.0.0": { "integrity": "sha512" },
+      },
+      "workspace": {
+        "dependencies": ["jsr:@scope/a@1", "npm:b@1"],
+        "members": {
+          "member": { "dependencies": ["npm:b@2"] },
+        },
+      },
+    }))
+    .unwrap();
+    let req = |text: &str| JsrDepPackageReq::from_str(text).unwrap();
+    let plan = plan(&content, &[req("npm:b@~2.1"), req("jsr:@scope/a@1")]);
+    assert_eq!(
+      plan.upgrades,
+      vec![
+        // the highest version is locked, preferring the one without
+        // peer dependencies
+        PlannedUpgrade {
+          req: req("npm:b@~2.1"),
+          replaced: vec![req("npm:b@1"), req("npm:b@2")],
+          locked: Some(LockfilePackageId::Npm("b@2.1.0".into())),
+          affected: vec![
+            DependencyPathRoot::WorkspaceRoot,
+            DependencyPathRoot::WorkspaceMember("member".to_string()),
+          ],
+        },
+        // an unchanged requirement replaces nothing
+        PlannedUpgrade {
+          req: req("jsr:@scope/a@1"),
+          replaced: Vec::new(),
+          locked: Some(LockfilePackageId::Jsr(
+            PackageNv::from_str("@scope/a@1.0.0").unwrap()
+          )),
+          affected: Vec::new(),
+        },
+      ]
+    );
+    // b@1.0.0 and its dependencies are still depended on by @scope/a
+    assert_eq!(
+      plan.orphaned_packages,
+      vec![LockfilePackageId::Npm("b@2.0.0".into())]
+    );
+    assert_eq!(plan.unresolved().count(), 0);
+  }
+}
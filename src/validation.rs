@@ -0,0 +1,791 @@
+// Copyright 2018-2024 the Deno authors. MIT license.
+
+use std::collections::BTreeSet;
+use std::collections::HashSet;
+use std::fmt;
+
+use deno_semver::SmallStackString;
+use deno_semver::StackString;
+use deno_semver::Version;
+use deno_semver::jsr::JsrDepPackageReq;
+use deno_semver::package::PackageKind;
+use deno_semver::package::PackageNv;
+
+use crate::JsonPath;
+use crate::LockfileContent;
+use crate::LockfileMutation;
+use crate::PackagesContent;
+use crate::overrides;
+use crate::redirects;
+
+/// An internal inconsistency found in a lockfile's content.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ValidationDiagnostic {
+  /// Path to the offending value in the lockfile.
+  pub path: JsonPath,
+  pub kind: ValidationDiagnosticKind,
+}
+
+impl fmt::Display for ValidationDiagnostic {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "{} at {}", self.kind, self.path)
+  }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ValidationDiagnosticKind {
+  /// A specifier resolves to a package that's not in the lockfile.
+  MissingSpecifierPackage {
+    specifier: JsrDepPackageReq,
+    version: SmallStackString,
+  },
+  /// A specifier is locked to a version that doesn't satisfy its version
+  /// requirement, such as after a hand edit or a bad merge.
+  UnsatisfiedSpecifier {
+    specifier: JsrDepPackageReq,
+    version: SmallStackString,
+  },
+  /// A specifier is locked to a version that can't be parsed.
+  InvalidSpecifierVersion {
+    specifier: JsrDepPackageReq,
+    version: SmallStackString,
+  },
+  /// An npm package depends on a package id that's not in the lockfile.
+  MissingNpmDependency {
+    package: StackString,
+    dependency: StackString,
+  },
+  /// A jsr package has a dependency without a corresponding specifier.
+  UnresolvedJsrDependency {
+    package: PackageNv,
+    dependency: JsrDepPackageReq,
+  },
+  /// A redirect points at a url that's not in the remote section and
+  /// isn't redirected itself.
+  DanglingRedirect { from: String, to: String },
+  /// An npm package has an optional peer that's also a dependency.
+  OptionalPeerIsDependency {
+    package: StackString,
+    name: StackString,
+  },
+  /// An integrity or checksum is not in an expected format.
+  MalformedIntegrity { integrity: String },
+  /// An override rule isn't a package requirement, such as `foo` instead
+  /// of `npm:foo`, so it can't be matched against the packages.
+  InvalidOverride { name: String },
+}
+
+impl fmt::Display for ValidationDiagnosticKind {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      ValidationDiagnosticKind::MissingSpecifierPackage {
+        specifier,
+        version,
+      } => write!(
+        f,
+        "Specifier '{}' resolves to version '{}', which is not in the lockfile",
+        specifier.to_string_normalized(),
+        version
+      ),
+      ValidationDiagnosticKind::UnsatisfiedSpecifier { specifier, version } => {
+        write!(
+          f,
+          "Specifier '{}' is locked to version '{}', which does not satisfy it",
+          specifier.to_string_normalized(),
+          version
+        )
+      }
+      ValidationDiagnosticKind::InvalidSpecifierVersion {
+        specifier,
+        version,
+      } => write!(
+        f,
+        "Specifier '{}' is locked to version '{}', which is not a valid version",
+        specifier.to_string_normalized(),
+        version
+      ),
+      ValidationDiagnosticKind::MissingNpmDependency {
+        package,
+        dependency,
+      } => write!(
+        f,
+        "npm package '{}' depends on '{}', which is not in the lockfile",
+        package, dependency
+      ),
+      ValidationDiagnosticKind::UnresolvedJsrDependency {
+        package,
+        dependency,
+      } => write!(
+        f,
+        "jsr package '{}' depends on '{}', which has no specifier",
+        package,
+        dependency.to_string_normalized()
+      ),
+      ValidationDiagnosticKind::DanglingRedirect { from, to } => write!(
+        f,
+        "Redirect from '{}' points to '{}', which is not in the lockfile",
+        from, to
+      ),
+      ValidationDiagnosticKind::OptionalPeerIsDependency { package, name } => {
+        write!(
+          f,
+          "npm package '{}' has '{}' as both an optional peer and a dependency",
+          package, name
+        )
+      }
+      ValidationDiagnosticKind::MalformedIntegrity { integrity } => {
+        write!(f, "Malformed integrity '{}'", integrity)
+      }
+      ValidationDiagnosticKind::InvalidOverride { name } => {
+        write!(f, "Override '{}' is not a package requirement", name)
+      }
+    }
+  }
+}
+
+fn specifier_path(specifier: &JsrDepPackageReq) -> JsonPath {
+  JsonPath::from_key("specifiers")
+    .join_key(specifier.to_string_normalized().as_str())
+}
+
+pub(crate) fn validate(content: &LockfileContent) -> Vec<ValidationDiagnostic> {
+  let packages = &content.packages;
+  let mut diagnostics = Vec::new();
+
+  let mut specifiers = packages.specifiers.iter().collect::<Vec<_>>();
+  specifiers.sort_by_cached_key(|(req, _)| req.to_string_normalized());
+  for (req, version) in specifiers {
+    if !specifier_target_exists(packages, req, version) {
+      diagnostics.push(ValidationDiagnostic {
+        path: specifier_path(req),
+        kind: ValidationDiagnosticKind::MissingSpecifierPackage {
+          specifier: req.clone(),
+          version: version.clone(),
+        },
+      });
+    }
+    let kind = match check_specifier_version(req, version) {
+      LockedVersion::Satisfies => None,
+      LockedVersion::Unsatisfied => {
+        Some(ValidationDiagnosticKind::UnsatisfiedSpecifier {
+          specifier: req.clone(),
+          version: version.clone(),
+        })
+      }
+      LockedVersion::Invalid => {
+        Some(ValidationDiagnosticKind::InvalidSpecifierVersion {
+          specifier: req.clone(),
+          version: version.clone(),
+        })
+      }
+    };
+    if let Some(kind) = kind {
+      diagnostics.push(ValidationDiagnostic {
+        path: specifier_path(req),
+        kind,
+      });
+    }
+  }
+
+  for (nv, package) in &packages.jsr {
+    let path = JsonPath::from_key("jsr").join_key(nv.to_string());
+    if !is_valid_hex_sha256(&package.integrity) {
+      diagnostics.push(ValidationDiagnostic {
+        path: path.join_key("integrity"),
+        kind: ValidationDiagnosticKind::MalformedIntegrity {
+          integrity: package.integrity.clone(),
+        },
+      });
+    }
+    let mut deps = package.dependencies.iter().collect::<Vec<_>>();
+    deps.sort_by_cached_key(|dep| dep.to_string_normalized());
+    for dep in deps {
+      if !packages.specifiers.contains_key(dep) {
+        diagnostics.push(ValidationDiagnostic {
+          path: path.join_key("dependencies"),
+          kind: ValidationDiagnosticKind::UnresolvedJsrDependency {
+            package: nv.clone(),
+            dependency: dep.clone(),
+          },
+        });
+      }
+    }
+  }
+
+  for (id, package) in &packages.npm {
+    let path = JsonPath::from_key("npm").join_key(id.as_str());
+    if let Some(integrity) = &package.integrity
+      && !is_valid_npm_integrity(integrity)
+    {
+      diagnostics.push(ValidationDiagnostic {
+        path: path.join_key("integrity"),
+        kind: ValidationDiagnosticKind::MalformedIntegrity {
+          integrity: integrity.clone(),
+        },
+      });
+    }
+    for (key, deps) in [
+      ("dependencies", &package.dependencies),
+      ("optionalDependencies", &package.optional_dependencies),
+      ("optionalPeers", &package.optional_peers),
+    ] {
+      for (i, dep_id) in deps.values().enumerate() {
+        if !packages.npm.contains_key(dep_id) {
+          diagnostics.push(ValidationDiagnostic {
+            path: path.join_key(key).join_index(i),
+            kind: ValidationDiagnosticKind::MissingNpmDependency {
+              package: id.clone(),
+              dependency: dep_id.clone(),
+            },
+          });
+        }
+      }
+    }
+    for name in package.optional_peers.keys() {
+      if package.dependencies.contains_key(name) {
+        diagnostics.push(ValidationDiagnostic {
+          path: path.join_key("optionalPeers"),
+          kind: ValidationDiagnosticKind::OptionalPeerIsDependency {
+            package: id.clone(),
+            name: name.clone(),
+          },
+        });
+      }
+    }
+  }
+
+  for (from, to) in &content.redirects {
+    if !content.remote.contains_key(to) && !content.redirects.contains_key(to) {
+      diagnostics.push(ValidationDiagnostic {
+        path: JsonPath::from_key("redirects").join_key(from.as_str()),
+        kind: ValidationDiagnosticKind::DanglingRedirect {
+          from: from.clone(),
+          to: to.clone(),
+        },
+      });
+    }
+  }
+
+  for (url, checksum) in &content.remote {
+    if !is_valid_hex_sha256(checksum) {
+      diagnostics.push(ValidationDiagnostic {
+        path: JsonPath::from_key("remote").join_key(url.as_str()),
+        kind: ValidationDiagnosticKind::MalformedIntegrity {
+          integrity: checksum.clone(),
+        },
+      });
+    }
+  }
+
+  let mut invalid_overrides =
+    overrides::invalid_rules(&content.workspace.overrides).collect::<Vec<_>>();
+  invalid_overrides.sort();
+  for name in invalid_overrides {
+    diagnostics.push(ValidationDiagnostic {
+      path: JsonPath::from_key("workspace")
+        .join_key("overrides")
+        .join_key(name.as_str()),
+      kind: ValidationDiagnosticKind::InvalidOverride { name: name.clone() },
+    });
+  }
+
+  diagnostics
+}
+
+/// An edit made to the lockfile's content by [`repair`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RepairEdit {
+  /// Removed a specifier whose package was not in the lockfile.
+  RemovedSpecifier {
+    specifier: JsrDepPackageReq,
+    version: SmallStackString,
+  },
+  /// Removed a specifier locked to a version that didn't satisfy it, so
+  /// it gets resolved again.
+  RemovedUnsatisfiedSpecifier {
+    specifier: JsrDepPackageReq,
+    version: SmallStackString,
+  },
+  /// Removed a specifier locked to a version that couldn't be parsed, so
+  /// it gets resolved again.
+  RemovedInvalidSpecifier {
+    specifier: JsrDepPackageReq,
+    version: SmallStackString,
+  },
+  /// Removed a jsr package dependency that had no specifier.
+  RemovedJsrDependency {
+    package: PackageNv,
+    dependency: JsrDepPackageReq,
+  },
+  /// Removed an npm package dependency on a package id that was not
+  /// in the lockfile.
+  RemovedNpmDependency {
+    package: StackString,
+    name: StackString,
+    dependency: StackString,
+  },
+  /// Removed a redirect that was part of a loop.
+  RemovedRedirect { from: String, to: String },
+}
+
+/// Repairs the content, recording each entry it changes in the journal
+/// when there is one.
+pub(crate) fn repair(
+  content: &mut LockfileContent,
+  mut journal: Option<&mut Vec<LockfileMutation>>,
+) -> Vec<RepairEdit> {
+  fn record(
+    journal: &mut Option<&mut Vec<LockfileMutation>>,
+    mutation: impl FnOnce() -> LockfileMutation,
+  ) {
+    if let Some(journal) = journal {
+      journal.push(mutation());
+    }
+  }
+
+  let mut edits = Vec::new();
+  let packages = &mut content.packages;
+
+  let mut dangling_specifiers = packages
+    .specifiers
+    .iter()
+    .filter(|(req, version)| !specifier_target_exists(packages, req, version))
+    .map(|(req, _)| req.clone())
+    .collect::<Vec<_>>();
+  dangling_specifiers.sort_by_cached_key(|req| req.to_string_normalized());
+  for req in dangling_specifiers {
+    let version = packages.specifiers.remove(&req).unwrap();
+    record(&mut journal, || LockfileMutation::Specifier {
+      req: req.clone(),
+      old: Some(version.clone()),
+      new: None,
+    });
+    edits.push(RepairEdit::RemovedSpecifier {
+      specifier: req,
+      version,
+    });
+  }
+
+  let mut unsatisfied_specifiers = packages
+    .specifiers
+    .iter()
+    .filter_map(
+      |(req, version)| match check_specifier_version(req, version) {
+        LockedVersion::Satisfies => None,
+        locked => Some((req.clone(), locked)),
+      },
+    )
+    .collect::<Vec<_>>();
+  unsatisfied_specifiers
+    .sort_by_cached_key(|(req, _)| req.to_string_normalized());
+  for (req, locked) in unsatisfied_specifiers {
+    let version = packages.specifiers.remove(&req).unwrap();
+    record(&mut journal, || LockfileMutation::Specifier {
+      req: req.clone(),
+      old: Some(version.clone()),
+      new: None,
+    });
+    edits.push(match locked {
+      LockedVersion::Invalid => RepairEdit::RemovedInvalidSpecifier {
+        specifier: req,
+        version,
+      },
+      _ => RepairEdit::RemovedUnsatisfiedSpecifier {
+        specifier: req,
+        version,
+      },
+    });
+  }
+
+  // done after removing specifiers as that may cause more of these
+  for (nv, package) in &mut packages.jsr {
+    let mut unresolved = package
+      .dependencies
+      .iter()
+      .filter(|dep| !packages.specifiers.contains_key(dep))
+      .cloned()
+      .collect::<Vec<_>>();
+    if unresolved.is_empty() {
+      continue;
+    }
+    unresolved.sort_by_cached_key(|dep| dep.to_string_normalized());
+    let old = journal.is_some().then(|| package.clone());
+    for dep in unresolved {
+      package.dependencies.remove(&dep);
+      edits.push(RepairEdit::RemovedJsrDependency {
+        package: nv.clone(),
+        dependency: dep,
+      });
+    }
+    record(&mut journal, || LockfileMutation::JsrPackage {
+      nv: nv.clone(),
+      old,
+      new: Some(package.clone()),
+    });
+  }
+
+  let npm_ids = packages.npm.keys().cloned().collect::<HashSet<_>>();
+  for (id, package) in &mut packages.npm {
+    let deps = [
+      &package.dependencies,
+      &package.optional_dependencies,
+      &package.optional_peers,
+    ];
+    if deps
+      .iter()
+      .all(|deps| deps.values().all(|dep_id| npm_ids.contains(dep_id)))
+    {
+      continue;
+    }
+    let old = journal.is_some().then(|| package.clone());
+    for deps in [
+      &mut package.dependencies,
+      &mut package.optional_dependencies,
+      &mut package.optional_peers,
+    ] {
+      deps.retain(|name, dep_id| {
+        let keep = npm_ids.contains(dep_id);
+        if !keep {
+          edits.push(RepairEdit::RemovedNpmDependency {
+            package: id.clone(),
+            name: name.clone(),
+            dependency: dep_id.clone(),
+          });
+        }
+        keep
+      });
+    }
+    record(&mut journal, || LockfileMutation::NpmPackage {
+      id: id.clone(),
+      old,
+      new: Some(package.clone()),
+    });
+  }
+
+  let in_loop = redirects::cycles(&content.redirects)
+    .into_iter()
+    .flatten()
+    .map(|url| url.to_string())
+    .collect::<BTreeSet<_>>();
+  for from in in_loop {
+    let to = content.redirects.remove(&from).unwrap();
+    record(&mut journal, || LockfileMutation::Redirect {
+      from: from.clone(),
+      old: Some(to.clone()),
+      new: None,
+    });
+    edits.push(RepairEdit::RemovedRedirect { from, to });
+  }
+
+  edits
+}
+
+fn specifier_target_exists(
+  packages: &PackagesContent,
+  req: &JsrDepPackageReq,
+  version: &str,
+) -> bool {
+  match req.kind {
+    PackageKind::Jsr => {
+      PackageNv::from_str(&format!("{}@{}", req.req.name, version))
+        .map(|nv| packages.jsr.contains_key(&nv))
+        .unwrap_or(false)
+    }
+    PackageKind::Npm => packages
+      .npm
+      .contains_key(format!("{}@{}", req.req.name, version).as_str()),
+  }
+}
+
+enum LockedVersion {
+  Satisfies,
+  Unsatisfied,
+  Invalid,
+}
+
+/// Checks the locked version against the specifier's version requirement.
+/// Tags aren't checked, but the version still needs to be valid.
+fn check_specifier_version(
+  req: &JsrDepPackageReq,
+  version: &str,
+) -> LockedVersion {
+  let version = match req.kind {
+    PackageKind::Jsr => Version::parse_standard(version).ok(),
+    // npm versions may have a peer dependency suffix, such as
+    // 1.0.0_react@18.2.0
+    PackageKind::Npm => {
+      Version::parse_from_npm(version.split('_').next().unwrap()).ok()
+    }
+  };
+  match version {
+    None => LockedVersion::Invalid,
+    Some(_) if req.req.version_req.tag().is_some() => LockedVersion::Satisfies,
+    Some(version) if req.req.version_req.matches(&version) => {
+      LockedVersion::Satisfies
+    }
+    Some(_) => LockedVersion::Unsatisfied,
+  }
+}
+
+fn is_valid_hex_sha256(value: &str) -> bool {
+  value.len() == 64 && value.chars().all(|c| c.is_ascii_hexdigit())
+}
+
+fn is_valid_npm_integrity(value: &str) -> bool {
+  fn is_valid_sri(value: &str) -> bool {
+    let Some((algorithm, hash)) = value.split_once('-') else {
+      return false;
+    };
+    let byte_len: usize = match algorithm {
+      "sha1" => 20,
+      "sha256" => 32,
+      "sha384" => 48,
+      "sha512" => 64,
+      _ => return false,
+    };
+    hash.len() == byte_len.div_ceil(3) * 4
+      && hash
+        .trim_end_matches('=')
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || c == '+' || c == '/')
+  }
+
+  // old packages only have a hex sha1 shasum
+  let is_legacy_sha1 =
+    value.len() == 40 && value.chars().all(|c| c.is_ascii_hexdigit());
+  is_legacy_sha1 || value.split(' ').all(is_valid_sri)
+}
+
+#[cfg(test)]
+mod test {
+  use pretty_assertions::assert_eq;
+
+  use super::*;
+
+  const SHA256: &str =
+    "3118d7a42c03c242c5a49c2ad91c8396110e14acca1324e7aaefd31a999b71a4";
+  const SHA512: &str = "sha512-MqBkQh/OHTS2egovRtLk45wEyNXwF+cokD+1YPf9u5VfJiRdAiRwB2froX5Co9Rh20xs4siNPm8naNotSD6RBw==";
+
+  #[test]
+  fn valid_content_has_no_diagnostics() {
+    let content = LockfileContent::from_json(serde_json::json!({
+      "version": "5",
+      "specifiers": {
+        "jsr:@scope/a@1": "1.0.0",
+        "npm:b@1": "1.0.0",
+      },
+      "jsr": {
+        "@scope/a@1.0.0": {
+          "integrity": SHA256,
+          "dependencies": ["npm:b"],
+        },
+      },
+      "npm": {
+        "b@1.0.0": { "integrity": SHA512, "dependencies": ["c"] },
+        "c@1.0.0": { "integrity": "da39a3ee5e6b4b0d3255bfef95601890afd80709" },
+      },
+      "redirects": {
+        "https://deno.land/x/a/mod.ts": "https://deno.land/x/a@1/mod.ts",
+      },
+      "remote": {
+        "https://deno.land/x/a@1/mod.ts": SHA256,
+      },
+    }))
+    .unwrap();
+    assert_eq!(validate(&content), vec![]);
+  }
+
+  #[test]
+  fn reports_inconsistencies() {
+    let mut content = LockfileContent::from_json(serde_json::json!({
+      "version": "5",
+      "specifiers": {
+        "jsr:@scope/a@1": "1.0.0",
+        "jsr:@scope/missing@1": "1.0.0",
+        "npm:b@1": "1.0.0",
+      },
+      "jsr": {
+        "@scope/a@1.0.0": {
+          "integrity": "not-hex",
+        },
+      },
+      "npm": {
+        "b@1.0.0": {
+          "integrity": "sha512-short",
+          "dependencies": ["c@1.0.0", "d@1.0.0"],
+          "optionalPeers": ["c@1.0.0", "e@1.0.0"],
+        },
+        "c@1.0.0": { "integrity": SHA512 },
+      },
+      "redirects": {
+        "https://deno.land/x/a/mod.ts": "https://deno.land/x/a@1/mod.ts",
+      },
+      "workspace": {
+        "overrides": { "b": "1.0.0", "npm:c@1": "1.0.0" },
+      },
+    }))
+    .unwrap();
+    content
+      .packages
+      .jsr
+      .get_mut(&PackageNv::from_str("@scope/a@1.0.0").unwrap())
+      .unwrap()
+      .dependencies
+      .insert(JsrDepPackageReq::from_str("jsr:@scope/other@1").unwrap());
+    let diagnostics = validate(&content)
+      .into_iter()
+      .map(|d| d.to_string())
+      .collect::<Vec<_>>();
+    assert_eq!(
+      diagnostics,
+      vec![
+        "Specifier 'jsr:@scope/missing@1' resolves to version '1.0.0', which is not in the lockfile at specifiers[\"jsr:@scope/missing@1\"]",
+        "Malformed integrity 'not-hex' at jsr[\"@scope/a@1.0.0\"].integrity",
+        "jsr package '@scope/a@1.0.0' depends on 'jsr:@scope/other@1', which has no specifier at jsr[\"@scope/a@1.0.0\"].dependencies",
+        "Malformed integrity 'sha512-short' at npm[\"b@1.0.0\"].integrity",
+        "npm package 'b@1.0.0' depends on 'd@1.0.0', which is not in the lockfile at npm[\"b@1.0.0\"].dependencies[1]",
+        "npm package 'b@1.0.0' depends on 'e@1.0.0', which is not in the lockfile at npm[\"b@1.0.0\"].optionalPeers[1]",
+        "npm package 'b@1.0.0' has 'c' as both an optional peer and a dependency at npm[\"b@1.0.0\"].optionalPeers",
+        "Redirect from 'https://deno.land/x/a/mod.ts' points to 'https://deno.land/x/a@1/mod.ts', which is not in the lockfile at redirects[\"https://deno.land/x/a/mod.ts\"]",
+        "Override 'b' is not a package requirement at workspace.overrides.b",
+      ]
+    );
+  }
+
+  #[test]
+  fn repairs_dangling_references() {
+    let mut content = LockfileContent::from_json(serde_json::json!({
+      "version": "5",
+      "specifiers": {
+        "jsr:@scope/a@1": "1.0.0",
+        "jsr:@scope/missing@1": "1.0.0",
+        "npm:b@1": "1.0.0",
+      },
+      "jsr": {
+        "@scope/a@1.0.0": {
+          "integrity": SHA256,
+          "dependencies": ["jsr:@scope/missing@1", "npm:b@1"],
+        },
+      },
+      "npm": {
+        "b@1.0.0": {
+          "integrity": SHA512,
+          "dependencies": ["c@1.0.0", "d@1.0.0"],
+          "optionalPeers": ["e@1.0.0"],
+        },
+        "c@1.0.0": { "integrity": SHA512 },
+      },
+      "redirects": {
+        "https://a.com/1": "https://a.com/2",
+        "https://a.com/2": "https://a.com/1",
+        "https://a.com/3": "https://a.com/1",
+        "https://a.com/4": "https://a.com/4",
+      },
+    }))
+    .unwrap();
+    let edits = repair(&mut content, None);
+    assert_eq!(
+      edits,
+      vec![
+        RepairEdit::RemovedSpecifier {
+          specifier: JsrDepPackageReq::from_str("jsr:@scope/missing@1")
+            .unwrap(),
+          version: "1.0.0".into(),
+        },
+        RepairEdit::RemovedJsrDependency {
+          package: PackageNv::from_str("@scope/a@1.0.0").unwrap(),
+          dependency: JsrDepPackageReq::from_str("jsr:@scope/missing@1")
+            .unwrap(),
+        },
+        RepairEdit::RemovedNpmDependency {
+          package: "b@1.0.0".into(),
+          name: "d".into(),
+          dependency: "d@1.0.0".into(),
+        },
+        RepairEdit::RemovedNpmDependency {
+          package: "b@1.0.0".into(),
+          name: "e".into(),
+          dependency: "e@1.0.0".into(),
+        },
+        RepairEdit::RemovedRedirect {
+          from: "https://a.com/1".to_string(),
+          to: "https://a.com/2".to_string(),
+        },
+        RepairEdit::RemovedRedirect {
+          from: "https://a.com/2".to_string(),
+          to: "https://a.com/1".to_string(),
+        },
+        RepairEdit::RemovedRedirect {
+          from: "https://a.com/4".to_string(),
+          to: "https://a.com/4".to_string(),
+        },
+      ]
+    );
+    // the chain into the loop is now dangling, but not a loop itself
+    assert_eq!(
+      validate(&content)
+        .into_iter()
+        .map(|d| d.to_string())
+        .collect::<Vec<_>>(),
+      vec![
+        "Redirect from 'https://a.com/3' points to 'https://a.com/1', which is not in the lockfile at redirects[\"https://a.com/3\"]",
+      ]
+    );
+    assert_eq!(repair(&mut content, None), vec![]);
+  }
+
+  #[test]
+  fn finds_and_repairs_unsatisfied_specifiers() {
+    let mut content = LockfileContent::from_json(serde_json::json!({
+      "version": "5",
+      "specifiers": {
+        "jsr:@scope/a@1": "2.0.0",
+        "jsr:@scope/b@^1.2": "1.3.0",
+        "npm:c@2": "1.0.0",
+        "npm:d@18": "18.2.0_e@1.0.0",
+        "npm:e@latest": "1.0.0",
+        "npm:f@latest": "not-a-version",
+      },
+      "jsr": {
+        "@scope/a@2.0.0": { "integrity": SHA256 },
+        "@scope/b@1.3.0": { "integrity": SHA256 },
+      },
+      "npm": {
+        "c@1.0.0": { "integrity": SHA512 },
+        "d@18.2.0_e@1.0.0": { "integrity": SHA512, "dependencies": ["e"] },
+        "e@1.0.0": { "integrity": SHA512 },
+        "f@not-a-version": { "integrity": SHA512 },
+      },
+    }))
+    .unwrap();
+    assert_eq!(
+      validate(&content)
+        .into_iter()
+        .map(|d| d.to_string())
+        .collect::<Vec<_>>(),
+      vec![
+        "Specifier 'jsr:@scope/a@1' is locked to version '2.0.0', which does not satisfy it at specifiers[\"jsr:@scope/a@1\"]",
+        "Specifier 'npm:c@2' is locked to version '1.0.0', which does not satisfy it at specifiers[\"npm:c@2\"]",
+        "Specifier 'npm:f@latest' is locked to version 'not-a-version', which is not a valid version at specifiers[\"npm:f@latest\"]",
+      ]
+    );
+    assert_eq!(
+      repair(&mut content, None),
+      vec![
+        RepairEdit::RemovedUnsatisfiedSpecifier {
+          specifier: JsrDepPackageReq::from_str("jsr:@scope/a@1").unwrap(),
+          version: "2.0.0".into(),
+        },
+        RepairEdit::RemovedUnsatisfiedSpecifier {
+          specifier: JsrDepPackageReq::from_str("npm:c@2").unwrap(),
+          version: "1.0.0".into(),
+        },
+        RepairEdit::RemovedInvalidSpecifier {
+          specifier: JsrDepPackageReq::from_str("npm:f@latest").unwrap(),
+          version: "not-a-version".into(),
+        },
+      ]
+    );
+    assert_eq!(validate(&content), vec![]);
+  }
+}
@@ -0,0 +1,553 @@
+// Copyright 2018-2024 the Deno authors. MIT license.
+
+use std::collections::BTreeMap;
+use std::collections::HashMap;
+use std::collections::HashSet;
+
+use deno_semver::jsr::JsrDepPackageReq;
+
+use crate::RemovedPackages;
+use crate::SetWorkspaceConfigOptions;
+use crate::WorkspaceConfig;
+use crate::WorkspaceConfigContent;
+use crate::WorkspaceMemberConfig;
+use crate::WorkspaceMemberConfigContent;
+
+/// Changes made by [`crate::Lockfile::set_workspace_config_with_report`].
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct WorkspaceConfigReport {
+  pub root: WorkspaceDepsChange,
+  /// Changes of the members that were added, removed, or changed.
+  pub members: BTreeMap<String, WorkspaceDepsChange>,
+  /// Members that were removed and added under a different name with
+  /// some of the same dependencies, keyed by their old name. Their
+  /// changes are in `members` under the new name.
+  pub renamed_members: BTreeMap<String, String>,
+  /// Names of the links that were added, removed, or changed.
+  pub changed_links: Vec<String>,
+  /// Packages of the override rules that were added, removed, or changed.
+  pub changed_overrides: Vec<String>,
+  /// Entries removed because they were no longer used after the
+  /// workspace dependencies, links, or overrides changed.
+  pub removed_packages: RemovedPackages,
+}
+
+impl WorkspaceConfigReport {
+  pub fn is_empty(&self) -> bool {
+    self.root.is_empty()
+      && self.members.is_empty()
+      && self.renamed_members.is_empty()
+      && self.changed_links.is_empty()
+      && self.changed_overrides.is_empty()
+      && self.removed_packages.is_empty()
+  }
+}
+
+/// Dependencies of a workspace member from its deno.json and package.json
+/// that were added or removed.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct WorkspaceDepsChange {
+  pub added: Vec<JsrDepPackageReq>,
+  pub removed: Vec<JsrDepPackageReq>,
+}
+
+impl WorkspaceDepsChange {
+  pub fn is_empty(&self) -> bool {
+    self.added.is_empty() && self.removed.is_empty()
+  }
+
+  fn between(
+    old: Option<&WorkspaceMemberConfig>,
+    new: Option<&WorkspaceMemberConfig>,
+  ) -> Self {
+    fn all_deps(
+      member: Option<&WorkspaceMemberConfig>,
+    ) -> HashSet<&JsrDepPackageReq> {
+      member
+        .into_iter()
+        .flat_map(|m| m.dependencies.iter().chain(&m.package_json_deps))
+        .collect()
+    }
+    fn sorted<'a>(
+      reqs: impl Iterator<Item = &'a &'a JsrDepPackageReq>,
+    ) -> Vec<JsrDepPackageReq> {
+      let mut reqs = reqs.map(|req| (*req).clone()).collect::<Vec<_>>();
+      reqs.sort_by_cached_key(|req| req.to_string_normalized());
+      reqs
+    }
+    let old = all_deps(old);
+    let new = all_deps(new);
+    Self {
+      added: sorted(new.difference(&old)),
+      removed: sorted(old.difference(&new)),
+    }
+  }
+}
+
+/// How a workspace config differs from the one in the lockfile.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct WorkspaceConfigDiff {
+  /// Whether the root's dependencies differ.
+  pub root: bool,
+  /// Names of the members that were added, removed, or changed.
+  pub members: Vec<String>,
+  /// Names of the links that were added, removed, or changed.
+  pub links: Vec<String>,
+  /// Packages of the override rules that were added, removed, or changed.
+  pub overrides: Vec<String>,
+}
+
+impl WorkspaceConfigDiff {
+  /// Whether setting the workspace config would leave the lockfile as is.
+  pub fn is_empty(&self) -> bool {
+    !self.root
+      && self.members.is_empty()
+      && self.links.is_empty()
+      && self.overrides.is_empty()
+  }
+}
+
+/// Gets the sorted keys of the entries that were added, removed, or
+/// changed, such as the links.
+pub(crate) fn changed_names<T: PartialEq>(
+  old: &HashMap<String, T>,
+  new: &HashMap<String, T>,
+) -> Vec<String> {
+  let mut names = old
+    .keys()
+    .chain(new.keys())
+    .collect::<HashSet<_>>()
+    .into_iter()
+    .filter(|name| old.get(*name) != new.get(*name))
+    .cloned()
+    .collect::<Vec<_>>();
+  names.sort();
+  names
+}
+
+/// Gets how the config differs from the lockfile's workspace config when
+/// set with the `no_config` and `no_npm` options, which keep the current
+/// dependencies for anything left empty.
+pub(crate) fn diff_options(
+  current: &WorkspaceConfigContent,
+  options: &SetWorkspaceConfigOptions,
+) -> WorkspaceConfigDiff {
+  let keep_package_json_deps = options.no_npm || options.no_config;
+  let member_differs = |current: Option<&WorkspaceMemberConfigContent>,
+                        new: &WorkspaceMemberConfig| {
+    let (dependencies, package_json_deps) = match current {
+      Some(current) => {
+        (&current.dependencies, &current.package_json.dependencies)
+      }
+      None => (&HashSet::new(), &HashSet::new()),
+    };
+    let deps_differ = !(options.no_config && new.dependencies.is_empty())
+      && new.dependencies != *dependencies;
+    let package_json_deps_differ = !(keep_package_json_deps
+      && new.package_json_deps.is_empty())
+      && new.package_json_deps != *package_json_deps;
+    deps_differ || package_json_deps_differ
+  };
+
+  let config = &options.config;
+  let mut members = config
+    .members
+    .iter()
+    .filter(|(name, new)| member_differs(current.members.get(*name), new))
+    .map(|(name, _)| name.clone())
+    .collect::<Vec<_>>();
+  if !options.no_config {
+    members.extend(
+      current
+        .members
+        .keys()
+        .filter(|name| !config.members.contains_key(*name))
+        .cloned(),
+    );
+  }
+  members.sort();
+  WorkspaceConfigDiff {
+    root: member_differs(Some(&current.root), &config.root),
+    members,
+    links: changed_names(&current.links, &config.links),
+    overrides: changed_names(&current.overrides, &config.overrides),
+  }
+}
+
+/// Fills in the workspace dependency, link, and override changes from
+/// the old config to the new one.
+pub(crate) fn diff_config(
+  old: &WorkspaceConfig,
+  new: &WorkspaceConfig,
+  report: &mut WorkspaceConfigReport,
+) {
+  report.root = WorkspaceDepsChange::between(Some(&old.root), Some(&new.root));
+  let member_names = old
+    .members
+    .keys()
+    .chain(new.members.keys())
+    .collect::<HashSet<_>>();
+  let old_names = report
+    .renamed_members
+    .iter()
+    .map(|(old_name, new_name)| (new_name, old_name))
+    .collect::<HashMap<_, _>>();
+  for name in member_names {
+    if report.renamed_members.contains_key(name) {
+      continue;
+    }
+    let old_name = old_names.get(name).copied().unwrap_or(name);
+    let change = WorkspaceDepsChange::between(
+      old.members.get(old_name),
+      new.members.get(name),
+    );
+    if !change.is_empty() {
+      report.members.insert(name.clone(), change);
+    }
+  }
+  report.changed_links = changed_names(&old.links, &new.links);
+  report.changed_overrides = changed_names(&old.overrides, &new.overrides);
+}
+
+#[cfg(test)]
+mod test {
+  use std::path::PathBuf;
+
+  use pretty_assertions::assert_eq;
+
+  use super::*;
+  use crate::Lockfile;
+  use crate::LockfileContent;
+
+  #[test]
+  fn reports_and_previews_changes() {
+    let mut lockfile = Lockfile::new_empty(PathBuf::from("/deno.lock"), false);
+    lockfile.content = LockfileContent::from_json(serde_json::json!({
+      "version": "5",
+      "specifiers": {
+        "jsr:@scope/a@1": "1.0.0",
+        "npm:b@1": "1.0.0",
+        "npm:c@1": "1.0.0",
+      },
+      "jsr": {
+        "@scope/a@1.0.0": { "integrity": "sha256" },
+      },
+      "npm": {
+        "b@1.0.0": { "integrity": "sha512" },
+        "c@1.0.0": { "integrity": "sha512" },
+      },
+      "workspace": {
+        "dependencies": ["jsr:@scope/a@1", "npm:b@1"],
+        "members": {
+          "pkg": { "dependencies": ["npm:c@1"] },
+        },
+      },
+    }))
+    .unwrap();
+    let req = |text: &str| JsrDepPackageReq::from_str(text).unwrap();
+    let options = || SetWorkspaceConfigOptions {
+      config: WorkspaceConfig {
+        root: WorkspaceMemberConfig {
+          dependencies: HashSet::from([req("npm:b@1"), req("npm:d@1")]),
+          package_json_deps: HashSet::new(),
+        },
+        members: HashMap::new(),
+        links: HashMap::new(),
+        overrides: HashMap::new(),
+      },
+      no_config: false,
+      no_npm: false,
+    };
+
+    let report = lockfile.preview_workspace_config(options());
+    assert!(!lockfile.has_content_changed);
+    assert_eq!(lockfile.content.packages.specifiers.len(), 3);
+
+    assert_eq!(
+      report.root,
+      WorkspaceDepsChange {
+        added: vec![req("npm:d@1")],
+        removed: vec![req("jsr:@scope/a@1")],
+      }
+    );
+    assert_eq!(
+      report.members,
+      BTreeMap::from([(
+        "pkg".to_string(),
+        WorkspaceDepsChange {
+          added: Vec::new(),
+          removed: vec![req("npm:c@1")],
+        }
+      )])
+    );
+    assert!(report.changed_links.is_empty());
+    let mut removed_specifiers = report
+      .removed_packages
+      .specifiers
+      .keys()
+      .map(|req| req.to_string())
+      .collect::<Vec<_>>();
+    removed_specifiers.sort();
+    assert_eq!(removed_specifiers, vec!["jsr:@scope/a@1", "npm:c@1"]);
+    assert_eq!(report.removed_packages.jsr.len(), 1);
+    // npm packages are only disassociated from the specifiers
+    assert!(report.removed_packages.npm.is_empty());
+
+    assert_eq!(lockfile.set_workspace_config_with_report(options()), report);
+    assert!(lockfile.has_content_changed);
+  }
+
+  #[test]
+  fn reports_removed_entries_and_keeps_remotes() {
+    let mut lockfile = Lockfile::new_empty(PathBuf::from("/deno.lock"), false);
+    lockfile.content = LockfileContent::from_json(serde_json::json!({
+      "version": "5",
+      "specifiers": {
+        "jsr:@scope/a@1": "1.0.0",
+        "jsr:@scope/b@1": "1.0.0",
+      },
+      "jsr": {
+        "@scope/a@1.0.0": {
+          "integrity": "sha256",
+          "dependencies": ["jsr:@scope/b@1"],
+        },
+        "@scope/b@1.0.0": { "integrity": "sha256" },
+      },
+      "remote": {
+        "https://deno.land/x/mod.ts": "checksum",
+      },
+      "workspace": {
+        "dependencies": ["jsr:@scope/a@1"],
+      },
+    }))
+    .unwrap();
+    let options = || SetWorkspaceConfigOptions {
+      config: WorkspaceConfig {
+        root: WorkspaceMemberConfig::default(),
+        members: HashMap::new(),
+        links: HashMap::new(),
+        overrides: HashMap::new(),
+      },
+      no_config: false,
+      no_npm: false,
+    };
+    let expected_jsr = lockfile.content.packages.jsr.clone();
+
+    let preview = lockfile.preview_workspace_config(options());
+    let report = lockfile.set_workspace_config_with_report(options());
+    assert_eq!(report, preview);
+    assert_eq!(report.removed_packages.jsr, expected_jsr);
+    assert_eq!(
+      report.removed_packages.specifiers,
+      HashMap::from([
+        (
+          JsrDepPackageReq::from_str("jsr:@scope/a@1").unwrap(),
+          "1.0.0".into()
+        ),
+        (
+          JsrDepPackageReq::from_str("jsr:@scope/b@1").unwrap(),
+          "1.0.0".into()
+        ),
+      ])
+    );
+    assert!(lockfile.content.packages.jsr.is_empty());
+    // remotes aren't tracked by the workspace config
+    assert_eq!(lockfile.content.remote.len(), 1);
+  }
+
+  #[test]
+  fn detects_renamed_members() {
+    let mut lockfile = Lockfile::new_empty(PathBuf::from("/deno.lock"), false);
+    lockfile.content = LockfileContent::from_json(serde_json::json!({
+      "version": "5",
+      "specifiers": {
+        "jsr:@scope/a@1": "1.0.0",
+        "npm:b@1": "1.0.0",
+      },
+      "jsr": {
+        "@scope/a@1.0.0": { "integrity": "sha256" },
+      },
+      "npm": {
+        "b@1.0.0": { "integrity": "sha512" },
+      },
+      "workspace": {
+        "members": {
+          "packages/a": { "dependencies": ["jsr:@scope/a@1", "npm:b@1"] },
+          "packages/other": { "dependencies": ["npm:b@1"] },
+        },
+      },
+    }))
+    .unwrap();
+    let req = |text: &str| JsrDepPackageReq::from_str(text).unwrap();
+    let member = |deps: &[&str]| WorkspaceMemberConfig {
+      dependencies: deps.iter().map(|dep| req(dep)).collect(),
+      package_json_deps: HashSet::new(),
+    };
+    let report =
+      lockfile.set_workspace_config_with_report(SetWorkspaceConfigOptions {
+        config: WorkspaceConfig {
+          root: Default::default(),
+          members: HashMap::from([
+            (
+              "libs/a".to_string(),
+              member(&["jsr:@scope/a@1", "npm:b@1", "npm:c@1"]),
+            ),
+            ("packages/other".to_string(), member(&["npm:b@1"])),
+          ]),
+          links: HashMap::new(),
+          overrides: HashMap::new(),
+        },
+        no_config: false,
+        no_npm: false,
+      });
+    assert_eq!(
+      report.renamed_members,
+      BTreeMap::from([("packages/a".to_string(), "libs/a".to_string())])
+    );
+    assert_eq!(
+      report.members,
+      BTreeMap::from([(
+        "libs/a".to_string(),
+        WorkspaceDepsChange {
+          added: vec![req("npm:c@1")],
+          removed: Vec::new(),
+        }
+      )])
+    );
+    assert_eq!(lockfile.content.packages.jsr.len(), 1);
+    let mut member_names = lockfile
+      .content
+      .workspace
+      .members
+      .keys()
+      .collect::<Vec<_>>();
+    member_names.sort();
+    assert_eq!(member_names, vec!["libs/a", "packages/other"]);
+
+    // sharing a single dependency out of several isn't a rename
+    let report =
+      lockfile.set_workspace_config_with_report(SetWorkspaceConfigOptions {
+        config: WorkspaceConfig {
+          root: Default::default(),
+          members: HashMap::from([
+            (
+              "libs/a".to_string(),
+              member(&["jsr:@scope/a@1", "npm:b@1", "npm:c@1"]),
+            ),
+            ("apps/web".to_string(), member(&["npm:b@1", "npm:d@1"])),
+          ]),
+          links: HashMap::new(),
+          overrides: HashMap::new(),
+        },
+        no_config: false,
+        no_npm: false,
+      });
+    assert_eq!(report.renamed_members, BTreeMap::new());
+    assert_eq!(
+      report.members,
+      BTreeMap::from([
+        (
+          "apps/web".to_string(),
+          WorkspaceDepsChange {
+            added: vec![req("npm:b@1"), req("npm:d@1")],
+            removed: Vec::new(),
+          }
+        ),
+        (
+          "packages/other".to_string(),
+          WorkspaceDepsChange {
+            added: Vec::new(),
+            removed: vec![req("npm:b@1")],
+          }
+        ),
+      ])
+    );
+  }
+
+  #[test]
+  fn diffs_without_mutating() {
+    let mut lockfile = Lockfile::new_empty(PathBuf::from("/deno.lock"), false);
+    lockfile.content = LockfileContent::from_json(serde_json::json!({
+      "version": "5",
+      "workspace": {
+        "dependencies": ["jsr:@scope/a@1"],
+        "packageJson": { "dependencies": ["npm:b@1"] },
+        "members": {
+          "a": { "dependencies": ["npm:c@1"] },
+        },
+      },
+    }))
+    .unwrap();
+    let req = |text: &str| JsrDepPackageReq::from_str(text).unwrap();
+    let member =
+      |deps: &[&str], package_json_deps: &[&str]| WorkspaceMemberConfig {
+        dependencies: deps.iter().map(|dep| req(dep)).collect(),
+        package_json_deps: package_json_deps
+          .iter()
+          .map(|dep| req(dep))
+          .collect(),
+      };
+    let options = |root, members: &[(&str, WorkspaceMemberConfig)], flags| {
+      let (no_config, no_npm) = flags;
+      SetWorkspaceConfigOptions {
+        config: WorkspaceConfig {
+          root,
+          members: members
+            .iter()
+            .map(|(name, member)| (name.to_string(), member.clone()))
+            .collect(),
+          links: HashMap::new(),
+          overrides: HashMap::new(),
+        },
+        no_config,
+        no_npm,
+      }
+    };
+    let root = member(&["jsr:@scope/a@1"], &["npm:b@1"]);
+    let a = member(&["npm:c@1"], &[]);
+    let cases = [
+      (
+        options(root.clone(), &[("a", a.clone())], (false, false)),
+        false,
+      ),
+      (
+        options(member(&["jsr:@scope/a@1"], &[]), &[], (false, true)),
+        true,
+      ),
+      (options(member(&[], &[]), &[], (true, false)), false),
+      (options(root.clone(), &[], (false, false)), true),
+      (
+        options(
+          root.clone(),
+          &[("a", a), ("b", member(&["npm:d@1"], &[]))],
+          (false, false),
+        ),
+        true,
+      ),
+    ];
+    for (options, expected_members_changed) in cases {
+      let diff = lockfile.workspace_config_diff(&options);
+      assert!(!diff.root);
+      assert_eq!(!diff.members.is_empty(), expected_members_changed);
+      let mut clone = lockfile.clone();
+      clone.set_workspace_config(options);
+      assert_eq!(diff.is_empty(), !clone.has_content_changed);
+    }
+    assert!(!lockfile.has_content_changed);
+
+    let diff = lockfile.workspace_config_diff(&options(
+      member(&[], &["npm:b@1"]),
+      &[],
+      (false, false),
+    ));
+    assert_eq!(
+      diff,
+      WorkspaceConfigDiff {
+        root: true,
+        members: vec!["a".to_string()],
+        links: Vec::new(),
+        overrides: Vec::new(),
+      }
+    );
+  }
+}
@@ -119,6 +119,9 @@ async fn config_changes_test(test: &CollectedTest) {
     #[serde(skip_serializing_if = "BTreeMap::is_empty")]
     #[serde(default)]
     links: BTreeMap<String, LinkConfigContent>,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    #[serde(default)]
+    overrides: BTreeMap<String, String>,
   }
 
   impl WorkspaceConfigContent {
@@ -172,6 +175,7 @@ async fn config_changes_test(test: &CollectedTest) {
             )
           })
           .collect(),
+        overrides: self.overrides.into_iter().collect(),
       }
     }
   }